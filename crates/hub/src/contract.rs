@@ -1,14 +1,15 @@
 use cosmwasm_std::{
     entry_point, from_binary, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response,
-    StdError, StdResult,
+    StdResult,
 };
 use cw20::Cw20ReceiveMsg;
 
 use steak::hub::{CallbackMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg};
 
+use crate::error::ContractError;
 use crate::helpers::{parse_received_fund, unwrap_reply};
 use crate::state::State;
-use crate::{execute, queries};
+use crate::{execute, migrations, queries};
 
 #[entry_point]
 pub fn instantiate(
@@ -16,45 +17,163 @@ pub fn instantiate(
     env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     execute::instantiate(deps, env, msg)
 }
 
 #[entry_point]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
     let api = deps.api;
     match msg {
         ExecuteMsg::Receive(cw20_msg) => receive(deps, env, info, cw20_msg),
         ExecuteMsg::Bond {
             receiver,
-        } => execute::bond(
-            deps,
-            env,
-            receiver.map(|s| api.addr_validate(&s)).transpose()?.unwrap_or(info.sender),
-            parse_received_fund(&info.funds, "uluna")?,
-        ),
+        } => {
+            let state = State::default();
+            state.assert_not_paused(deps.storage, "bond", |flags| flags.bond)?;
+
+            let denom = state.denom.load(deps.storage)?;
+            execute::bond(
+                deps,
+                env,
+                receiver.map(|s| api.addr_validate(&s)).transpose()?.unwrap_or(info.sender),
+                parse_received_fund(&info.funds, &denom)?,
+                None,
+            )
+        },
+        ExecuteMsg::BondAndSend {
+            contract,
+            msg,
+        } => {
+            let state = State::default();
+            state.assert_not_paused(deps.storage, "bond", |flags| flags.bond)?;
+
+            let denom = state.denom.load(deps.storage)?;
+            execute::bond(
+                deps,
+                env,
+                api.addr_validate(&contract)?,
+                parse_received_fund(&info.funds, &denom)?,
+                Some(msg),
+            )
+        },
+        ExecuteMsg::CancelUnbond {
+            amount,
+            token_id,
+        } => execute::cancel_unbond(deps, env, info.sender, amount, token_id),
         ExecuteMsg::WithdrawUnbonded {
             receiver,
-        } => execute::withdraw_unbonded(
-            deps,
-            env,
-            info.sender.clone(),
-            receiver.map(|s| api.addr_validate(&s)).transpose()?.unwrap_or(info.sender),
-        ),
+            batch_ids,
+            limit,
+            hook,
+        } => {
+            let state = State::default();
+            state.assert_not_paused(deps.storage, "withdraw_unbonded", |flags| flags.withdraw_unbonded)?;
+
+            execute::withdraw_unbonded(
+                deps,
+                env,
+                info.sender.clone(),
+                receiver.map(|s| api.addr_validate(&s)).transpose()?.unwrap_or(info.sender),
+                batch_ids,
+                limit,
+                hook,
+            )
+        },
         ExecuteMsg::AddValidator {
             validator,
         } => execute::add_validator(deps, info.sender, validator),
         ExecuteMsg::RemoveValidator {
             validator,
         } => execute::remove_validator(deps, env, info.sender, validator),
+        ExecuteMsg::SetValidatorWeights {
+            weights,
+        } => execute::set_validator_weights(deps, info.sender, weights),
+        ExecuteMsg::SetSwapRoute {
+            denom,
+            router,
+            operations,
+            max_spread,
+            min_price,
+        } => execute::set_swap_route(deps, info.sender, denom, router, operations, max_spread, min_price),
+        ExecuteMsg::RemoveSwapRoute {
+            denom,
+        } => execute::remove_swap_route(deps, info.sender, denom),
+        ExecuteMsg::UpdatePauseFlags {
+            bond,
+            queue_unbond,
+            instant_unbond,
+            withdraw_unbonded,
+            harvest,
+        } => execute::update_pause_flags(
+            deps,
+            info.sender,
+            bond,
+            queue_unbond,
+            instant_unbond,
+            withdraw_unbonded,
+            harvest,
+        ),
+        ExecuteMsg::SetGuardian {
+            guardian,
+        } => execute::set_guardian(deps, info.sender, guardian),
         ExecuteMsg::TransferOwnership {
             new_owner,
         } => execute::transfer_ownership(deps, info.sender, new_owner),
         ExecuteMsg::AcceptOwnership {} => execute::accept_ownership(deps, info.sender),
-        ExecuteMsg::Harvest {} => execute::harvest(deps, env),
-        ExecuteMsg::Rebalance {} => execute::rebalance(deps, env),
-        ExecuteMsg::Reconcile {} => execute::reconcile(deps, env),
-        ExecuteMsg::SubmitBatch {} => execute::submit_batch(deps, env),
+        ExecuteMsg::UpdateConfig {
+            epoch_period,
+            unbond_period,
+            netting,
+            min_bond,
+            min_unbond,
+        } => execute::update_config(
+            deps,
+            info.sender,
+            epoch_period,
+            unbond_period,
+            netting,
+            min_bond,
+            min_unbond,
+        ),
+        ExecuteMsg::UpdateFee {
+            new_fee_rate,
+            new_fee_account,
+        } => execute::update_fee(deps, info.sender, new_fee_rate, new_fee_account),
+        ExecuteMsg::DepositInsurance {} => {
+            let denom = State::default().denom.load(deps.storage)?;
+            execute::deposit_insurance(deps, env, info.sender, parse_received_fund(&info.funds, &denom)?)
+        },
+        ExecuteMsg::UpdateInsuranceRate {
+            new_insurance_rate,
+        } => execute::update_insurance_rate(deps, info.sender, new_insurance_rate),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate,
+            new_fee_rate,
+        } => execute::update_buffer(deps, info.sender, new_target_rate, new_fee_rate),
+        ExecuteMsg::UpdateKeeperReward {
+            new_rate,
+            new_amount,
+            new_cooldown,
+        } => execute::update_keeper_reward(deps, info.sender, new_rate, new_amount, new_cooldown),
+        ExecuteMsg::UpdateBondCaps {
+            max_total_bonded,
+            max_bonded_per_address,
+        } => execute::update_bond_caps(deps, info.sender, max_total_bonded, max_bonded_per_address),
+        ExecuteMsg::Harvest {} => {
+            let state = State::default();
+            state.assert_not_paused(deps.storage, "harvest", |flags| flags.harvest)?;
+
+            execute::harvest(deps, env, info.sender)
+        },
+        ExecuteMsg::Rebalance {} => execute::rebalance(deps, env, info.sender),
+        ExecuteMsg::Reconcile {} => execute::reconcile(deps, env, info.sender),
+        ExecuteMsg::SubmitBatch {} => execute::submit_batch(deps, env, info.sender),
         ExecuteMsg::Callback(callback_msg) => callback(deps, env, info, callback_msg),
     }
 }
@@ -64,19 +183,20 @@ fn receive(
     env: Env,
     info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let api = deps.api;
     match from_binary(&cw20_msg.msg)? {
         ReceiveMsg::QueueUnbond {
             receiver,
         } => {
             let state = State::default();
+            state.assert_not_paused(deps.storage, "queue_unbond", |flags| flags.queue_unbond)?;
 
             let steak_token = state.steak_token.load(deps.storage)?;
             if info.sender != steak_token {
-                return Err(StdError::generic_err(
-                    format!("expecting Steak token, received {}", info.sender),
-                ));
+                return Err(ContractError::InvalidToken {
+                    sender: info.sender.into(),
+                });
             }
 
             execute::queue_unbond(
@@ -86,6 +206,26 @@ fn receive(
                 cw20_msg.amount,
             )
         },
+        ReceiveMsg::InstantUnbond {
+            receiver,
+        } => {
+            let state = State::default();
+            state.assert_not_paused(deps.storage, "instant_unbond", |flags| flags.instant_unbond)?;
+
+            let steak_token = state.steak_token.load(deps.storage)?;
+            if info.sender != steak_token {
+                return Err(ContractError::InvalidToken {
+                    sender: info.sender.into(),
+                });
+            }
+
+            execute::instant_unbond(
+                deps,
+                env,
+                api.addr_validate(&receiver.unwrap_or(cw20_msg.sender))?,
+                cw20_msg.amount,
+            )
+        },
     }
 }
 
@@ -94,22 +234,26 @@ fn callback(
     env: Env,
     info: MessageInfo,
     callback_msg: CallbackMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     if env.contract.address != info.sender {
-        return Err(StdError::generic_err("callbacks can only be invoked by the contract itself"));
+        return Err(ContractError::UnauthorizedCallback {});
     }
 
     match callback_msg {
+        CallbackMsg::Swap {} => execute::swap(deps, env),
         CallbackMsg::Reinvest {} => execute::reinvest(deps, env),
     }
 }
 
 #[entry_point]
-pub fn reply(deps: DepsMut, env: Env, reply: Reply) -> StdResult<Response> {
+pub fn reply(deps: DepsMut, env: Env, reply: Reply) -> Result<Response, ContractError> {
     match reply.id {
         1 => execute::register_steak_token(deps, unwrap_reply(reply)?),
         2 => execute::register_received_coins(deps, env, unwrap_reply(reply)?.events),
-        id => Err(StdError::generic_err(format!("invalid reply id: {}; must be 1-2", id))),
+        3 => execute::register_unbond_nft(deps, unwrap_reply(reply)?),
+        id => Err(ContractError::InvalidReplyId {
+            id,
+        }),
     }
 }
 
@@ -127,17 +271,48 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::UnbondRequestsByBatch {
             id,
             start_after,
+            start_after_seq,
             limit,
-        } => to_binary(&queries::unbond_requests_by_batch(deps, id, start_after, limit)?),
+        } => to_binary(&queries::unbond_requests_by_batch(deps, id, start_after, start_after_seq, limit)?),
         QueryMsg::UnbondRequestsByUser {
             user,
             start_after,
             limit,
         } => to_binary(&queries::unbond_requests_by_user(deps, user, start_after, limit)?),
+        QueryMsg::UnbondingSummary {
+            user,
+        } => to_binary(&queries::unbonding_summary(deps, env, user)?),
+        QueryMsg::Insurance {} => to_binary(&queries::insurance(deps)?),
+        QueryMsg::Buffer {} => to_binary(&queries::buffer(deps)?),
+        QueryMsg::KeeperReward {} => to_binary(&queries::keeper_reward(deps)?),
+        QueryMsg::ExchangeRates {
+            start_after,
+            limit,
+        } => to_binary(&queries::exchange_rates(deps, start_after, limit)?),
+        QueryMsg::TwapExchangeRate {
+            window_seconds,
+        } => to_binary(&queries::twap_exchange_rate(deps, env, window_seconds)?),
+        QueryMsg::Harvests {
+            start_after,
+            limit,
+        } => to_binary(&queries::harvests(deps, start_after, limit)?),
+        QueryMsg::Apr {} => to_binary(&queries::apr(deps, env)?),
+        QueryMsg::InsuranceDraws {
+            start_after,
+            limit,
+        } => to_binary(&queries::insurance_draws(deps, start_after, limit)?),
+        QueryMsg::SwapRoutes {} => to_binary(&queries::swap_routes(deps)?),
+        QueryMsg::SimulateBond {
+            amount,
+            receiver,
+        } => to_binary(&queries::simulate_bond(deps, env, amount, receiver)?),
+        QueryMsg::SimulateUnbond {
+            usteak,
+        } => to_binary(&queries::simulate_unbond(deps, env, usteak)?),
     }
 }
 
 #[entry_point]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::new())
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    migrations::migrate(deps, msg)
 }
@@ -0,0 +1,235 @@
+use cosmwasm_std::{Decimal, StdError, Uint128};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("unauthorized: sender is not owner")]
+    Unauthorized {},
+
+    #[error("unauthorized: sender is not new owner")]
+    UnauthorizedNotNewOwner {},
+
+    #[error("unauthorized: guardian can only pause")]
+    UnauthorizedGuardianUnpause {},
+
+    #[error("action is paused: {action}")]
+    Paused {
+        action: String,
+    },
+
+    #[error("callbacks can only be invoked by the contract itself")]
+    UnauthorizedCallback {},
+
+    #[error("expecting Steak token, received {sender}")]
+    InvalidToken {
+        sender: String,
+    },
+
+    #[error("must deposit exactly one coin; received {received}")]
+    InvalidFundsCount {
+        received: usize,
+    },
+
+    #[error("expected {expected} deposit, received {received}")]
+    InvalidDenom {
+        expected: String,
+        received: String,
+    },
+
+    #[error("deposit amount must be non-zero")]
+    ZeroDeposit {},
+
+    #[error("invalid reply id: {id}; must be 1-3")]
+    InvalidReplyId {
+        id: u64,
+    },
+
+    #[error("cannot find `{ty}` event")]
+    EventNotFound {
+        ty: String,
+    },
+
+    #[error("cannot find `{key}` attribute")]
+    AttributeNotFound {
+        key: String,
+    },
+
+    #[error("no {denom} available to be bonded")]
+    NothingToReinvest {
+        denom: String,
+    },
+
+    #[error("batch can only be submitted for unbonding after {est_unbond_start_time}")]
+    BatchNotReady {
+        est_unbond_start_time: u64,
+    },
+
+    #[error("amount to cancel must be non-zero")]
+    ZeroCancelAmount {},
+
+    #[error("cannot cancel more than the amount queued in the pending batch: {queued}")]
+    CancelExceedsQueued {
+        queued: Uint128,
+    },
+
+    #[error("withdrawable amount is zero")]
+    NothingToWithdraw {},
+
+    #[error("validator is already whitelisted: {validator}")]
+    ValidatorAlreadyWhitelisted {
+        validator: String,
+    },
+
+    #[error("validator is not already whitelisted: {validator}")]
+    ValidatorNotWhitelisted {
+        validator: String,
+    },
+
+    #[error("validator weight must be non-zero: {validator}")]
+    ZeroWeight {
+        validator: String,
+    },
+
+    #[error("insurance rate cannot be higher than {max_insurance_rate}")]
+    InsuranceRateTooHigh {
+        max_insurance_rate: Decimal,
+    },
+
+    #[error("buffer target rate cannot be higher than {max_target_rate}")]
+    BufferTargetRateTooHigh {
+        max_target_rate: Decimal,
+    },
+
+    #[error("instant unbonding fee rate cannot be higher than {max_fee_rate}")]
+    InstantUnbondFeeRateTooHigh {
+        max_fee_rate: Decimal,
+    },
+
+    #[error("bond amount must be at least {min_bond} uluna")]
+    BondTooSmall {
+        min_bond: Uint128,
+    },
+
+    #[error("unbond amount must be at least {min_unbond} usteak")]
+    UnbondTooSmall {
+        min_unbond: Uint128,
+    },
+
+    #[error("remaining unbond amount must be zero or at least {min_unbond} usteak")]
+    RemainingUnbondTooSmall {
+        min_unbond: Uint128,
+    },
+
+    #[error("bond exceeds the total bond cap; {headroom} uluna can still be bonded")]
+    BondCapExceeded {
+        headroom: Uint128,
+    },
+
+    #[error("bond exceeds the per-address bond cap; {headroom} uluna can still be bonded to this address")]
+    AddressBondCapExceeded {
+        headroom: Uint128,
+    },
+
+    #[error("bond amount is too small to mint any Steak")]
+    ZeroMintAmount {},
+
+    #[error("the first bond must be greater than {min_amount} uluna")]
+    InitialBondTooSmall {
+        min_amount: Uint128,
+    },
+
+    #[error("keeper rate cannot be higher than {max_keeper_rate}")]
+    KeeperRateTooHigh {
+        max_keeper_rate: Decimal,
+    },
+
+    #[error("keeper cooldown cannot be shorter than {min_cooldown} seconds")]
+    KeeperCooldownTooShort {
+        min_cooldown: u64,
+    },
+
+    #[error("insufficient buffer for instant unbonding: {available} available, {required} required")]
+    InsufficientBuffer {
+        available: Uint128,
+        required: Uint128,
+    },
+
+    #[error("unbond NFT {token_id} is not owned by the user")]
+    UnbondNftNotOwned {
+        token_id: String,
+    },
+
+    #[error("invalid unbond NFT token id: {token_id}")]
+    InvalidTokenId {
+        token_id: String,
+    },
+
+    #[error("unbonding request {token_id} is not in the pending batch")]
+    UnbondRequestNotPending {
+        token_id: String,
+    },
+
+    #[error("invalid swap route for {denom}")]
+    InvalidSwapRoute {
+        denom: String,
+    },
+
+    #[error("swap route not found for {denom}")]
+    SwapRouteNotFound {
+        denom: String,
+    },
+
+    #[error("max spread cannot be higher than 1")]
+    MaxSpreadTooHigh {},
+
+    #[error("min price must be non-zero")]
+    ZeroMinPrice {},
+
+    #[error("epoch period and unbond period must be non-zero")]
+    ZeroPeriod {},
+
+    #[error("epoch period is too long")]
+    PeriodTooLong {},
+
+    #[error("epoch period must be at least 1/{max_entries} of unbond period")]
+    EpochPeriodTooShort {
+        max_entries: u64,
+    },
+
+    #[error("fee rate cannot be higher than {max_fee_rate}")]
+    FeeRateTooHigh {
+        max_fee_rate: Decimal,
+    },
+
+    #[error("cannot migrate from an unknown contract")]
+    UnknownContract {},
+
+    #[error("cannot migrate from a different contract: {contract}")]
+    InvalidContract {
+        contract: String,
+    },
+
+    #[error("invalid contract version: {version}")]
+    InvalidVersion {
+        version: String,
+    },
+
+    #[error("cannot migrate from a newer version: {previous_version} > {new_version}")]
+    Downgrade {
+        previous_version: String,
+        new_version: String,
+    },
+
+    #[error("storage upgrade is required to migrate from this version: {step}")]
+    StepMissing {
+        step: String,
+    },
+
+    #[error("storage upgrade has already been applied: {step}")]
+    StepAlreadyApplied {
+        step: String,
+    },
+}
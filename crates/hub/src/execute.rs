@@ -1,33 +1,124 @@
+use std::cmp;
 use std::str::FromStr;
 
 use cosmwasm_std::{
-    to_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, DistributionMsg, Env, Event, Order,
-    Response, StdError, StdResult, SubMsg, SubMsgResponse, Uint128, WasmMsg,
+    to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, DistributionMsg, Env, Event,
+    Order, Response, StdResult, Storage, SubMsg, SubMsgResponse, Uint128, WasmMsg,
 };
 use cw20::{Cw20ExecuteMsg, MinterResponse};
+use cw_storage_plus::Bound;
 use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
+use cw721_base::{
+    ExecuteMsg as Cw721ExecuteMsg, Extension, InstantiateMsg as Cw721InstantiateMsg, MintMsg,
+};
 
-use steak::hub::{Batch, CallbackMsg, ExecuteMsg, InstantiateMsg, PendingBatch, UnbondRequest};
+use steak::hub::{
+    Batch, CallbackMsg, ExchangeRateSnapshot, ExecuteMsg, HarvestRecord, InstantiateMsg, InsuranceDraw,
+    PendingBatch, SwapRoute, UnbondRequest, UnbondedHookMsg, WithdrawUnbondedResponse,
+};
+use steak::router::{
+    AssetInfo, ExecuteMsg as RouterExecuteMsg, QueryMsg as RouterQueryMsg,
+    SimulateSwapOperationsResponse, SwapOperation,
+};
 
-use crate::helpers::{query_cw20_total_supply, query_delegation, query_delegations};
+use crate::error::ContractError;
+use crate::helpers::{
+    parse_unbond_nft_token_id, query_cw20_balance, query_cw20_total_supply, query_delegation, query_delegations,
+    query_unbond_nft_owner, query_unbond_nft_tokens_page,
+};
 use crate::math::{
-    compute_mint_amount, compute_redelegations_for_rebalancing, compute_redelegations_for_removal,
-    compute_unbond_amount, compute_undelegations, reconcile_batches,
+    compute_buffer_top_up, compute_cumulative_exchange_rate, compute_mint_amount, compute_redelegations_for_rebalancing,
+    compute_redelegations_for_removal, compute_unbond_amount, compute_undelegations,
+    find_validator_to_delegate, reconcile_batches, USTEAK_LOCKED_ON_FIRST_BOND,
 };
 use crate::state::State;
-use crate::types::{Coins, Delegation};
+use crate::types::{Coins, Delegation, Redelegation, Undelegation};
+
+/// Contract name and version, stored under the cw2 standard for use in migrations
+pub const CONTRACT_NAME: &str = "crates.io:steak-hub";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The protocol fee can never be set higher than 10% of the harvested staking rewards
+const MAX_FEE_RATE_PERCENT: u64 = 10;
+
+/// No more than 10% of the harvested staking rewards can be put into the insurance reserve
+const MAX_INSURANCE_RATE_PERCENT: u64 = 10;
+
+/// No more than 5% of the harvested staking rewards can be put into the keeper pool
+const MAX_KEEPER_RATE_PERCENT: u64 = 5;
+
+/// Each maintenance action can be rewarded at most once an hour, such that the keeper pool can not be
+/// drained by invoking the same action over and over
+pub const MIN_KEEPER_COOLDOWN: u64 = 60 * 60;
+
+/// Rebalancing is only rewarded if it moves at least 1% of the Luna staked, such that the keeper pool
+/// can not be farmed by redelegating the small imbalances left by each bond
+const MIN_REWARDED_REBALANCE_PERCENT: u64 = 1;
+
+/// No more than 20% of the Luna backing Steak can be kept unstaked in the instant unbonding buffer
+const MAX_BUFFER_TARGET_RATE_PERCENT: u64 = 20;
+
+/// The instant unbonding fee can never be set higher than 10% of the unbonded amount
+const MAX_INSTANT_UNBOND_FEE_RATE_PERCENT: u64 = 10;
+
+/// Exchange rate snapshots are kept at least 1 hour apart; a more recent snapshot replaces the latest
+/// one until then
+const EXCHANGE_RATE_SNAPSHOT_INTERVAL: u64 = 60 * 60;
+
+/// Exchange rate snapshots older than 30 days are pruned
+const EXCHANGE_RATE_RETENTION: u64 = 30 * 24 * 60 * 60;
+
+/// At most this many expired exchange rate snapshots are pruned each time a new one is recorded
+const MAX_EXCHANGE_RATES_PRUNED: usize = 10;
+
+/// Harvested staking rewards are aggregated by the hour
+const HARVEST_RECORD_INTERVAL: u64 = 60 * 60;
+
+/// Harvest records older than 30 days are pruned
+const HARVEST_RECORD_RETENTION: u64 = 30 * 24 * 60 * 60;
+
+/// At most this many expired harvest records are pruned each time rewards are harvested
+const MAX_HARVEST_RECORDS_PRUNED: usize = 10;
+
+/// When withdrawing unbonded Luna, a user's unbonding requests are loaded at most this many at a time
+const UNBOND_REQUESTS_PAGE_SIZE: usize = 30;
+
+/// Default value of the staking module's `MaxEntries` parameter, i.e. the maximum number of
+/// unbonding entries allowed at a time for each delegator-validator pair
+const MAX_UNBONDING_ENTRIES: u64 = 7;
+
+/// Weight assigned to validators when they are whitelisted, unless the owner sets otherwise
+pub(crate) const DEFAULT_VALIDATOR_WEIGHT: u64 = 1;
 
 //--------------------------------------------------------------------------------------------------
 // Instantiation
 //--------------------------------------------------------------------------------------------------
 
-pub fn instantiate(deps: DepsMut, env: Env, msg: InstantiateMsg) -> StdResult<Response> {
+pub fn instantiate(deps: DepsMut, env: Env, msg: InstantiateMsg) -> Result<Response, ContractError> {
     let state = State::default();
 
+    assert_valid_fee_rate(msg.fee_rate)?;
+    assert_valid_periods(msg.epoch_period, msg.unbond_period)?;
+
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
     state.owner.save(deps.storage, &deps.api.addr_validate(&msg.owner)?)?;
+    state.denom.save(deps.storage, &msg.denom)?;
+    state.fee_account.save(deps.storage, &deps.api.addr_validate(&msg.fee_account)?)?;
+    state.fee_rate.save(deps.storage, &msg.fee_rate)?;
+    if let Some(max_total_bonded) = msg.max_total_bonded {
+        state.max_total_bonded.save(deps.storage, &max_total_bonded)?;
+    }
+    if let Some(max_bonded_per_address) = msg.max_bonded_per_address {
+        state.max_bonded_per_address.save(deps.storage, &max_bonded_per_address)?;
+    }
     state.epoch_period.save(deps.storage, &msg.epoch_period)?;
     state.unbond_period.save(deps.storage, &msg.unbond_period)?;
-    state.validators.save(deps.storage, &msg.validators)?;
+    state.keeper_cooldown.save(deps.storage, &MIN_KEEPER_COOLDOWN)?;
+    state.validators.save(
+        deps.storage,
+        &msg.validators.into_iter().map(|v| (v, DEFAULT_VALIDATOR_WEIGHT)).collect(),
+    )?;
     state.unlocked_coins.save(deps.storage, &vec![])?;
 
     state.pending_batch.save(
@@ -35,10 +126,29 @@ pub fn instantiate(deps: DepsMut, env: Env, msg: InstantiateMsg) -> StdResult<Re
         &PendingBatch {
             id: 1,
             usteak_to_burn: Uint128::zero(),
+            uluna_to_bond: Uint128::zero(),
             est_unbond_start_time: env.block.time.seconds() + msg.epoch_period,
         },
     )?;
 
+    let mut submsgs: Vec<SubMsg> = vec![];
+    if let Some(cw721_code_id) = msg.cw721_code_id {
+        submsgs.push(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                admin: Some(msg.owner.clone()),
+                code_id: cw721_code_id,
+                msg: to_binary(&Cw721InstantiateMsg {
+                    name: format!("{} Unbonding Request", msg.name),
+                    symbol: format!("{}-UNBOND", msg.symbol),
+                    minter: env.contract.address.to_string(),
+                })?,
+                funds: vec![],
+                label: "steak_unbond_nft".to_string(),
+            }),
+            3,
+        ));
+    }
+
     Ok(Response::new().add_submessage(SubMsg::reply_on_success(
         CosmosMsg::Wasm(WasmMsg::Instantiate {
             admin: Some(msg.owner), // use the owner as admin for now; can be changed later by a `MsgUpdateAdmin`
@@ -58,29 +168,48 @@ pub fn instantiate(deps: DepsMut, env: Env, msg: InstantiateMsg) -> StdResult<Re
             label: "steak_token".to_string(),
         }),
         1,
-    )))
+    ))
+    .add_submessages(submsgs))
+}
+
+pub fn register_steak_token(deps: DepsMut, response: SubMsgResponse) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    let contract_addr = parse_instantiated_contract(&deps, &response)?;
+    state.steak_token.save(deps.storage, &contract_addr)?;
+
+    Ok(Response::new())
 }
 
-pub fn register_steak_token(deps: DepsMut, response: SubMsgResponse) -> StdResult<Response> {
+pub fn register_unbond_nft(deps: DepsMut, response: SubMsgResponse) -> Result<Response, ContractError> {
     let state = State::default();
 
+    let contract_addr = parse_instantiated_contract(&deps, &response)?;
+    state.unbond_nft.save(deps.storage, &contract_addr)?;
+
+    Ok(Response::new())
+}
+
+/// Find the address of the contract instantiated by a submessage from its `instantiate` event
+fn parse_instantiated_contract(deps: &DepsMut, response: &SubMsgResponse) -> Result<Addr, ContractError> {
     let event = response
         .events
         .iter()
         .find(|event| event.ty == "instantiate")
-        .ok_or_else(|| StdError::generic_err("cannot find `instantiate` event"))?;
+        .ok_or_else(|| ContractError::EventNotFound {
+            ty: "instantiate".to_string(),
+        })?;
 
     let contract_addr_str = &event
         .attributes
         .iter()
         .find(|attr| attr.key == "_contract_address")
-        .ok_or_else(|| StdError::generic_err("cannot find `_contract_address` attribute"))?
+        .ok_or_else(|| ContractError::AttributeNotFound {
+            key: "_contract_address".to_string(),
+        })?
         .value;
 
-    let contract_addr = deps.api.addr_validate(contract_addr_str)?;
-    state.steak_token.save(deps.storage, &contract_addr)?;
-
-    Ok(Response::new())
+    Ok(deps.api.addr_validate(contract_addr_str)?)
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -91,67 +220,194 @@ pub fn register_steak_token(deps: DepsMut, response: SubMsgResponse) -> StdResul
 /// they all have the same amount of delegation. This is however quite gas-expensive: $1.5 cost in
 /// the case of 15 validators.
 ///
-/// To save gas for users, now we simply delegate all deposited Luna to the validator with the
-/// smallest amount of delegation. If delegations become severely unbalance as a result of this
-/// (e.g. when a single user makes a very big deposit), anyone can invoke `ExecuteMsg::Rebalance`
-/// to balance the delegations.
+/// To save gas for users, now we simply delegate all deposited Luna to the validator whose delegation
+/// falls the furthest below its weight-proportional target. If delegations become severely unbalance
+/// as a result of this (e.g. when a single user makes a very big deposit), anyone can invoke
+/// `ExecuteMsg::Rebalance` to balance the delegations.
+///
+/// If the instant unbonding buffer is below its target, it is topped up with the deposited Luna first.
+/// If netting is enabled, the rest is not delegated right away, but held until the pending batch is
+/// submitted, where it is netted against the unbonding requests.
+///
+/// If a hook message is provided, the Steak is minted to the contract itself and then sent to the
+/// receiver contract along with the message, using CW20 `Send`.
 pub fn bond(
     deps: DepsMut,
     env: Env,
     receiver: Addr,
     uluna_to_bond: Uint128,
-) -> StdResult<Response> {
+    hook_msg: Option<Binary>,
+) -> Result<Response, ContractError> {
     let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
     let steak_token = state.steak_token.load(deps.storage)?;
-    let validators = state.validators.load(deps.storage)?;
-
-    // Query the current delegations made to validators, and find the validator with the smallest
-    // delegated amount through a linear search
-    // The code for linear search is a bit uglier than using `sort_by` but cheaper: O(n) vs O(n * log(n))
-    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address)?;
-    let mut validator = &delegations[0].validator;
-    let mut amount = delegations[0].amount;
-    for d in &delegations[1..] {
-        if d.amount < amount {
-            validator = &d.validator;
-            amount = d.amount;
-        }
-    }
-    let new_delegation = Delegation {
-        validator: validator.clone(),
-        amount: uluna_to_bond.u128(),
-    };
 
-    // Query the current supply of Steak and compute the amount to mint
-    let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
-    let usteak_to_mint = compute_mint_amount(usteak_supply, uluna_to_bond, &delegations);
+    let PreparedBond {
+        delegations,
+        usteak_to_mint,
+        usteak_to_lock,
+        uluna_to_buffer,
+        uluna_to_stake,
+        validator,
+    } = prepare_bond(deps.as_ref(), &env, Some(&receiver), uluna_to_bond)?;
+
+    if !uluna_to_buffer.is_zero() {
+        let uluna_buffer = state.buffer.may_load(deps.storage)?.unwrap_or_default();
+        state.buffer.save(deps.storage, &(uluna_buffer + uluna_to_buffer))?;
+    }
 
-    let delegate_submsg = SubMsg::reply_on_success(new_delegation.to_cosmos_msg(), 2);
+    let mut delegate_submsgs: Vec<SubMsg> = vec![];
+    if let Some(validator) = validator {
+        let new_delegation = Delegation::new(&validator, uluna_to_stake.u128(), &denom);
+        record_delegation_change(deps.storage, &delegations, &validator, uluna_to_stake.u128(), 0)?;
+        delegate_submsgs.push(SubMsg::reply_on_success(new_delegation.to_cosmos_msg(), 2));
+    } else if !uluna_to_stake.is_zero() {
+        state.pending_batch.update(deps.storage, |mut pending_batch| -> StdResult<_> {
+            pending_batch.uluna_to_bond += uluna_to_stake;
+            Ok(pending_batch)
+        })?;
+    }
 
-    let mint_msg: CosmosMsg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: steak_token.into(),
+    let mut mint_msgs: Vec<CosmosMsg> = vec![];
+    if !usteak_to_lock.is_zero() {
+        mint_msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: steak_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: env.contract.address.to_string(),
+                amount: usteak_to_lock,
+            })?,
+            funds: vec![],
+        }));
+    }
+    let mint_recipient = if hook_msg.is_some() {
+        &env.contract.address
+    } else {
+        &receiver
+    };
+    mint_msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: steak_token.to_string(),
         msg: to_binary(&Cw20ExecuteMsg::Mint {
-            recipient: receiver.to_string(),
+            recipient: mint_recipient.to_string(),
             amount: usteak_to_mint,
         })?,
         funds: vec![],
-    });
+    }));
+    if let Some(msg) = hook_msg {
+        mint_msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: steak_token.into(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: receiver.to_string(),
+                amount: usteak_to_mint,
+                msg,
+            })?,
+            funds: vec![],
+        }));
+    }
 
     let event = Event::new("steakhub/bonded")
         .add_attribute("time", env.block.time.seconds().to_string())
         .add_attribute("height", env.block.height.to_string())
         .add_attribute("receiver", receiver)
         .add_attribute("uluna_bonded", uluna_to_bond)
-        .add_attribute("usteak_minted", usteak_to_mint);
+        .add_attribute("usteak_minted", usteak_to_mint)
+        .add_attribute("usteak_locked", usteak_to_lock);
 
     Ok(Response::new()
-        .add_submessage(delegate_submsg)
-        .add_message(mint_msg)
+        .add_submessages(delegate_submsgs)
+        .add_messages(mint_msgs)
         .add_event(event)
         .add_attribute("action", "steakhub/bond"))
 }
 
-pub fn harvest(deps: DepsMut, env: Env) -> StdResult<Response> {
+/// The outcome of bonding an amount of `uluna`, as computed by `prepare_bond`
+pub(crate) struct PreparedBond {
+    /// The contract's current delegations
+    pub delegations: Vec<Delegation>,
+    /// Amount of `usteak` to be minted to the receiver
+    pub usteak_to_mint: Uint128,
+    /// Amount of `usteak` to be minted to the contract itself, only on the first bond
+    pub usteak_to_lock: Uint128,
+    /// Amount of `uluna` to top up the instant unbonding buffer with
+    pub uluna_to_buffer: Uint128,
+    /// Amount of `uluna` to be staked, either delegated right away or held for netting
+    pub uluna_to_stake: Uint128,
+    /// The validator to delegate `uluna_to_stake` to. `None` if it is held for netting, or is zero
+    pub validator: Option<String>,
+}
+
+/// Run the checks of `bond` and compute its outcome without making any change, such that
+/// `queries::simulate_bond` follows the exact same steps. The per-address bond cap is only checked if a
+/// receiver is provided.
+pub(crate) fn prepare_bond(
+    deps: Deps,
+    env: &Env,
+    receiver: Option<&Addr>,
+    uluna_to_bond: Uint128,
+) -> Result<PreparedBond, ContractError> {
+    let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
+    let steak_token = state.steak_token.load(deps.storage)?;
+    let (validators, weights): (Vec<_>, Vec<_>) = state.validators.load(deps.storage)?.into_iter().unzip();
+    let netting = state.netting.may_load(deps.storage)?.unwrap_or_default();
+    let uluna_unstaked = state.load_uluna_unstaked(deps.storage)?;
+
+    let min_bond = state.min_bond.may_load(deps.storage)?.unwrap_or_default();
+    if uluna_to_bond < min_bond {
+        return Err(ContractError::BondTooSmall {
+            min_bond,
+        });
+    }
+
+    // Query the current delegations made to validators and the current supply of Steak, and compute
+    // the amount to mint
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
+
+    assert_within_bond_caps(
+        deps,
+        &steak_token,
+        receiver,
+        uluna_to_bond,
+        usteak_supply,
+        &delegations,
+        uluna_unstaked,
+    )?;
+
+    let (usteak_to_mint, usteak_to_lock) =
+        compute_mint_amount(usteak_supply, uluna_to_bond, &delegations, uluna_unstaked);
+
+    // The first bond must leave some Steak for the user after the locked amount is deducted, and no
+    // bond may be rounded down to zero Steak, e.g. after the exchange rate is inflated by a donation
+    if usteak_to_mint.is_zero() {
+        if !usteak_to_lock.is_zero() {
+            return Err(ContractError::InitialBondTooSmall {
+                min_amount: Uint128::new(USTEAK_LOCKED_ON_FIRST_BOND),
+            });
+        }
+        return Err(ContractError::ZeroMintAmount {});
+    }
+
+    let uluna_to_buffer = query_buffer_top_up(deps.storage, uluna_to_bond, &delegations)?;
+    let uluna_to_stake = uluna_to_bond - uluna_to_buffer;
+
+    // Find the validator furthest below its target through a linear search
+    let validator = if netting || uluna_to_stake.is_zero() {
+        None
+    } else {
+        Some(find_validator_to_delegate(uluna_to_stake, &delegations, &weights).to_string())
+    };
+
+    Ok(PreparedBond {
+        delegations,
+        usteak_to_mint,
+        usteak_to_lock,
+        uluna_to_buffer,
+        uluna_to_stake,
+        validator,
+    })
+}
+
+pub fn harvest(deps: DepsMut, env: Env, keeper: Addr) -> Result<Response, ContractError> {
     let withdraw_submsgs = deps
         .querier
         .query_all_delegations(&env.contract.address)?
@@ -166,52 +422,182 @@ pub fn harvest(deps: DepsMut, env: Env) -> StdResult<Response> {
         })
         .collect::<Vec<_>>();
 
-    let callback_msg = CallbackMsg::Reinvest {}.into_cosmos_msg(&env.contract.address)?;
+    let callback_msgs = vec![
+        CallbackMsg::Swap {}.into_cosmos_msg(&env.contract.address)?,
+        CallbackMsg::Reinvest {}.into_cosmos_msg(&env.contract.address)?,
+    ];
+
+    let (keeper_msgs, keeper_events) = pay_keeper_reward(deps.storage, &env, &keeper, "harvest")?;
 
     Ok(Response::new()
         .add_submessages(withdraw_submsgs)
-        .add_message(callback_msg)
+        .add_messages(callback_msgs)
+        .add_messages(keeper_msgs)
+        .add_events(keeper_events)
         .add_attribute("action", "steakhub/harvest"))
 }
 
+/// NOTE:
+/// 1. Coins without a swap route are left in `unlocked_coins` untouched.
+/// 2. The Luna returned by the router is registered to `unlocked_coins` the same way staking rewards
+///    are, i.e. by parsing the `coin_received` events, and will be staked by the `Reinvest` callback.
+/// 3. The minimum amount to receive is derived from the route's `min_price` set by the owner. This
+///    guards the route as a whole, while the router enforces the max spread on each of the individual
+///    operations. The router's simulation is only used to skip swaps that would return less than the
+///    minimum, leaving the coins in `unlocked_coins`.
+pub fn swap(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let state = State::default();
+    let unlocked_coins = state.unlocked_coins.load(deps.storage)?;
+
+    let mut coins_to_keep: Vec<Coin> = vec![];
+    let mut coins_to_swap: Vec<Coin> = vec![];
+    let mut swap_submsgs: Vec<SubMsg> = vec![];
+    for coin in unlocked_coins {
+        let route = match state.swap_routes.may_load(deps.storage, &coin.denom)? {
+            Some(route) if !coin.amount.is_zero() => route,
+            _ => {
+                coins_to_keep.push(coin);
+                continue;
+            },
+        };
+
+        // The minimum amount to receive is based on the price set by the owner, not the router's
+        // simulation, which could have been manipulated in the same transaction
+        let minimum_receive = coin.amount * route.min_price;
+
+        let simulation: SimulateSwapOperationsResponse = deps.querier.query_wasm_smart(
+            &route.router,
+            &RouterQueryMsg::SimulateSwapOperations {
+                offer_amount: coin.amount,
+                operations: route.operations.clone(),
+            },
+        )?;
+
+        // The amount is too small to return anything, or the swap would return less than the minimum
+        // amount; keep it until more is accumulated or the price recovers, instead of failing harvest
+        if minimum_receive.is_zero() || simulation.amount < minimum_receive {
+            coins_to_keep.push(coin);
+            continue;
+        }
+
+        swap_submsgs.push(SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: route.router,
+                msg: to_binary(&RouterExecuteMsg::ExecuteSwapOperations {
+                    operations: route.operations,
+                    minimum_receive: Some(minimum_receive),
+                    to: None,
+                    max_spread: Some(route.max_spread),
+                })?,
+                funds: vec![coin.clone()],
+            }),
+            2,
+        ));
+        coins_to_swap.push(coin);
+    }
+
+    state.unlocked_coins.save(deps.storage, &coins_to_keep)?;
+
+    let coins_swapped = coins_to_swap
+        .iter()
+        .map(|coin| coin.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let event = Event::new("steakhub/swapped")
+        .add_attribute("time", env.block.time.seconds().to_string())
+        .add_attribute("height", env.block.height.to_string())
+        .add_attribute("coins_swapped", coins_swapped);
+
+    Ok(Response::new()
+        .add_submessages(swap_submsgs)
+        .add_event(event)
+        .add_attribute("action", "steakhub/swap"))
+}
+
 /// NOTE:
 /// 1. When delegation Luna here, we don't need to use a `SubMsg` to handle the received coins,
-/// because we have already withdrawn all claimable staking rewards previously in the same atomic
-/// execution.
+///    because we have already withdrawn all claimable staking rewards previously in the same atomic
+///    execution.
 /// 2. Same as with `bond`, in the latest implementation we only delegate staking rewards with the
-/// validator that has the smallest delegation amount.
-pub fn reinvest(deps: DepsMut, env: Env) -> StdResult<Response> {
+///    validator that falls the furthest below its weight-proportional target.
+/// 3. Before delegating, the protocol fee is deducted from the rewards and sent to the fee account,
+///    and the insurance and keeper slices are deducted and kept in the contract as part of the
+///    insurance reserve and the keeper pool respectively.
+/// 4. If the instant unbonding buffer is below its target, it is topped up with the rewards before
+///    the rest is delegated.
+pub fn reinvest(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
     let state = State::default();
-    let validators = state.validators.load(deps.storage)?;
+    let denom = state.denom.load(deps.storage)?;
+    let (validators, weights): (Vec<_>, Vec<_>) = state.validators.load(deps.storage)?.into_iter().unzip();
+    let fee_account = state.fee_account.load(deps.storage)?;
+    let fee_rate = state.fee_rate.load(deps.storage)?;
+    let insurance_rate = state.insurance_rate.may_load(deps.storage)?.unwrap_or_default();
+    let keeper_rate = state.keeper_rate.may_load(deps.storage)?.unwrap_or_default();
     let mut unlocked_coins = state.unlocked_coins.load(deps.storage)?;
 
-    let uluna_to_bond = unlocked_coins
+    let uluna_harvested = unlocked_coins
         .iter()
-        .find(|coin| coin.denom == "uluna")
-        .ok_or_else(|| StdError::generic_err("no uluna available to be bonded"))?
+        .find(|coin| coin.denom == denom)
+        .ok_or_else(|| ContractError::NothingToReinvest {
+            denom: denom.clone(),
+        })?
         .amount;
 
-    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address)?;
-    let mut validator = &delegations[0].validator;
-    let mut amount = delegations[0].amount;
-    for d in &delegations[1..] {
-        if d.amount < amount {
-            validator = &d.validator;
-            amount = d.amount;
-        }
+    let uluna_protocol_fee = uluna_harvested * fee_rate;
+    let uluna_insurance = uluna_harvested * insurance_rate;
+    let uluna_keeper = uluna_harvested * keeper_rate;
+    let uluna_to_bond = uluna_harvested - uluna_protocol_fee - uluna_insurance - uluna_keeper;
+
+    if !uluna_insurance.is_zero() {
+        let uluna_reserve = state.insurance_reserve.may_load(deps.storage)?.unwrap_or_default();
+        state.insurance_reserve.save(deps.storage, &(uluna_reserve + uluna_insurance))?;
     }
-    let new_delegation = Delegation::new(validator, uluna_to_bond.u128());
+    if !uluna_keeper.is_zero() {
+        let uluna_pool = state.keeper_pool.may_load(deps.storage)?.unwrap_or_default();
+        state.keeper_pool.save(deps.storage, &(uluna_pool + uluna_keeper))?;
+    }
+
+    record_harvest(deps.storage, &env, uluna_harvested, uluna_to_bond)?;
 
-    unlocked_coins.retain(|coin| coin.denom != "uluna");
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let uluna_to_buffer = top_up_buffer(deps.storage, uluna_to_bond, &delegations)?;
+    let uluna_to_stake = uluna_to_bond - uluna_to_buffer;
+
+    unlocked_coins.retain(|coin| coin.denom != denom);
     state.unlocked_coins.save(deps.storage, &unlocked_coins)?;
 
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    if !uluna_to_stake.is_zero() {
+        let validator = find_validator_to_delegate(uluna_to_stake, &delegations, &weights);
+        let new_delegation = Delegation::new(validator, uluna_to_stake.u128(), &denom);
+        record_delegation_change(deps.storage, &delegations, validator, uluna_to_stake.u128(), 0)?;
+        msgs.push(new_delegation.to_cosmos_msg());
+    }
+
+    let steak_token = state.steak_token.load(deps.storage)?;
+    let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
+    let uluna_staked: u128 = delegations.iter().map(|d| d.amount).sum::<u128>() + uluna_to_stake.u128();
+    let uluna_total = Uint128::new(uluna_staked) + state.load_uluna_unstaked(deps.storage)?;
+    record_exchange_rate(deps.storage, &env, uluna_total, usteak_supply)?;
+    if !uluna_protocol_fee.is_zero() {
+        msgs.push(CosmosMsg::Bank(BankMsg::Send {
+            to_address: fee_account.into(),
+            amount: vec![Coin::new(uluna_protocol_fee.u128(), &denom)],
+        }));
+    }
+
     let event = Event::new("steakhub/harvested")
         .add_attribute("time", env.block.time.seconds().to_string())
         .add_attribute("height", env.block.height.to_string())
-        .add_attribute("uluna_bonded", uluna_to_bond);
+        .add_attribute("uluna_bonded", uluna_to_bond)
+        .add_attribute("uluna_protocol_fee", uluna_protocol_fee)
+        .add_attribute("uluna_insurance", uluna_insurance)
+        .add_attribute("uluna_keeper", uluna_keeper)
+        .add_attribute("uluna_buffered", uluna_to_buffer);
 
     Ok(Response::new()
-        .add_message(new_delegation.to_cosmos_msg())
+        .add_messages(msgs)
         .add_event(event)
         .add_attribute("action", "steakhub/reinvest"))
 }
@@ -221,7 +607,7 @@ pub fn register_received_coins(
     deps: DepsMut,
     env: Env,
     mut events: Vec<Event>,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     events.retain(|event| event.ty == "coin_received");
     if events.is_empty() {
         return Ok(Response::new());
@@ -243,19 +629,23 @@ pub fn register_received_coins(
         .add_attribute("action", "steakhub/register_received_coins"))
 }
 
-fn parse_coin_receiving_event(env: &Env, event: &Event) -> StdResult<Coins> {
+fn parse_coin_receiving_event(env: &Env, event: &Event) -> Result<Coins, ContractError> {
     let receiver = &event
         .attributes
         .iter()
         .find(|attr| attr.key == "receiver")
-        .ok_or_else(|| StdError::generic_err("cannot find `receiver` attribute"))?
+        .ok_or_else(|| ContractError::AttributeNotFound {
+            key: "receiver".to_string(),
+        })?
         .value;
 
     let amount_str = &event
         .attributes
         .iter()
         .find(|attr| attr.key == "amount")
-        .ok_or_else(|| StdError::generic_err("cannot find `amount` attribute"))?
+        .ok_or_else(|| ContractError::AttributeNotFound {
+            key: "amount".to_string(),
+        })?
         .value;
 
     let amount = if *receiver == env.contract.address {
@@ -276,28 +666,52 @@ pub fn queue_unbond(
     env: Env,
     receiver: Addr,
     usteak_to_burn: Uint128,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let state = State::default();
+    let unbond_nft = state.unbond_nft.may_load(deps.storage)?;
+
+    let min_unbond = state.min_unbond.may_load(deps.storage)?.unwrap_or_default();
+    if usteak_to_burn < min_unbond {
+        return Err(ContractError::UnbondTooSmall {
+            min_unbond,
+        });
+    }
 
     let mut pending_batch = state.pending_batch.load(deps.storage)?;
+    let (seq, request) = find_unbond_request(&deps, unbond_nft.as_ref(), pending_batch.id, &receiver)?;
+
     pending_batch.usteak_to_burn += usteak_to_burn;
     state.pending_batch.save(deps.storage, &pending_batch)?;
 
-    state.unbond_requests.update(
-        deps.storage,
-        (pending_batch.id, &receiver),
-        |x| -> StdResult<_> {
-            let mut request = x.unwrap_or_else(|| UnbondRequest {
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    let mut request = match request {
+        Some(request) => request,
+        None => {
+            let request = UnbondRequest {
                 id: pending_batch.id,
                 user: receiver.clone(),
                 shares: Uint128::zero(),
-            });
-            request.shares += usteak_to_burn;
-            Ok(request)
+                seq,
+                held_claimed: false,
+            };
+            if let Some(unbond_nft) = &unbond_nft {
+                msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: unbond_nft.into(),
+                    msg: to_binary(&Cw721ExecuteMsg::<Extension>::Mint(MintMsg {
+                        token_id: request.token_id(),
+                        owner: receiver.to_string(),
+                        token_uri: None,
+                        extension: None,
+                    }))?,
+                    funds: vec![],
+                }));
+            }
+            request
         },
-    )?;
+    };
+    request.shares += usteak_to_burn;
+    state.unbond_requests.save(deps.storage, (pending_batch.id, &request.key()), &request)?;
 
-    let mut msgs: Vec<CosmosMsg> = vec![];
     if env.block.time.seconds() >= pending_batch.est_unbond_start_time {
         msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: env.contract.address.into(),
@@ -319,25 +733,235 @@ pub fn queue_unbond(
         .add_attribute("action", "steakhub/queue_unbond"))
 }
 
-pub fn submit_batch(deps: DepsMut, env: Env) -> StdResult<Response> {
+/// NOTE: Only requests in the pending batch can be cancelled. Once a batch is submitted, the Steak has
+/// been burned and the Luna is unbonding.
+///
+/// If unbond NFTs are enabled, the request is cancelled by the current owner of its NFT, who receives
+/// the Steak. `token_id` identifies the request, and defaults to the user's own request.
+pub fn cancel_unbond(
+    deps: DepsMut,
+    env: Env,
+    user: Addr,
+    usteak_to_cancel: Uint128,
+    token_id: Option<String>,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+    let steak_token = state.steak_token.load(deps.storage)?;
+    let unbond_nft = state.unbond_nft.may_load(deps.storage)?;
+
+    if usteak_to_cancel.is_zero() {
+        return Err(ContractError::ZeroCancelAmount {});
+    }
+
+    let mut pending_batch = state.pending_batch.load(deps.storage)?;
+    let request = match (&unbond_nft, token_id) {
+        (Some(_), Some(token_id)) => {
+            let (id, key) = parse_unbond_nft_token_id(&token_id)?;
+            if id != pending_batch.id {
+                return Err(ContractError::UnbondRequestNotPending {
+                    token_id,
+                });
+            }
+            state.unbond_requests.may_load(deps.storage, (id, &key))?
+        },
+        _ => find_unbond_request(&deps, unbond_nft.as_ref(), pending_batch.id, &user)?.1,
+    };
+    let queued = request.as_ref().map(|request| request.shares).unwrap_or_default();
+    let mut request = match request {
+        Some(request) if usteak_to_cancel <= request.shares => request,
+        _ => {
+            return Err(ContractError::CancelExceedsQueued {
+                queued,
+            })
+        },
+    };
+
+    // A partial cancellation must not leave a request smaller than could have been queued
+    let min_unbond = state.min_unbond.may_load(deps.storage)?.unwrap_or_default();
+    let usteak_remaining = request.shares - usteak_to_cancel;
+    if !usteak_remaining.is_zero() && usteak_remaining < min_unbond {
+        return Err(ContractError::RemainingUnbondTooSmall {
+            min_unbond,
+        });
+    }
+
+    if let Some(unbond_nft) = &unbond_nft {
+        assert_unbond_nft_owner(&deps, unbond_nft, &request, &user)?;
+    }
+
+    let mut msgs: Vec<CosmosMsg> = vec![];
+    request.shares -= usteak_to_cancel;
+    if request.shares.is_zero() {
+        state.unbond_requests.remove(deps.storage, (pending_batch.id, &request.key()))?;
+        if let Some(unbond_nft) = &unbond_nft {
+            msgs.push(burn_unbond_nft_msg(unbond_nft, &request)?);
+        }
+    } else {
+        state.unbond_requests.save(deps.storage, (pending_batch.id, &request.key()), &request)?;
+    }
+
+    pending_batch.usteak_to_burn -= usteak_to_cancel;
+    state.pending_batch.save(deps.storage, &pending_batch)?;
+
+    msgs.insert(
+        0,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: steak_token.into(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: user.to_string(),
+                amount: usteak_to_cancel,
+            })?,
+            funds: vec![],
+        }),
+    );
+
+    let event = Event::new("steakhub/unbond_cancelled")
+        .add_attribute("time", env.block.time.seconds().to_string())
+        .add_attribute("height", env.block.height.to_string())
+        .add_attribute("id", pending_batch.id.to_string())
+        .add_attribute("user", user)
+        .add_attribute("usteak_cancelled", usteak_to_cancel);
+
+    Ok(Response::new()
+        .add_messages(msgs)
+        .add_event(event)
+        .add_attribute("action", "steakhub/cancel_unbond"))
+}
+
+/// NOTE: The exchange rate counts the buffer as Luna backing Steak, so the fee left in the buffer
+/// accrues to the remaining stakers
+pub fn instant_unbond(
+    deps: DepsMut,
+    env: Env,
+    receiver: Addr,
+    usteak_to_burn: Uint128,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
+    let steak_token = state.steak_token.load(deps.storage)?;
+    let validators = state
+        .validators
+        .load(deps.storage)?
+        .into_iter()
+        .map(|(validator, _)| validator)
+        .collect::<Vec<_>>();
+    let fee_rate = state.instant_unbond_fee_rate.may_load(deps.storage)?.unwrap_or_default();
+    let uluna_buffer = state.buffer.may_load(deps.storage)?.unwrap_or_default();
+
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
+
+    let uluna_to_unbond = compute_unbond_amount(
+        usteak_supply,
+        usteak_to_burn,
+        &delegations,
+        state.load_uluna_unstaked(deps.storage)?,
+    );
+    let uluna_fee = uluna_to_unbond * fee_rate;
+    let uluna_to_refund = uluna_to_unbond - uluna_fee;
+
+    if uluna_to_refund > uluna_buffer {
+        return Err(ContractError::InsufficientBuffer {
+            available: uluna_buffer,
+            required: uluna_to_refund,
+        });
+    }
+
+    state.buffer.save(deps.storage, &(uluna_buffer - uluna_to_refund))?;
+
+    let burn_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: steak_token.into(),
+        msg: to_binary(&Cw20ExecuteMsg::Burn {
+            amount: usteak_to_burn,
+        })?,
+        funds: vec![],
+    });
+
+    let refund_msg = CosmosMsg::Bank(BankMsg::Send {
+        to_address: receiver.to_string(),
+        amount: vec![Coin::new(uluna_to_refund.u128(), &denom)],
+    });
+
+    let event = Event::new("steakhub/instant_unbonded")
+        .add_attribute("time", env.block.time.seconds().to_string())
+        .add_attribute("height", env.block.height.to_string())
+        .add_attribute("receiver", receiver)
+        .add_attribute("usteak_burned", usteak_to_burn)
+        .add_attribute("uluna_refunded", uluna_to_refund)
+        .add_attribute("uluna_fee", uluna_fee);
+
+    Ok(Response::new()
+        .add_message(burn_msg)
+        .add_message(refund_msg)
+        .add_event(event)
+        .add_attribute("action", "steakhub/instant_unbond"))
+}
+
+/// NOTE: Bonds held during the epoch are netted against the unbonding requests, and only the difference
+/// is delegated or undelegated. The batch's share of the instant unbonding buffer is paid from the buffer.
+/// Both are recorded in the batch, from which unbonders claim them through `withdraw_unbonded`.
+pub fn submit_batch(deps: DepsMut, env: Env, keeper: Addr) -> Result<Response, ContractError> {
     let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
     let steak_token = state.steak_token.load(deps.storage)?;
-    let validators = state.validators.load(deps.storage)?;
+    let (validators, weights): (Vec<_>, Vec<_>) = state.validators.load(deps.storage)?.into_iter().unzip();
     let unbond_period = state.unbond_period.load(deps.storage)?;
     let pending_batch = state.pending_batch.load(deps.storage)?;
 
     let current_time = env.block.time.seconds();
     if current_time < pending_batch.est_unbond_start_time {
-        return Err(StdError::generic_err(
-            format!("batch can only be submitted for unbonding after {}", pending_batch.est_unbond_start_time),
-        ));
+        return Err(ContractError::BatchNotReady {
+            est_unbond_start_time: pending_batch.est_unbond_start_time,
+        });
     }
 
-    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address)?;
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
     let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
+    let uluna_staked: u128 = delegations.iter().map(|d| d.amount).sum();
+    let uluna_buffer = state.buffer.may_load(deps.storage)?.unwrap_or_default();
+
+    let uluna_to_unbond = compute_unbond_amount(
+        usteak_supply,
+        pending_batch.usteak_to_burn,
+        &delegations,
+        state.load_uluna_unstaked(deps.storage)?,
+    );
+
+    // The amount to unbond is priced against the Luna held by the contract as well as the Luna staked,
+    // so the batch's share of the held Luna is paid from it directly: bonds held during the epoch are
+    // matched first, followed by the batch's share of the instant unbonding buffer. Only the remainder
+    // is undelegated, which can not exceed the amount staked.
+    //
+    // The Luna paid from the contract's holdings stays in the contract as part of the batch. It can be
+    // claimed through `withdraw_unbonded` right away, while the rest is claimed once unbonding finishes
+    let uluna_matched = cmp::min(uluna_to_unbond, pending_batch.uluna_to_bond);
+    let uluna_from_buffer = if usteak_supply.is_zero() {
+        Uint128::zero()
+    } else {
+        cmp::min(
+            uluna_buffer.multiply_ratio(pending_batch.usteak_to_burn, usteak_supply),
+            uluna_to_unbond - uluna_matched,
+        )
+    };
+    let uluna_held = uluna_matched + uluna_from_buffer;
+    let uluna_to_undelegate = cmp::min(uluna_to_unbond - uluna_held, Uint128::new(uluna_staked));
+    let uluna_to_delegate = pending_batch.uluna_to_bond - uluna_matched;
+
+    state.buffer.save(deps.storage, &(uluna_buffer - uluna_from_buffer))?;
+
+    let mut undelegate_submsgs: Vec<SubMsg> = vec![];
+    let mut new_undelegations: Vec<Undelegation> = vec![];
+    if !uluna_to_undelegate.is_zero() {
+        new_undelegations = compute_undelegations(uluna_to_undelegate, &delegations, &weights);
+        for ud in &new_undelegations {
+            record_delegation_change(deps.storage, &delegations, &ud.validator, 0, ud.amount)?;
+        }
 
-    let uluna_to_unbond = compute_unbond_amount(usteak_supply, pending_batch.usteak_to_burn, &delegations);
-    let new_undelegations = compute_undelegations(uluna_to_unbond, &delegations);
+        undelegate_submsgs = new_undelegations
+            .iter()
+            .map(|d| SubMsg::reply_on_success(d.to_cosmos_msg(), 2))
+            .collect();
+    }
 
     // NOTE: Regarding the `uluna_unclaimed` value
     //
@@ -347,18 +971,50 @@ pub fn submit_batch(deps: DepsMut, env: Env) -> StdResult<Response> {
     // In this case, users who invokes `withdraw_unbonded` will have their txs failed as the contract
     // does not have enough Luna balance.
     //
-    // I don't have a solution for this... other than to manually fund contract with the slashed amount.
-    state.previous_batches.save(
-        deps.storage,
-        pending_batch.id,
-        &Batch {
-            id: pending_batch.id,
-            reconciled: false,
-            total_shares: pending_batch.usteak_to_burn,
-            uluna_unclaimed: uluna_to_unbond,
-            est_unbond_end_time: current_time + unbond_period,
-        },
-    )?;
+    // `reconcile` handles this by first covering the shortfall with the insurance reserve, and only
+    // deducting what the reserve cannot cover from the affected batches. The undelegations are
+    // recorded, such that the deduction can be attributed to batches that were actually unbonding
+    // from the slashed validator(s).
+    //
+    // The Luna held by the contract is not exposed to slashing, so it is tracked separately as
+    // `uluna_held`. If it covers the whole batch, nothing needs to be undelegated, and the batch is
+    // finished right away.
+    if !pending_batch.usteak_to_burn.is_zero() {
+        let fully_matched = uluna_to_undelegate.is_zero();
+        state.previous_batches.save(
+            deps.storage,
+            pending_batch.id,
+            &Batch {
+                id: pending_batch.id,
+                reconciled: fully_matched,
+                total_shares: pending_batch.usteak_to_burn,
+                uluna_unclaimed: uluna_to_undelegate,
+                est_unbond_end_time: if fully_matched {
+                    current_time
+                } else {
+                    current_time + unbond_period
+                },
+                uluna_held,
+                held_shares: if uluna_held.is_zero() {
+                    Uint128::zero()
+                } else {
+                    pending_batch.usteak_to_burn
+                },
+                undelegations: new_undelegations
+                    .iter()
+                    .map(|ud| (ud.validator.clone(), Uint128::new(ud.amount)))
+                    .collect(),
+            },
+        )?;
+    }
+
+    let mut delegate_submsgs: Vec<SubMsg> = vec![];
+    if !uluna_to_delegate.is_zero() {
+        let validator = find_validator_to_delegate(uluna_to_delegate, &delegations, &weights);
+        let new_delegation = Delegation::new(validator, uluna_to_delegate.u128(), &denom);
+        record_delegation_change(deps.storage, &delegations, validator, uluna_to_delegate.u128(), 0)?;
+        delegate_submsgs.push(SubMsg::reply_on_success(new_delegation.to_cosmos_msg(), 2));
+    }
 
     let epoch_period = state.epoch_period.load(deps.storage)?;
     state.pending_batch.save(
@@ -366,39 +1022,50 @@ pub fn submit_batch(deps: DepsMut, env: Env) -> StdResult<Response> {
         &PendingBatch {
             id: pending_batch.id + 1,
             usteak_to_burn: Uint128::zero(),
+            uluna_to_bond: Uint128::zero(),
             est_unbond_start_time: current_time + epoch_period,
         },
     )?;
 
-    let undelegate_submsgs = new_undelegations
-        .iter()
-        .map(|d| SubMsg::reply_on_success(d.to_cosmos_msg(), 2))
-        .collect::<Vec<_>>();
+    let uluna_staked = uluna_staked + uluna_to_delegate.u128() - uluna_to_undelegate.u128();
+    let uluna_total = Uint128::new(uluna_staked) + state.load_uluna_unstaked(deps.storage)?;
+    record_exchange_rate(deps.storage, &env, uluna_total, usteak_supply - pending_batch.usteak_to_burn)?;
 
-    let burn_msg = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: steak_token.into(),
-        msg: to_binary(&Cw20ExecuteMsg::Burn {
-            amount: pending_batch.usteak_to_burn,
-        })?,
-        funds: vec![],
-    });
+    let mut burn_msgs: Vec<CosmosMsg> = vec![];
+    if !pending_batch.usteak_to_burn.is_zero() {
+        burn_msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: steak_token.into(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                amount: pending_batch.usteak_to_burn,
+            })?,
+            funds: vec![],
+        }));
+    }
 
     let event = Event::new("steakhub/unbond_submitted")
         .add_attribute("time", env.block.time.seconds().to_string())
         .add_attribute("height", env.block.height.to_string())
         .add_attribute("id", pending_batch.id.to_string())
         .add_attribute("uluna_unbonded", uluna_to_unbond)
-        .add_attribute("usteak_burned", pending_batch.usteak_to_burn);
+        .add_attribute("usteak_burned", pending_batch.usteak_to_burn)
+        .add_attribute("uluna_matched", uluna_matched)
+        .add_attribute("uluna_from_buffer", uluna_from_buffer);
+
+    let (keeper_msgs, keeper_events) = pay_keeper_reward(deps.storage, &env, &keeper, "submit_batch")?;
 
     Ok(Response::new()
         .add_submessages(undelegate_submsgs)
-        .add_message(burn_msg)
+        .add_submessages(delegate_submsgs)
+        .add_messages(burn_msgs)
+        .add_messages(keeper_msgs)
         .add_event(event)
+        .add_events(keeper_events)
         .add_attribute("action", "steakhub/unbond"))
 }
 
-pub fn reconcile(deps: DepsMut, env: Env) -> StdResult<Response> {
+pub fn reconcile(deps: DepsMut, env: Env, keeper: Addr) -> Result<Response, ContractError> {
     let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
     let current_time = env.block.time.seconds();
 
     // Load batches that have not been reconciled
@@ -414,31 +1081,127 @@ pub fn reconcile(deps: DepsMut, env: Env) -> StdResult<Response> {
         })
         .collect::<StdResult<Vec<_>>>()?;
 
-    let mut batches = all_batches
+    let (mut batches, unfinished_batches): (Vec<_>, Vec<_>) = all_batches
         .into_iter()
-        .filter(|b| current_time > b.est_unbond_end_time)
-        .collect::<Vec<_>>();
+        .partition(|b| current_time > b.est_unbond_end_time);
 
     let uluna_expected_received: Uint128 = batches
         .iter()
         .map(|b| b.uluna_unclaimed)
         .sum();
 
+    // The batches already hold the part of their Luna that was not undelegated, until it is claimed
+    let uluna_held: Uint128 = batches
+        .iter()
+        .chain(&unfinished_batches)
+        .map(|b| b.uluna_held)
+        .sum();
+
     let unlocked_coins = state.unlocked_coins.load(deps.storage)?;
-    let uluna_expected_unlocked = Coins(unlocked_coins).find("uluna").amount;
+    let uluna_expected_unlocked = Coins(unlocked_coins).find(&denom).amount;
+
+    let uluna_reserve = state.insurance_reserve.may_load(deps.storage)?.unwrap_or_default();
+    let uluna_keeper_pool = state.keeper_pool.may_load(deps.storage)?.unwrap_or_default();
+    let uluna_unstaked = state.load_uluna_unstaked(deps.storage)?;
+
+    let uluna_expected =
+        uluna_expected_received + uluna_held + uluna_expected_unlocked + uluna_reserve + uluna_keeper_pool
+            + uluna_unstaked;
+    let uluna_actual = deps.querier.query_balance(&env.contract.address, &denom)?.amount;
+
+    // Any shortfall is first covered by the insurance reserve. Only what the reserve cannot cover is
+    // deducted from the batches
+    let uluna_shortfall = if batches.is_empty() {
+        Uint128::zero()
+    } else {
+        uluna_expected.saturating_sub(uluna_actual)
+    };
+    let uluna_drawn = cmp::min(uluna_shortfall, uluna_reserve);
+    let uluna_to_deduct = uluna_shortfall - uluna_drawn;
+
+    // A validator has been slashed if the contract's current delegation to it is less than expected
+    let mut slash_ratios: Vec<(String, Decimal)> = vec![];
+    for (validator, _) in batches.iter().flat_map(|b| &b.undelegations) {
+        if slash_ratios.iter().any(|(v, _)| v == validator) {
+            continue;
+        }
 
-    let uluna_expected = uluna_expected_received + uluna_expected_unlocked;
-    let uluna_actual = deps.querier.query_balance(&env.contract.address, "uluna")?.amount;
+        let delegation = query_delegation(&deps.querier, validator, &env.contract.address, &denom)?;
+        let uluna_expected = state.expected_delegations.may_load(deps.storage, validator)?.unwrap_or_default();
+        if uluna_expected.u128() > delegation.amount {
+            slash_ratios.push((
+                validator.clone(),
+                Decimal::from_ratio(uluna_expected.u128() - delegation.amount, uluna_expected),
+            ));
+        }
+    }
 
-    let uluna_to_deduct = uluna_expected.checked_sub(uluna_actual).unwrap_or_else(|_| Uint128::zero());
     if !uluna_to_deduct.is_zero() {
-        reconcile_batches(&mut batches, uluna_expected - uluna_actual);
+        reconcile_batches(&mut batches, uluna_to_deduct, &slash_ratios);
     }
 
-    for batch in &batches {
-        state.previous_batches.save(deps.storage, batch.id, batch)?;
+    // Once no batch still unbonding was undelegated from a slashed validator, the slash has been fully
+    // accounted for. Reset the expected delegation such that it is not attributed to future batches.
+    // This includes validators slashed without any batch being exposed to them
+    let recorded_validators = state
+        .expected_delegations
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for validator in recorded_validators {
+        let is_exposed = unfinished_batches
+            .iter()
+            .any(|b| b.undelegations.iter().any(|(v, _)| *v == validator));
+        if is_exposed {
+            continue;
+        }
+
+        let delegation = query_delegation(&deps.querier, &validator, &env.contract.address, &denom)?;
+        let uluna_expected = state.expected_delegations.load(deps.storage, &validator)?;
+        if uluna_expected.u128() > delegation.amount {
+            state.expected_delegations.save(deps.storage, &validator, &Uint128::new(delegation.amount))?;
+        }
     }
 
+    for batch in batches.iter_mut() {
+        batch.reconciled = true;
+        state.previous_batches.save(deps.storage, batch.id, batch)?;
+    }
+
+    if !uluna_drawn.is_zero() {
+        state.insurance_reserve.save(deps.storage, &(uluna_reserve - uluna_drawn))?;
+
+        let id = state
+            .insurance_draws
+            .keys(deps.storage, None, None, Order::Descending)
+            .next()
+            .transpose()?
+            .map_or(1, |id| id + 1);
+
+        state.insurance_draws.save(
+            deps.storage,
+            id,
+            &InsuranceDraw {
+                id,
+                time: current_time,
+                batch_ids: batches.iter().map(|b| b.id).collect(),
+                uluna_drawn,
+                uluna_deducted: uluna_to_deduct,
+            },
+        )?;
+    }
+
+    let steak_token = state.steak_token.load(deps.storage)?;
+    let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
+    let validators = state
+        .validators
+        .load(deps.storage)?
+        .into_iter()
+        .map(|(validator, _)| validator)
+        .collect::<Vec<_>>();
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let uluna_staked: u128 = delegations.iter().map(|d| d.amount).sum();
+    record_exchange_rate(deps.storage, &env, Uint128::new(uluna_staked) + uluna_unstaked, usteak_supply)?;
+
     let ids = batches
         .iter()
         .map(|b| b.id.to_string())
@@ -447,36 +1210,66 @@ pub fn reconcile(deps: DepsMut, env: Env) -> StdResult<Response> {
 
     let event = Event::new("steakhub/reconciled")
         .add_attribute("ids", ids)
+        .add_attribute("uluna_insurance_drawn", uluna_drawn.to_string())
         .add_attribute("uluna_deducted", uluna_to_deduct.to_string());
 
+    // Only reward the keeper if there was something to reconcile. The reward must be paid after the
+    // contract's balance has been checked above, as the balance does not yet reflect the payout
+    let (keeper_msgs, keeper_events) = if batches.is_empty() {
+        (vec![], vec![])
+    } else {
+        pay_keeper_reward(deps.storage, &env, &keeper, "reconcile")?
+    };
+
     Ok(Response::new()
+        .add_messages(keeper_msgs)
         .add_event(event)
+        .add_events(keeper_events)
         .add_attribute("action", "steakhub/reconcile"))
 }
 
+pub fn deposit_insurance(
+    deps: DepsMut,
+    env: Env,
+    depositor: Addr,
+    uluna_to_deposit: Uint128,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    let uluna_reserve = state.insurance_reserve.may_load(deps.storage)?.unwrap_or_default();
+    state.insurance_reserve.save(deps.storage, &(uluna_reserve + uluna_to_deposit))?;
+
+    let event = Event::new("steakhub/insurance_deposited")
+        .add_attribute("time", env.block.time.seconds().to_string())
+        .add_attribute("height", env.block.height.to_string())
+        .add_attribute("depositor", depositor)
+        .add_attribute("uluna_deposited", uluna_to_deposit);
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/deposit_insurance"))
+}
+
 pub fn withdraw_unbonded(
     deps: DepsMut,
     env: Env,
     user: Addr,
     receiver: Addr,
-) -> StdResult<Response> {
+    batch_ids: Option<Vec<u64>>,
+    limit: Option<u32>,
+    hook: bool,
+) -> Result<Response, ContractError> {
     let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
+    let unbond_nft = state.unbond_nft.may_load(deps.storage)?;
     let current_time = env.block.time.seconds();
 
-    // NOTE: If the user has too many unclaimed requests, this may not fit in the WASM memory...
-    // However, this is practically never going to happen. Who would create hundreds of unbonding
-    // requests and never claim them?
-    let requests = state
-        .unbond_requests
-        .idx
-        .user
-        .prefix(user.to_string())
-        .range(deps.storage, None, None, Order::Ascending)
-        .map(|item| {
-            let (_, v) = item?;
-            Ok(v)
-        })
-        .collect::<StdResult<Vec<_>>>()?;
+    // Duplicate IDs must be removed, otherwise the same request could be withdrawn twice
+    let batch_ids = batch_ids.map(|mut ids| {
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    });
 
     // NOTE: Luna in the following batches are withdrawn it the batch:
     // - is a _previous_ batch, not a _pending_ batch
@@ -484,66 +1277,287 @@ pub fn withdraw_unbonded(
     // - has finished unbonding
     // If not sure whether the batches have been reconciled, the user should first invoke `ExecuteMsg::Reconcile`
     // before withdrawing.
+    //
+    // Additionally, a request's part of the Luna held by the contract for its batch is withdrawn right
+    // away, even if the batch is still unbonding. Such a request is reported as both withdrawn and
+    // remaining.
+    //
+    // At most `limit` requests are withdrawn. Requests that are examined but not yet withdrawable are
+    // reported as remaining. Once the limit is reached, the requests left unexamined are indicated by
+    // `has_more` instead.
+    let limit = limit.map(|limit| limit as usize).unwrap_or(usize::MAX);
     let mut total_uluna_to_refund = Uint128::zero();
-    let mut ids: Vec<String> = vec![];
-    for request in &requests {
-        if let Ok(mut batch) = state.previous_batches.load(deps.storage, request.id) {
-            if batch.reconciled && batch.est_unbond_end_time < current_time {
-                let uluna_to_refund = batch
-                    .uluna_unclaimed
-                    .multiply_ratio(request.shares, batch.total_shares);
-
-                ids.push(request.id.to_string());
-
-                total_uluna_to_refund += uluna_to_refund;
-                batch.total_shares -= request.shares;
-                batch.uluna_unclaimed -= uluna_to_refund;
-
-                if batch.total_shares.is_zero() {
-                    state.previous_batches.remove(deps.storage, request.id)?;
-                } else {
-                    state.previous_batches.save(deps.storage, batch.id, &batch)?;
+    let mut ids: Vec<u64> = vec![];
+    let mut remaining_ids: Vec<u64> = vec![];
+    let mut has_more = false;
+    let mut burn_msgs: Vec<CosmosMsg> = vec![];
+    match &unbond_nft {
+        // If unbond NFTs are enabled, the user is entitled to the requests whose NFTs they own, which
+        // are not necessarily the ones they queued themselves. The NFTs are paged through, filtering by
+        // `batch_ids`, and paging stops once `limit` requests are withdrawn. The requests left unexamined
+        // are then indicated by `has_more`, and listed by `QueryMsg::UnbondingSummary`.
+        Some(unbond_nft) => {
+            let mut start_after: Option<String> = None;
+            'pages: loop {
+                let token_ids = query_unbond_nft_tokens_page(&deps.querier, unbond_nft, &user, start_after)?;
+                for token_id in &token_ids {
+                    if ids.len() >= limit {
+                        has_more = true;
+                        break 'pages;
+                    }
+
+                    let (id, key) = parse_unbond_nft_token_id(token_id)?;
+                    if batch_ids.as_ref().is_some_and(|batch_ids| !batch_ids.contains(&id)) {
+                        continue;
+                    }
+
+                    let request = state.unbond_requests.load(deps.storage, (id, &key))?;
+                    match withdraw_unbond_request(deps.storage, &request, current_time)? {
+                        Some((uluna_to_refund, is_removed)) => {
+                            total_uluna_to_refund += uluna_to_refund;
+                            ids.push(id);
+                            if is_removed {
+                                burn_msgs.push(burn_unbond_nft_msg(unbond_nft, &request)?);
+                            } else {
+                                remaining_ids.push(id);
+                            }
+                        },
+                        None => remaining_ids.push(id),
+                    }
+                }
+                match token_ids.last() {
+                    Some(token_id) => start_after = Some(token_id.clone()),
+                    None => break,
                 }
-
-                state.unbond_requests.remove(deps.storage, (request.id, &user))?;
             }
-        }
+        },
+        // Otherwise, the user is entitled to the requests they queued. These are loaded from the requests
+        // in `batch_ids` if specified, or else paged through by user, loading no more than needed to
+        // reach `limit`. As with NFTs, `has_more` indicates the requests left unexamined.
+        None => match &batch_ids {
+            Some(batch_ids) => {
+                for id in batch_ids {
+                    let request = match state.unbond_requests.may_load(deps.storage, (*id, user.as_str()))? {
+                        Some(request) => request,
+                        None => continue,
+                    };
+                    if ids.len() >= limit {
+                        has_more = true;
+                        break;
+                    }
+                    match withdraw_unbond_request(deps.storage, &request, current_time)? {
+                        Some((uluna_to_refund, is_removed)) => {
+                            total_uluna_to_refund += uluna_to_refund;
+                            ids.push(request.id);
+                            if !is_removed {
+                                remaining_ids.push(request.id);
+                            }
+                        },
+                        None => remaining_ids.push(request.id),
+                    }
+                }
+            },
+            None => {
+                let mut start_after: Option<Vec<u8>> = None;
+                'pages: loop {
+                    let requests = state
+                        .unbond_requests
+                        .idx
+                        .user
+                        .prefix(user.to_string())
+                        .range(deps.storage, start_after.clone().map(Bound::exclusive), None, Order::Ascending)
+                        .take(limit.saturating_sub(ids.len()).clamp(1, UNBOND_REQUESTS_PAGE_SIZE))
+                        .collect::<StdResult<Vec<_>>>()?;
+                    if requests.is_empty() {
+                        break;
+                    }
+                    for (pk, request) in requests {
+                        if ids.len() >= limit {
+                            has_more = true;
+                            break 'pages;
+                        }
+                        match withdraw_unbond_request(deps.storage, &request, current_time)? {
+                            Some((uluna_to_refund, is_removed)) => {
+                                total_uluna_to_refund += uluna_to_refund;
+                                ids.push(request.id);
+                                if !is_removed {
+                                    remaining_ids.push(request.id);
+                                }
+                            },
+                            None => remaining_ids.push(request.id),
+                        }
+                        start_after = Some(pk);
+                    }
+                }
+            },
+        },
     }
 
     if total_uluna_to_refund.is_zero() {
-        return Err(StdError::generic_err("withdrawable amount is zero"));
+        return Err(ContractError::NothingToWithdraw {});
     }
 
-    let refund_msg = CosmosMsg::Bank(BankMsg::Send {
-        to_address: receiver.clone().into(),
-        amount: vec![Coin::new(total_uluna_to_refund.u128(), "uluna")],
-    });
+    let refund_coins = vec![Coin::new(total_uluna_to_refund.u128(), &denom)];
+    let refund_msg = if hook {
+        UnbondedHookMsg::SteakUnbondedHook {
+            batch_ids: ids.clone(),
+            amount: total_uluna_to_refund,
+        }
+        .into_cosmos_msg(&receiver, refund_coins)?
+    } else {
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: receiver.clone().into(),
+            amount: refund_coins,
+        })
+    };
 
     let event = Event::new("steakhub/unbonded_withdrawn")
         .add_attribute("time", env.block.time.seconds().to_string())
         .add_attribute("height", env.block.height.to_string())
-        .add_attribute("ids", ids.join(","))
+        .add_attribute("ids", join_ids(&ids))
+        .add_attribute("ids_remaining", join_ids(&remaining_ids))
         .add_attribute("user", user)
         .add_attribute("receiver", receiver)
         .add_attribute("uluna_refunded", total_uluna_to_refund);
 
+    let data = WithdrawUnbondedResponse {
+        batch_ids: ids,
+        batch_ids_remaining: remaining_ids,
+        has_more,
+        amount: total_uluna_to_refund,
+    };
+
     Ok(Response::new()
         .add_message(refund_msg)
+        .add_messages(burn_msgs)
         .add_event(event)
-        .add_attribute("action", "steakhub/withdraw_unbonded"))
+        .add_attribute("action", "steakhub/withdraw_unbonded")
+        .set_data(to_binary(&data)?))
+}
+
+fn join_ids(ids: &[u64]) -> String {
+    ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// Withdraw an unbonding request's part of the Luna held by the contract for its batch, if not yet
+/// claimed, and the rest if the batch is reconciled and has finished unbonding, in which case the
+/// request is removed. Returns the amount of `uluna` to refund and whether the request was removed, or
+/// `None` if nothing is withdrawable
+fn withdraw_unbond_request(
+    storage: &mut dyn Storage,
+    request: &UnbondRequest,
+    current_time: u64,
+) -> StdResult<Option<(Uint128, bool)>> {
+    let state = State::default();
+
+    let mut batch = match state.previous_batches.may_load(storage, request.id)? {
+        Some(batch) => batch,
+        None => return Ok(None),
+    };
+
+    let mut request = request.clone();
+    let mut uluna_to_refund = Uint128::zero();
+    let is_held_claimable = !request.held_claimed && !batch.held_shares.is_zero();
+    if is_held_claimable {
+        let uluna_held = batch.uluna_held.multiply_ratio(request.shares, batch.held_shares);
+        batch.held_shares -= request.shares;
+        batch.uluna_held -= uluna_held;
+        request.held_claimed = true;
+        uluna_to_refund += uluna_held;
+    }
+
+    // Batches that did not need to undelegate anything finish unbonding as soon as they are submitted
+    let is_finished = batch.reconciled && batch.est_unbond_end_time <= current_time;
+    if is_finished {
+        let uluna_unclaimed = batch.uluna_unclaimed.multiply_ratio(request.shares, batch.total_shares);
+        batch.total_shares -= request.shares;
+        batch.uluna_unclaimed -= uluna_unclaimed;
+        uluna_to_refund += uluna_unclaimed;
+    }
+
+    if !is_held_claimable && !is_finished {
+        return Ok(None);
+    }
+
+    if batch.total_shares.is_zero() {
+        state.previous_batches.remove(storage, request.id)?;
+    } else {
+        state.previous_batches.save(storage, batch.id, &batch)?;
+    }
+
+    if is_finished {
+        state.unbond_requests.remove(storage, (request.id, &request.key()))?;
+    } else {
+        state.unbond_requests.save(storage, (request.id, &request.key()), &request)?;
+    }
+
+    Ok(Some((uluna_to_refund, is_finished)))
+}
+
+/// Find the user's request in a batch. If unbond NFTs are enabled and the NFT of the user's request has
+/// been transferred, the request belongs to the NFT's new owner. The user's next request in the batch is
+/// then given the next sequence number, such that it is represented by a new NFT. Returns the sequence
+/// number, along with the request if it exists
+fn find_unbond_request(
+    deps: &DepsMut,
+    unbond_nft: Option<&Addr>,
+    id: u64,
+    user: &Addr,
+) -> StdResult<(u64, Option<UnbondRequest>)> {
+    let state = State::default();
+    let mut seq = 0u64;
+    loop {
+        let request = state.unbond_requests.may_load(deps.storage, (id, &UnbondRequest::key_of(user, seq)))?;
+        match (&request, unbond_nft) {
+            (Some(request), Some(unbond_nft))
+                if query_unbond_nft_owner(&deps.querier, unbond_nft, request)? != *user =>
+            {
+                seq += 1;
+            },
+            _ => return Ok((seq, request)),
+        }
+    }
+}
+
+/// Assert the NFT representing an unbonding request is owned by the user
+fn assert_unbond_nft_owner(
+    deps: &DepsMut,
+    unbond_nft: &Addr,
+    request: &UnbondRequest,
+    user: &Addr,
+) -> Result<(), ContractError> {
+    if query_unbond_nft_owner(&deps.querier, unbond_nft, request)? != *user {
+        return Err(ContractError::UnbondNftNotOwned {
+            token_id: request.token_id(),
+        });
+    }
+    Ok(())
+}
+
+/// Burn the NFT representing an unbonding request that has been paid out or cancelled
+fn burn_unbond_nft_msg(unbond_nft: &Addr, request: &UnbondRequest) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: unbond_nft.into(),
+        msg: to_binary(&Cw721ExecuteMsg::<Extension>::Burn {
+            token_id: request.token_id(),
+        })?,
+        funds: vec![],
+    }))
 }
 
 //--------------------------------------------------------------------------------------------------
 // Ownership and management logics
 //--------------------------------------------------------------------------------------------------
 
-pub fn rebalance(deps: DepsMut, env: Env) -> StdResult<Response> {
+pub fn rebalance(deps: DepsMut, env: Env, keeper: Addr) -> Result<Response, ContractError> {
     let state = State::default();
-    let validators = state.validators.load(deps.storage)?;
+    let denom = state.denom.load(deps.storage)?;
+    let (validators, weights): (Vec<_>, Vec<_>) = state.validators.load(deps.storage)?.into_iter().unzip();
 
-    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address)?;
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
 
-    let new_redelegations = compute_redelegations_for_rebalancing(&delegations);
+    let new_redelegations = compute_redelegations_for_rebalancing(&delegations, &weights);
+    record_redelegations(deps.storage, &delegations, &new_redelegations)?;
 
     let redelegate_submsgs = new_redelegations
         .iter()
@@ -555,22 +1569,35 @@ pub fn rebalance(deps: DepsMut, env: Env) -> StdResult<Response> {
     let event = Event::new("steakhub/rebalanced")
         .add_attribute("uluna_moved", amount.to_string());
 
+    // Only reward the keeper if the validators were significantly out of balance
+    let uluna_staked: u128 = delegations.iter().map(|d| d.amount).sum();
+    let min_rewarded_amount = Uint128::new(uluna_staked) * Decimal::percent(MIN_REWARDED_REBALANCE_PERCENT);
+    let (keeper_msgs, keeper_events) = if amount == 0 || Uint128::new(amount) < min_rewarded_amount {
+        (vec![], vec![])
+    } else {
+        pay_keeper_reward(deps.storage, &env, &keeper, "rebalance")?
+    };
+
     Ok(Response::new()
         .add_submessages(redelegate_submsgs)
+        .add_messages(keeper_msgs)
         .add_event(event)
+        .add_events(keeper_events)
         .add_attribute("action", "steakhub/rebalance"))
 }
 
-pub fn add_validator(deps: DepsMut, sender: Addr, validator: String) -> StdResult<Response> {
+pub fn add_validator(deps: DepsMut, sender: Addr, validator: String) -> Result<Response, ContractError> {
     let state = State::default();
 
     state.assert_owner(deps.storage, &sender)?;
 
     state.validators.update(deps.storage, |mut validators| {
-        if validators.contains(&validator) {
-            return Err(StdError::generic_err("validator is already whitelisted"));
+        if validators.iter().any(|(v, _)| *v == validator) {
+            return Err(ContractError::ValidatorAlreadyWhitelisted {
+                validator: validator.clone(),
+            });
         }
-        validators.push(validator.clone());
+        validators.push((validator.clone(), DEFAULT_VALIDATOR_WEIGHT));
         Ok(validators)
     })?;
 
@@ -587,22 +1614,31 @@ pub fn remove_validator(
     env: Env,
     sender: Addr,
     validator: String,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let state = State::default();
 
     state.assert_owner(deps.storage, &sender)?;
 
+    let denom = state.denom.load(deps.storage)?;
     let validators = state.validators.update(deps.storage, |mut validators| {
-        if !validators.contains(&validator) {
-            return Err(StdError::generic_err("validator is not already whitelisted"));
+        if !validators.iter().any(|(v, _)| *v == validator) {
+            return Err(ContractError::ValidatorNotWhitelisted {
+                validator: validator.clone(),
+            });
         }
-        validators.retain(|v| *v != validator);
+        validators.retain(|(v, _)| *v != validator);
         Ok(validators)
     })?;
+    let (validators, weights): (Vec<_>, Vec<_>) = validators.into_iter().unzip();
 
-    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address)?;
-    let delegation_to_remove = query_delegation(&deps.querier, &validator, &env.contract.address)?;
-    let new_redelegations = compute_redelegations_for_removal(&delegation_to_remove, &delegations);
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let delegation_to_remove = query_delegation(&deps.querier, &validator, &env.contract.address, &denom)?;
+    let new_redelegations = compute_redelegations_for_removal(&delegation_to_remove, &delegations, &weights);
+    record_redelegations(
+        deps.storage,
+        &[delegations, vec![delegation_to_remove]].concat(),
+        &new_redelegations,
+    )?;
 
     let redelegate_submsgs = new_redelegations
         .iter()
@@ -618,7 +1654,180 @@ pub fn remove_validator(
         .add_attribute("action", "steakhub/remove_validator"))
 }
 
-pub fn transfer_ownership(deps: DepsMut, sender: Addr, new_owner: String) -> StdResult<Response> {
+/// NOTE: Changing the weights does not move any delegation by itself. New delegations and undelegations
+/// will gravitate towards the new targets, or anyone can invoke `ExecuteMsg::Rebalance` to move the
+/// delegations right away.
+pub fn set_validator_weights(
+    deps: DepsMut,
+    sender: Addr,
+    weights: Vec<(String, u64)>,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let mut validators = state.validators.load(deps.storage)?;
+    let mut event = Event::new("steakhub/validator_weights_updated");
+    for (validator, weight) in weights {
+        if weight == 0 {
+            return Err(ContractError::ZeroWeight {
+                validator,
+            });
+        }
+
+        let (_, current_weight) = validators
+            .iter_mut()
+            .find(|(v, _)| *v == validator)
+            .ok_or_else(|| ContractError::ValidatorNotWhitelisted {
+                validator: validator.clone(),
+            })?;
+        *current_weight = weight;
+
+        event = event.add_attribute(validator, weight.to_string());
+    }
+
+    state.validators.save(deps.storage, &validators)?;
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/set_validator_weights"))
+}
+
+pub fn set_swap_route(
+    deps: DepsMut,
+    sender: Addr,
+    denom: String,
+    router: String,
+    operations: Vec<SwapOperation>,
+    max_spread: Decimal,
+    min_price: Decimal,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let bond_denom = state.denom.load(deps.storage)?;
+    assert_valid_swap_route(&denom, &bond_denom, &operations)?;
+
+    if max_spread > Decimal::one() {
+        return Err(ContractError::MaxSpreadTooHigh {});
+    }
+
+    if min_price.is_zero() {
+        return Err(ContractError::ZeroMinPrice {});
+    }
+
+    let router = deps.api.addr_validate(&router)?;
+    state.swap_routes.save(
+        deps.storage,
+        &denom,
+        &SwapRoute {
+            denom: denom.clone(),
+            router: router.to_string(),
+            operations,
+            max_spread,
+            min_price,
+        },
+    )?;
+
+    let event = Event::new("steakhub/swap_route_set")
+        .add_attribute("denom", denom)
+        .add_attribute("router", router)
+        .add_attribute("max_spread", max_spread.to_string())
+        .add_attribute("min_price", min_price.to_string());
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/set_swap_route"))
+}
+
+pub fn remove_swap_route(deps: DepsMut, sender: Addr, denom: String) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    if !state.swap_routes.has(deps.storage, &denom) {
+        return Err(ContractError::SwapRouteNotFound {
+            denom,
+        });
+    }
+
+    state.swap_routes.remove(deps.storage, &denom);
+
+    let event = Event::new("steakhub/swap_route_removed")
+        .add_attribute("denom", denom);
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/remove_swap_route"))
+}
+
+/// NOTE: The guardian is meant to be a hot wallet or a monitoring bot that can react quickly to an
+/// incident, so it may only pause actions. Unpausing is reserved for the owner.
+pub fn update_pause_flags(
+    deps: DepsMut,
+    sender: Addr,
+    bond: Option<bool>,
+    queue_unbond: Option<bool>,
+    instant_unbond: Option<bool>,
+    withdraw_unbonded: Option<bool>,
+    harvest: Option<bool>,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    if state.assert_owner(deps.storage, &sender).is_err() {
+        if state.guardian.may_load(deps.storage)?.as_ref() != Some(&sender) {
+            return Err(ContractError::Unauthorized {});
+        }
+        if [bond, queue_unbond, instant_unbond, withdraw_unbonded, harvest].contains(&Some(false)) {
+            return Err(ContractError::UnauthorizedGuardianUnpause {});
+        }
+    }
+
+    let mut pause_flags = state.pause_flags.may_load(deps.storage)?.unwrap_or_default();
+    pause_flags.bond = bond.unwrap_or(pause_flags.bond);
+    pause_flags.queue_unbond = queue_unbond.unwrap_or(pause_flags.queue_unbond);
+    pause_flags.instant_unbond = instant_unbond.unwrap_or(pause_flags.instant_unbond);
+    pause_flags.withdraw_unbonded = withdraw_unbonded.unwrap_or(pause_flags.withdraw_unbonded);
+    pause_flags.harvest = harvest.unwrap_or(pause_flags.harvest);
+    state.pause_flags.save(deps.storage, &pause_flags)?;
+
+    let event = Event::new("steakhub/pause_flags_updated")
+        .add_attribute("sender", sender)
+        .add_attribute("bond", pause_flags.bond.to_string())
+        .add_attribute("queue_unbond", pause_flags.queue_unbond.to_string())
+        .add_attribute("instant_unbond", pause_flags.instant_unbond.to_string())
+        .add_attribute("withdraw_unbonded", pause_flags.withdraw_unbonded.to_string())
+        .add_attribute("harvest", pause_flags.harvest.to_string());
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_pause_flags"))
+}
+
+pub fn set_guardian(deps: DepsMut, sender: Addr, guardian: Option<String>) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let event = match guardian {
+        Some(guardian) => {
+            let guardian = deps.api.addr_validate(&guardian)?;
+            state.guardian.save(deps.storage, &guardian)?;
+            Event::new("steakhub/guardian_set").add_attribute("guardian", guardian)
+        },
+        None => {
+            state.guardian.remove(deps.storage);
+            Event::new("steakhub/guardian_removed")
+        },
+    };
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/set_guardian"))
+}
+
+pub fn transfer_ownership(deps: DepsMut, sender: Addr, new_owner: String) -> Result<Response, ContractError> {
     let state = State::default();
 
     state.assert_owner(deps.storage, &sender)?;
@@ -628,14 +1837,14 @@ pub fn transfer_ownership(deps: DepsMut, sender: Addr, new_owner: String) -> Std
         .add_attribute("action", "steakhub/transfer_ownership"))
 }
 
-pub fn accept_ownership(deps: DepsMut, sender: Addr) -> StdResult<Response> {
+pub fn accept_ownership(deps: DepsMut, sender: Addr) -> Result<Response, ContractError> {
     let state = State::default();
 
     let previous_owner = state.owner.load(deps.storage)?;
     let new_owner = state.new_owner.load(deps.storage)?;
 
     if sender != new_owner {
-        return Err(StdError::generic_err("unauthorized: sender is not new owner"));
+        return Err(ContractError::UnauthorizedNotNewOwner {});
     }
 
     state.owner.save(deps.storage, &sender)?;
@@ -649,3 +1858,547 @@ pub fn accept_ownership(deps: DepsMut, sender: Addr) -> StdResult<Response> {
         .add_event(event)
         .add_attribute("action", "steakhub/transfer_ownership"))
 }
+
+pub fn update_config(
+    deps: DepsMut,
+    sender: Addr,
+    epoch_period: Option<u64>,
+    unbond_period: Option<u64>,
+    netting: Option<bool>,
+    min_bond: Option<Uint128>,
+    min_unbond: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let previous_epoch_period = state.epoch_period.load(deps.storage)?;
+    let new_epoch_period = epoch_period.unwrap_or(previous_epoch_period);
+    let new_unbond_period = unbond_period.unwrap_or(state.unbond_period.load(deps.storage)?);
+
+    assert_valid_periods(new_epoch_period, new_unbond_period)?;
+
+    state.epoch_period.save(deps.storage, &new_epoch_period)?;
+    state.unbond_period.save(deps.storage, &new_unbond_period)?;
+
+    // Disabling netting does not release the bonds already held; they are delegated when the pending
+    // batch is submitted
+    let new_netting = match netting {
+        Some(netting) => netting,
+        None => state.netting.may_load(deps.storage)?.unwrap_or_default(),
+    };
+    state.netting.save(deps.storage, &new_netting)?;
+
+    let new_min_bond = match min_bond {
+        Some(min_bond) => min_bond,
+        None => state.min_bond.may_load(deps.storage)?.unwrap_or_default(),
+    };
+    state.min_bond.save(deps.storage, &new_min_bond)?;
+
+    let new_min_unbond = match min_unbond {
+        Some(min_unbond) => min_unbond,
+        None => state.min_unbond.may_load(deps.storage)?.unwrap_or_default(),
+    };
+    state.min_unbond.save(deps.storage, &new_min_unbond)?;
+
+    // The pending batch was scheduled based on the previous epoch period. Reschedule it such that it
+    // is submitted one new epoch period after the previous batch was submitted
+    if new_epoch_period != previous_epoch_period {
+        state.pending_batch.update(deps.storage, |mut pending_batch| -> StdResult<_> {
+            pending_batch.est_unbond_start_time =
+                pending_batch.est_unbond_start_time - previous_epoch_period + new_epoch_period;
+            Ok(pending_batch)
+        })?;
+    }
+
+    let event = Event::new("steakhub/config_updated")
+        .add_attribute("epoch_period", new_epoch_period.to_string())
+        .add_attribute("unbond_period", new_unbond_period.to_string())
+        .add_attribute("netting", new_netting.to_string())
+        .add_attribute("min_bond", new_min_bond)
+        .add_attribute("min_unbond", new_min_unbond);
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_config"))
+}
+
+pub fn update_fee(
+    deps: DepsMut,
+    sender: Addr,
+    new_fee_rate: Decimal,
+    new_fee_account: Option<String>,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+    assert_valid_fee_rate(new_fee_rate)?;
+
+    state.fee_rate.save(deps.storage, &new_fee_rate)?;
+    if let Some(new_fee_account) = new_fee_account {
+        state.fee_account.save(deps.storage, &deps.api.addr_validate(&new_fee_account)?)?;
+    }
+
+    let event = Event::new("steakhub/fee_updated")
+        .add_attribute("fee_rate", new_fee_rate.to_string())
+        .add_attribute("fee_account", state.fee_account.load(deps.storage)?);
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_fee"))
+}
+
+pub fn update_insurance_rate(
+    deps: DepsMut,
+    sender: Addr,
+    new_insurance_rate: Decimal,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let max_insurance_rate = Decimal::percent(MAX_INSURANCE_RATE_PERCENT);
+    if new_insurance_rate > max_insurance_rate {
+        return Err(ContractError::InsuranceRateTooHigh {
+            max_insurance_rate,
+        });
+    }
+
+    state.insurance_rate.save(deps.storage, &new_insurance_rate)?;
+
+    let event = Event::new("steakhub/insurance_rate_updated")
+        .add_attribute("insurance_rate", new_insurance_rate.to_string());
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_insurance_rate"))
+}
+
+pub fn update_buffer(
+    deps: DepsMut,
+    sender: Addr,
+    new_target_rate: Decimal,
+    new_fee_rate: Decimal,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let max_target_rate = Decimal::percent(MAX_BUFFER_TARGET_RATE_PERCENT);
+    if new_target_rate > max_target_rate {
+        return Err(ContractError::BufferTargetRateTooHigh {
+            max_target_rate,
+        });
+    }
+
+    let max_fee_rate = Decimal::percent(MAX_INSTANT_UNBOND_FEE_RATE_PERCENT);
+    if new_fee_rate > max_fee_rate {
+        return Err(ContractError::InstantUnbondFeeRateTooHigh {
+            max_fee_rate,
+        });
+    }
+
+    state.buffer_target_rate.save(deps.storage, &new_target_rate)?;
+    state.instant_unbond_fee_rate.save(deps.storage, &new_fee_rate)?;
+
+    let event = Event::new("steakhub/buffer_updated")
+        .add_attribute("target_rate", new_target_rate.to_string())
+        .add_attribute("fee_rate", new_fee_rate.to_string());
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_buffer"))
+}
+
+pub fn update_keeper_reward(
+    deps: DepsMut,
+    sender: Addr,
+    new_rate: Decimal,
+    new_amount: Uint128,
+    new_cooldown: u64,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let max_keeper_rate = Decimal::percent(MAX_KEEPER_RATE_PERCENT);
+    if new_rate > max_keeper_rate {
+        return Err(ContractError::KeeperRateTooHigh {
+            max_keeper_rate,
+        });
+    }
+
+    if new_cooldown < MIN_KEEPER_COOLDOWN {
+        return Err(ContractError::KeeperCooldownTooShort {
+            min_cooldown: MIN_KEEPER_COOLDOWN,
+        });
+    }
+
+    state.keeper_rate.save(deps.storage, &new_rate)?;
+    state.keeper_reward.save(deps.storage, &new_amount)?;
+    state.keeper_cooldown.save(deps.storage, &new_cooldown)?;
+
+    let event = Event::new("steakhub/keeper_reward_updated")
+        .add_attribute("rate", new_rate.to_string())
+        .add_attribute("amount", new_amount)
+        .add_attribute("cooldown", new_cooldown.to_string());
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_keeper_reward"))
+}
+
+pub fn update_bond_caps(
+    deps: DepsMut,
+    sender: Addr,
+    max_total_bonded: Option<Uint128>,
+    max_bonded_per_address: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let state = State::default();
+
+    state.assert_owner(deps.storage, &sender)?;
+
+    let mut event = Event::new("steakhub/bond_caps_updated");
+
+    if let Some(max_total_bonded) = max_total_bonded {
+        state.max_total_bonded.save(deps.storage, &max_total_bonded)?;
+        event = event.add_attribute("max_total_bonded", max_total_bonded);
+    } else {
+        state.max_total_bonded.remove(deps.storage);
+        event = event.add_attribute("max_total_bonded", "none");
+    }
+
+    if let Some(max_bonded_per_address) = max_bonded_per_address {
+        state.max_bonded_per_address.save(deps.storage, &max_bonded_per_address)?;
+        event = event.add_attribute("max_bonded_per_address", max_bonded_per_address);
+    } else {
+        state.max_bonded_per_address.remove(deps.storage);
+        event = event.add_attribute("max_bonded_per_address", "none");
+    }
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/update_bond_caps"))
+}
+
+/// Assert that bonding the given amount does not push the total amount of `uluna` backing Steak above
+/// `max_total_bonded`, or the value of the Steak held by the receiver above `max_bonded_per_address`.
+/// As Steak is transferable, the per-address cap can be circumvented by bonding to several addresses;
+/// it is meant for guarded launches only
+fn assert_within_bond_caps(
+    deps: Deps,
+    steak_token: &Addr,
+    receiver: Option<&Addr>,
+    uluna_to_bond: Uint128,
+    usteak_supply: Uint128,
+    delegations: &[Delegation],
+    uluna_unstaked: Uint128,
+) -> Result<(), ContractError> {
+    let state = State::default();
+
+    if let Some(max_total_bonded) = state.max_total_bonded.may_load(deps.storage)? {
+        let uluna_total = Uint128::new(delegations.iter().map(|d| d.amount).sum()) + uluna_unstaked;
+        let headroom = max_total_bonded.saturating_sub(uluna_total);
+        if uluna_to_bond > headroom {
+            return Err(ContractError::BondCapExceeded {
+                headroom,
+            });
+        }
+    }
+
+    let max_bonded_per_address = state.max_bonded_per_address.may_load(deps.storage)?;
+    if let (Some(max_bonded_per_address), Some(receiver)) = (max_bonded_per_address, receiver) {
+        let usteak_held = query_cw20_balance(&deps.querier, steak_token, receiver)?;
+        let uluna_held = if usteak_held.is_zero() {
+            Uint128::zero()
+        } else {
+            compute_unbond_amount(usteak_supply, usteak_held, delegations, uluna_unstaked)
+        };
+        let headroom = max_bonded_per_address.saturating_sub(uluna_held);
+        if uluna_to_bond > headroom {
+            return Err(ContractError::AddressBondCapExceeded {
+                headroom,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Pay the keeper reward from the keeper pool to the account who invoked a maintenance action. No
+/// reward is paid if the action has already been rewarded within the cooldown period, or if it was
+/// invoked by the contract itself, e.g. `submit_batch` triggered by `queue_unbond`
+fn pay_keeper_reward(
+    storage: &mut dyn Storage,
+    env: &Env,
+    keeper: &Addr,
+    action: &str,
+) -> StdResult<(Vec<CosmosMsg>, Vec<Event>)> {
+    let state = State::default();
+    let current_time = env.block.time.seconds();
+
+    if *keeper == env.contract.address {
+        return Ok((vec![], vec![]));
+    }
+
+    let uluna_reward = state.keeper_reward.may_load(storage)?.unwrap_or_default();
+    let uluna_pool = state.keeper_pool.may_load(storage)?.unwrap_or_default();
+    let uluna_rewarded = cmp::min(uluna_reward, uluna_pool);
+    if uluna_rewarded.is_zero() {
+        return Ok((vec![], vec![]));
+    }
+
+    let cooldown = state.keeper_cooldown.may_load(storage)?.unwrap_or(MIN_KEEPER_COOLDOWN);
+    if let Some(last_rewarded) = state.keeper_last_rewarded.may_load(storage, action)? {
+        if current_time < last_rewarded + cooldown {
+            return Ok((vec![], vec![]));
+        }
+    }
+
+    state.keeper_pool.save(storage, &(uluna_pool - uluna_rewarded))?;
+    state.keeper_last_rewarded.save(storage, action, &current_time)?;
+
+    let denom = state.denom.load(storage)?;
+    let msg = CosmosMsg::Bank(BankMsg::Send {
+        to_address: keeper.into(),
+        amount: vec![Coin::new(uluna_rewarded.u128(), &denom)],
+    });
+
+    let event = Event::new("steakhub/keeper_rewarded")
+        .add_attribute("time", current_time.to_string())
+        .add_attribute("height", env.block.height.to_string())
+        .add_attribute("action", action)
+        .add_attribute("keeper", keeper)
+        .add_attribute("uluna_rewarded", uluna_rewarded);
+
+    Ok((vec![msg], vec![event]))
+}
+
+/// Record staking rewards harvested, adding them to the current hour's record. Expired records are
+/// pruned a few at a time, such that storage stays bounded.
+fn record_harvest(
+    storage: &mut dyn Storage,
+    env: &Env,
+    uluna_harvested: Uint128,
+    uluna_bonded: Uint128,
+) -> StdResult<()> {
+    let state = State::default();
+    let current_time = env.block.time.seconds();
+
+    let expiry = Bound::exclusive(current_time.saturating_sub(HARVEST_RECORD_RETENTION));
+    let expired_times = state
+        .harvests
+        .keys(storage, None, Some(expiry), Order::Ascending)
+        .take(MAX_HARVEST_RECORDS_PRUNED)
+        .collect::<StdResult<Vec<_>>>()?;
+    for time in expired_times {
+        state.harvests.remove(storage, time);
+    }
+
+    let time = current_time - current_time % HARVEST_RECORD_INTERVAL;
+    state.harvests.update(storage, time, |x| -> StdResult<_> {
+        let mut record = x.unwrap_or_else(|| HarvestRecord {
+            time,
+            uluna_harvested: Uint128::zero(),
+            uluna_bonded: Uint128::zero(),
+        });
+        record.uluna_harvested += uluna_harvested;
+        record.uluna_bonded += uluna_bonded;
+        Ok(record)
+    })?;
+
+    Ok(())
+}
+
+/// Record a snapshot of the exchange rate, given the amount of `uluna` backing Steak and the supply of
+/// Steak once the current execution has completed.
+///
+/// Snapshots are kept at least `EXCHANGE_RATE_SNAPSHOT_INTERVAL` apart, except the latest one, which
+/// is replaced until the interval has elapsed since the one before it. The cumulative exchange rate
+/// carries over, so time-weighted averages stay exact. Expired snapshots are pruned a few at a time,
+/// such that storage stays bounded.
+fn record_exchange_rate(
+    storage: &mut dyn Storage,
+    env: &Env,
+    uluna_total: Uint128,
+    usteak_supply: Uint128,
+) -> StdResult<()> {
+    let state = State::default();
+    let current_time = env.block.time.seconds();
+
+    let recent_snapshots = state
+        .exchange_rates
+        .range(storage, None, None, Order::Descending)
+        .take(2)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let cumulative_exchange_rate = recent_snapshots
+        .first()
+        .map_or(Decimal::zero(), |latest| compute_cumulative_exchange_rate(latest, current_time));
+
+    if let [latest, previous] = recent_snapshots.as_slice() {
+        if current_time < previous.time + EXCHANGE_RATE_SNAPSHOT_INTERVAL {
+            state.exchange_rates.remove(storage, latest.time);
+        }
+    }
+
+    let expiry = Bound::exclusive(current_time.saturating_sub(EXCHANGE_RATE_RETENTION));
+    let expired_times = state
+        .exchange_rates
+        .keys(storage, None, Some(expiry), Order::Ascending)
+        .take(MAX_EXCHANGE_RATES_PRUNED)
+        .collect::<StdResult<Vec<_>>>()?;
+    for time in expired_times {
+        state.exchange_rates.remove(storage, time);
+    }
+
+    let exchange_rate = if usteak_supply.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(uluna_total, usteak_supply)
+    };
+
+    state.exchange_rates.save(
+        storage,
+        current_time,
+        &ExchangeRateSnapshot {
+            time: current_time,
+            total_uluna: uluna_total,
+            total_usteak: usteak_supply,
+            exchange_rate,
+            cumulative_exchange_rate,
+        },
+    )
+}
+
+/// Compute the part of an incoming amount of `uluna` needed to top up the instant unbonding buffer,
+/// if it is below its target
+fn query_buffer_top_up(
+    storage: &dyn Storage,
+    uluna_incoming: Uint128,
+    current_delegations: &[Delegation],
+) -> StdResult<Uint128> {
+    let state = State::default();
+    let target_rate = state.buffer_target_rate.may_load(storage)?.unwrap_or_default();
+    let uluna_buffer = state.buffer.may_load(storage)?.unwrap_or_default();
+
+    let uluna_staked: u128 = current_delegations.iter().map(|d| d.amount).sum();
+    let uluna_total = Uint128::new(uluna_staked) + state.load_uluna_unstaked(storage)?;
+
+    Ok(compute_buffer_top_up(uluna_incoming, uluna_total, uluna_buffer, target_rate))
+}
+
+/// Put part of an incoming amount of `uluna` into the instant unbonding buffer if it is below its
+/// target. Returns the amount put into the buffer
+fn top_up_buffer(
+    storage: &mut dyn Storage,
+    uluna_incoming: Uint128,
+    current_delegations: &[Delegation],
+) -> StdResult<Uint128> {
+    let state = State::default();
+
+    let uluna_to_buffer = query_buffer_top_up(storage, uluna_incoming, current_delegations)?;
+    if !uluna_to_buffer.is_zero() {
+        let uluna_buffer = state.buffer.may_load(storage)?.unwrap_or_default();
+        state.buffer.save(storage, &(uluna_buffer + uluna_to_buffer))?;
+    }
+
+    Ok(uluna_to_buffer)
+}
+
+/// Record a change in the amount of `uluna` delegated to a validator. A validator without a record,
+/// e.g. after migrating from an older version, starts from its current delegation
+fn record_delegation_change(
+    storage: &mut dyn Storage,
+    current_delegations: &[Delegation],
+    validator: &str,
+    uluna_added: u128,
+    uluna_removed: u128,
+) -> StdResult<()> {
+    let state = State::default();
+
+    let uluna_expected = match state.expected_delegations.may_load(storage, validator)? {
+        Some(uluna_expected) => uluna_expected.u128(),
+        None => current_delegations
+            .iter()
+            .find(|d| d.validator == validator)
+            .map_or(0, |d| d.amount),
+    };
+
+    state.expected_delegations.save(
+        storage,
+        validator,
+        &Uint128::new((uluna_expected + uluna_added).saturating_sub(uluna_removed)),
+    )
+}
+
+fn record_redelegations(
+    storage: &mut dyn Storage,
+    current_delegations: &[Delegation],
+    redelegations: &[Redelegation],
+) -> StdResult<()> {
+    for rd in redelegations {
+        record_delegation_change(storage, current_delegations, &rd.src, 0, rd.amount)?;
+        record_delegation_change(storage, current_delegations, &rd.dst, rd.amount, 0)?;
+    }
+    Ok(())
+}
+
+/// A swap route must start from the reward denom, end with the bond denom, and each operation must
+/// offer the asset returned by the previous one
+fn assert_valid_swap_route(
+    denom: &str,
+    bond_denom: &str,
+    operations: &[SwapOperation],
+) -> Result<(), ContractError> {
+    let native = |denom: &str| AssetInfo::NativeToken {
+        denom: denom.to_string(),
+    };
+
+    let is_valid = denom != bond_denom
+        && !operations.is_empty()
+        && operations[0].offer_asset_info() == native(denom)
+        && operations[operations.len() - 1].ask_asset_info() == native(bond_denom)
+        && operations.windows(2).all(|ops| ops[0].ask_asset_info() == ops[1].offer_asset_info());
+
+    if !is_valid {
+        return Err(ContractError::InvalidSwapRoute {
+            denom: denom.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The epoch period must be long enough such that the number of batches unbonding at the same time
+/// never exceeds the staking module's limit on unbonding entries
+fn assert_valid_periods(epoch_period: u64, unbond_period: u64) -> Result<(), ContractError> {
+    if epoch_period == 0 || unbond_period == 0 {
+        return Err(ContractError::ZeroPeriod {});
+    }
+
+    let max_unbond_period = epoch_period
+        .checked_mul(MAX_UNBONDING_ENTRIES)
+        .ok_or(ContractError::PeriodTooLong {})?;
+    if unbond_period > max_unbond_period {
+        return Err(ContractError::EpochPeriodTooShort {
+            max_entries: MAX_UNBONDING_ENTRIES,
+        });
+    }
+
+    Ok(())
+}
+
+pub(crate) fn assert_valid_fee_rate(fee_rate: Decimal) -> Result<(), ContractError> {
+    let max_fee_rate = Decimal::percent(MAX_FEE_RATE_PERCENT);
+    if fee_rate > max_fee_rate {
+        return Err(ContractError::FeeRateTooHigh {
+            max_fee_rate,
+        });
+    }
+    Ok(())
+}
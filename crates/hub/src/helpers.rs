@@ -3,8 +3,11 @@ use std::str::FromStr;
 use cosmwasm_std::{
     Addr, Coin, QuerierWrapper, Reply, StdError, StdResult, SubMsgResponse, Uint128,
 };
-use cw20::{Cw20QueryMsg, TokenInfoResponse};
+use cw20::{BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
+use cw721::{Cw721QueryMsg, OwnerOfResponse, TokensResponse};
+use steak::hub::UnbondRequest;
 
+use crate::error::ContractError;
 use crate::types::Delegation;
 
 /// Unwrap a `Reply` object to extract the response
@@ -21,15 +24,94 @@ pub(crate) fn query_cw20_total_supply(
     Ok(token_info.total_supply)
 }
 
+/// Query the balance of a CW20 token held by an account
+pub(crate) fn query_cw20_balance(
+    querier: &QuerierWrapper,
+    token_addr: &Addr,
+    account: &Addr,
+) -> StdResult<Uint128> {
+    let res: BalanceResponse = querier.query_wasm_smart(
+        token_addr,
+        &Cw20QueryMsg::Balance {
+            address: account.to_string(),
+        },
+    )?;
+    Ok(res.balance)
+}
+
+/// Query the owner of the NFT representing an unbonding request
+pub(crate) fn query_unbond_nft_owner(
+    querier: &QuerierWrapper,
+    unbond_nft: &Addr,
+    request: &UnbondRequest,
+) -> StdResult<Addr> {
+    let res: OwnerOfResponse = querier.query_wasm_smart(
+        unbond_nft,
+        &Cw721QueryMsg::OwnerOf {
+            token_id: request.token_id(),
+            include_expired: None,
+        },
+    )?;
+    Ok(Addr::unchecked(res.owner))
+}
+
+/// Query IDs of all NFTs representing unbonding requests that are owned by a user
+pub(crate) fn query_unbond_nft_tokens(
+    querier: &QuerierWrapper,
+    unbond_nft: &Addr,
+    owner: &Addr,
+) -> StdResult<Vec<String>> {
+    let mut token_ids: Vec<String> = vec![];
+    loop {
+        let page = query_unbond_nft_tokens_page(querier, unbond_nft, owner, token_ids.last().cloned())?;
+        if page.is_empty() {
+            return Ok(token_ids);
+        }
+        token_ids.extend(page);
+    }
+}
+
+/// Query one page of IDs of NFTs representing unbonding requests that are owned by a user, starting
+/// after the given token ID. An empty page means there are no more tokens
+pub(crate) fn query_unbond_nft_tokens_page(
+    querier: &QuerierWrapper,
+    unbond_nft: &Addr,
+    owner: &Addr,
+    start_after: Option<String>,
+) -> StdResult<Vec<String>> {
+    let res: TokensResponse = querier.query_wasm_smart(
+        unbond_nft,
+        &Cw721QueryMsg::Tokens {
+            owner: owner.to_string(),
+            start_after,
+            limit: None,
+        },
+    )?;
+    Ok(res.tokens)
+}
+
+/// Parse the ID of an unbond NFT into the batch ID and the key of the request within the batch, i.e.
+/// the reverse of `UnbondRequest::token_id`
+pub(crate) fn parse_unbond_nft_token_id(token_id: &str) -> Result<(u64, String), ContractError> {
+    token_id
+        .split_once('/')
+        .and_then(|(id, key)| Some((id.parse().ok()?, key.to_string())))
+        .ok_or_else(|| ContractError::InvalidTokenId {
+            token_id: token_id.to_string(),
+        })
+}
+
 /// Query the amounts of Luna a staker is delegating to a specific validator
 pub(crate) fn query_delegation(
     querier: &QuerierWrapper,
     validator: &str,
     delegator_addr: &Addr,
+    denom: &str,
 ) -> StdResult<Delegation> {
     Ok(Delegation {
         validator: validator.to_string(),
         amount: querier.query_delegation(delegator_addr, validator)?.map(|fd| fd.amount.amount.u128()).unwrap_or(0),
+        denom: denom.to_string(),
     })
 }
 
@@ -38,10 +120,11 @@ pub(crate) fn query_delegations(
     querier: &QuerierWrapper,
     validators: &[String],
     delegator_addr: &Addr,
+    denom: &str,
 ) -> StdResult<Vec<Delegation>> {
     validators
         .iter()
-        .map(|validator| query_delegation(querier, validator, delegator_addr))
+        .map(|validator| query_delegation(querier, validator, delegator_addr, denom))
         .collect()
 }
 
@@ -56,7 +139,7 @@ pub(crate) fn query_delegations(
 ///
 /// This assumes the denom never starts with a number, which is true on Terra.
 pub(crate) fn parse_coin(s: &str) -> StdResult<Coin> {
-    for (i, c) in s.chars().enumerate() {
+    for (i, c) in s.char_indices() {
         if c.is_alphabetic() {
             let amount = Uint128::from_str(&s[..i])?;
             let denom = &s[i..];
@@ -69,22 +152,23 @@ pub(crate) fn parse_coin(s: &str) -> StdResult<Coin> {
 
 /// Find the amount of a denom sent along a message, assert it is non-zero, and no other denom were
 /// sent together
-pub(crate) fn parse_received_fund(funds: &[Coin], denom: &str) -> StdResult<Uint128> {
+pub(crate) fn parse_received_fund(funds: &[Coin], denom: &str) -> Result<Uint128, ContractError> {
     if funds.len() != 1 {
-        return Err(StdError::generic_err(
-            format!("must deposit exactly one coin; received {}", funds.len()),
-        ));
+        return Err(ContractError::InvalidFundsCount {
+            received: funds.len(),
+        });
     }
 
     let fund = &funds[0];
     if fund.denom != denom {
-        return Err(StdError::generic_err(
-            format!("expected {} deposit, received {}", denom, fund.denom),
-        ));
+        return Err(ContractError::InvalidDenom {
+            expected: denom.to_string(),
+            received: fund.denom.clone(),
+        });
     }
 
     if fund.amount.is_zero() {
-        return Err(StdError::generic_err("deposit amount must be non-zero"));
+        return Err(ContractError::ZeroDeposit {});
     }
 
     Ok(fund.amount)
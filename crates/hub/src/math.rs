@@ -1,8 +1,8 @@
 use std::{cmp, cmp::Ordering};
 
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Decimal, Uint128, Uint256};
 
-use steak::hub::Batch;
+use steak::hub::{Batch, ExchangeRateSnapshot};
 
 use crate::types::{Delegation, Redelegation, Undelegation};
 
@@ -10,19 +10,37 @@ use crate::types::{Delegation, Redelegation, Undelegation};
 // Minting/burning logics
 //--------------------------------------------------------------------------------------------------
 
-/// Compute the amount of Steak token to mint for a specific Luna stake amount. If current total
-/// staked amount is zero, we use 1 usteak = 1 uluna; otherwise, we calculate base on the current
-/// uluna per ustake ratio.
+/// Amount of Steak locked in the contract forever on the first bond
+///
+/// Without it, the first depositor could bond a tiny amount, then donate Luna to the contract to
+/// inflate the exchange rate, such that later deposits are rounded down to zero Steak, with the
+/// Luna accruing to the first depositor. With this amount locked, the donation is shared with the
+/// locked Steak, making the attack prohibitively expensive.
+pub(crate) const USTEAK_LOCKED_ON_FIRST_BOND: u128 = 1000;
+
+/// Compute the amount of Steak token to mint for a specific Luna stake amount. If there is no Steak
+/// yet or the total staked amount is zero, we use 1 usteak = 1 uluna; otherwise, we calculate base on
+/// the current uluna per ustake ratio.
+///
+/// `uluna_unstaked` is Luna held by the contract that backs Steak without being delegated, e.g. bonds
+/// held to be netted against unbonding requests.
+///
+/// Returns the amount to mint to the user, and the amount to lock in the contract forever, which is
+/// non-zero on the first bond only.
 pub(crate) fn compute_mint_amount(
     usteak_supply: Uint128,
     uluna_to_bond: Uint128,
     current_delegations: &[Delegation],
-) -> Uint128 {
-    let uluna_bonded: u128 = current_delegations.iter().map(|d| d.amount).sum();
-    if uluna_bonded == 0 {
-        uluna_to_bond
+    uluna_unstaked: Uint128,
+) -> (Uint128, Uint128) {
+    let uluna_bonded: u128 = current_delegations.iter().map(|d| d.amount).sum::<u128>() + uluna_unstaked.u128();
+    if usteak_supply.is_zero() {
+        let usteak_to_lock = cmp::min(uluna_to_bond, Uint128::new(USTEAK_LOCKED_ON_FIRST_BOND));
+        (uluna_to_bond - usteak_to_lock, usteak_to_lock)
+    } else if uluna_bonded == 0 {
+        (uluna_to_bond, Uint128::zero())
     } else {
-        usteak_supply.multiply_ratio(uluna_to_bond, uluna_bonded)
+        (usteak_supply.multiply_ratio(uluna_to_bond, uluna_bonded), Uint128::zero())
     }
 }
 
@@ -34,50 +52,107 @@ pub(crate) fn compute_unbond_amount(
     usteak_supply: Uint128,
     usteak_to_burn: Uint128,
     current_delegations: &[Delegation],
+    uluna_unstaked: Uint128,
 ) -> Uint128 {
-    let uluna_bonded: u128 = current_delegations.iter().map(|d| d.amount).sum();
+    let uluna_bonded: u128 = current_delegations.iter().map(|d| d.amount).sum::<u128>() + uluna_unstaked.u128();
     Uint128::new(uluna_bonded).multiply_ratio(usteak_to_burn, usteak_supply)
 }
 
+/// Compute the amount of `uluna` out of an incoming amount to put into the instant unbonding buffer,
+/// such that the buffer is topped up to its target share of the total amount of `uluna` backing Steak.
+/// `uluna_total` does not include the incoming amount.
+pub(crate) fn compute_buffer_top_up(
+    uluna_incoming: Uint128,
+    uluna_total: Uint128,
+    uluna_buffer: Uint128,
+    target_rate: Decimal,
+) -> Uint128 {
+    let uluna_target = (uluna_total + uluna_incoming) * target_rate;
+    cmp::min(uluna_incoming, uluna_target.saturating_sub(uluna_buffer))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Delegation logics
 //--------------------------------------------------------------------------------------------------
 
-/// Given the current delegations made to validators, and a specific amount of `uluna` to unstake,
-/// compute the undelegations to make such that the delegated amount to each validator is as even
-/// as possible.
+/// Split an amount of `uluna` among validators in proportion to their weights. The remainder from
+/// rounding down is handed out 1 `uluna` at a time, starting from the first validator.
+///
+/// `weights` must be in the same order as the validators the amounts are computed for.
+pub(crate) fn compute_target_amounts(uluna_to_distribute: u128, weights: &[u64]) -> Vec<u128> {
+    let total_weight: u128 = weights.iter().map(|w| *w as u128).sum();
+
+    let mut targets = weights
+        .iter()
+        .map(|w| Uint128::new(uluna_to_distribute).multiply_ratio(*w, total_weight).u128())
+        .collect::<Vec<_>>();
+
+    let remainder = uluna_to_distribute - targets.iter().sum::<u128>();
+    for target in targets.iter_mut().take(remainder as usize) {
+        *target += 1;
+    }
+
+    targets
+}
+
+/// Given the current delegations made to validators and their weights, find the validator whose
+/// delegated amount falls the furthest below its weight-proportional target once `uluna_to_bond`
+/// is added to the total. Ties are broken in favor of the validator that comes first.
+///
+/// With equal weights, this is simply the validator with the smallest delegated amount.
+pub(crate) fn find_validator_to_delegate<'a>(
+    uluna_to_bond: Uint128,
+    current_delegations: &'a [Delegation],
+    weights: &[u64],
+) -> &'a str {
+    let uluna_staked: u128 = current_delegations.iter().map(|d| d.amount).sum();
+    let uluna_total = Uint256::from(uluna_staked + uluna_to_bond.u128());
+    let total_weight = Uint256::from(weights.iter().map(|w| *w as u128).sum::<u128>());
+
+    // The shortfall of validator `i` is `uluna_total * weight_i / total_weight - amount_i`. To avoid
+    // rounding and negative numbers, `i` has a bigger shortfall than `j` if and only if
+    // `uluna_total * weight_i + amount_j * total_weight > uluna_total * weight_j + amount_i * total_weight`
+    let mut best = 0;
+    for i in 1..current_delegations.len() {
+        let lhs = uluna_total * Uint256::from(weights[i])
+            + Uint256::from(current_delegations[best].amount) * total_weight;
+        let rhs = uluna_total * Uint256::from(weights[best])
+            + Uint256::from(current_delegations[i].amount) * total_weight;
+        if lhs > rhs {
+            best = i;
+        }
+    }
+
+    &current_delegations[best].validator
+}
+
+/// Given the current delegations made to validators and their weights, and a specific amount of
+/// `uluna` to unstake, compute the undelegations to make such that the delegated amount to each
+/// validator is as close to its weight-proportional target as possible.
 ///
 /// This function is based on Lido's implementation:
 /// https://github.com/lidofinance/lido-terra-contracts/blob/v1.0.2/contracts/lido_terra_validators_registry/src/common.rs#L55-102
 pub(crate) fn compute_undelegations(
     uluna_to_unbond: Uint128,
     current_delegations: &[Delegation],
+    weights: &[u64],
 ) -> Vec<Undelegation> {
     let uluna_staked: u128 = current_delegations.iter().map(|d| d.amount).sum();
-    let validator_count = current_delegations.len() as u128;
 
     let uluna_to_distribute = uluna_staked - uluna_to_unbond.u128();
-    let uluna_per_validator = uluna_to_distribute / validator_count;
-    let remainder = uluna_to_distribute % validator_count;
+    let targets = compute_target_amounts(uluna_to_distribute, weights);
 
     let mut new_undelegations: Vec<Undelegation> = vec![];
     let mut uluna_available = uluna_to_unbond.u128();
-    for (i, d) in current_delegations.iter().enumerate() {
-        let remainder_for_validator: u128 = if (i + 1) as u128 <= remainder { 1 } else { 0 };
-        let uluna_for_validator = uluna_per_validator + remainder_for_validator;
-
-        let mut uluna_to_undelegate = if d.amount < uluna_for_validator {
-            0
-        } else {
-            d.amount - uluna_for_validator
-        };
+    for (d, uluna_for_validator) in current_delegations.iter().zip(targets) {
+        let mut uluna_to_undelegate = d.amount.saturating_sub(uluna_for_validator);
 
         uluna_to_undelegate = std::cmp::min(uluna_to_undelegate, uluna_available);
         uluna_available -= uluna_to_undelegate;
 
         if uluna_to_undelegate > 0 {
             new_undelegations.push(
-                Undelegation::new(&d.validator, uluna_to_undelegate),
+                Undelegation::new(&d.validator, uluna_to_undelegate, &d.denom),
             );
         }
 
@@ -90,40 +165,37 @@ pub(crate) fn compute_undelegations(
 }
 
 /// Given a validator who is to be removed from the whitelist, and current delegations made to other
-/// validators, compute the new delegations to make such that the delegated amount to each validator
-// is as even as possible.
+/// validators and their weights, compute the new delegations to make such that the delegated amount
+/// to each validator is as close to its weight-proportional target as possible.
 ///
 /// This function is based on Lido's implementation:
 /// https://github.com/lidofinance/lido-terra-contracts/blob/v1.0.2/contracts/lido_terra_validators_registry/src/common.rs#L19-L53
 pub(crate) fn compute_redelegations_for_removal(
     delegation_to_remove: &Delegation,
     current_delegations: &[Delegation],
+    weights: &[u64],
 ) -> Vec<Redelegation> {
     let uluna_staked: u128 = current_delegations.iter().map(|d| d.amount).sum();
-    let validator_count = current_delegations.len() as u128;
 
     let uluna_to_distribute = uluna_staked + delegation_to_remove.amount;
-    let uluna_per_validator = uluna_to_distribute / validator_count;
-    let remainder = uluna_to_distribute % validator_count;
+    let targets = compute_target_amounts(uluna_to_distribute, weights);
 
     let mut new_redelegations: Vec<Redelegation> = vec![];
     let mut uluna_available = delegation_to_remove.amount;
-    for (i, d) in current_delegations.iter().enumerate() {
-        let remainder_for_validator: u128 = if (i + 1) as u128 <= remainder { 1 } else { 0 };
-        let uluna_for_validator = uluna_per_validator + remainder_for_validator;
-
-        let mut uluna_to_redelegate = if d.amount > uluna_for_validator {
-            0
-        } else {
-            uluna_for_validator - d.amount
-        };
+    for (d, uluna_for_validator) in current_delegations.iter().zip(targets) {
+        let mut uluna_to_redelegate = uluna_for_validator.saturating_sub(d.amount);
 
         uluna_to_redelegate = std::cmp::min(uluna_to_redelegate, uluna_available);
         uluna_available -= uluna_to_redelegate;
 
         if uluna_to_redelegate > 0 {
             new_redelegations.push(
-                Redelegation::new(&delegation_to_remove.validator, &d.validator, uluna_to_redelegate),
+                Redelegation::new(
+                    &delegation_to_remove.validator,
+                    &d.validator,
+                    uluna_to_redelegate,
+                    &delegation_to_remove.denom,
+                ),
             );
         }
 
@@ -136,17 +208,15 @@ pub(crate) fn compute_redelegations_for_removal(
 }
 
 /// Compute redelegation moves that will make each validator's delegation the targeted amount (hopefully
-/// this sentence makes sense)
+/// this sentence makes sense), i.e. its weight-proportional share of the total amount staked
 ///
 /// This algorithm does not guarantee the minimal number of moves, but is the best I can some up with...
 pub(crate) fn compute_redelegations_for_rebalancing(
     current_delegations: &[Delegation],
+    weights: &[u64],
 ) -> Vec<Redelegation> {
     let uluna_staked: u128 = current_delegations.iter().map(|d| d.amount).sum();
-    let validator_count = current_delegations.len() as u128;
-
-    let uluna_per_validator = uluna_staked / validator_count;
-    let remainder = uluna_staked % validator_count;
+    let targets = compute_target_amounts(uluna_staked, weights);
 
     // If a validator's current delegated amount is greater than the target amount, Luna will be
     // redelegated _from_ them. They will be put in `src_validators` vector
@@ -154,16 +224,13 @@ pub(crate) fn compute_redelegations_for_rebalancing(
     // redelegated _to_ them. They will be put in `dst_validators` vector
     let mut src_delegations: Vec<Delegation> = vec![];
     let mut dst_delegations: Vec<Delegation> = vec![];
-    for (i, d) in current_delegations.iter().enumerate() {
-        let remainder_for_validator: u128 = if (i + 1) as u128 <= remainder { 1 } else { 0 };
-        let uluna_for_validator = uluna_per_validator + remainder_for_validator;
-
+    for (d, uluna_for_validator) in current_delegations.iter().zip(targets) {
         match d.amount.cmp(&uluna_for_validator) {
             Ordering::Greater => {
-                src_delegations.push(Delegation::new(&d.validator, d.amount - uluna_for_validator));
+                src_delegations.push(Delegation::new(&d.validator, d.amount - uluna_for_validator, &d.denom));
             },
             Ordering::Less => {
-                dst_delegations.push(Delegation::new(&d.validator, uluna_for_validator - d.amount));
+                dst_delegations.push(Delegation::new(&d.validator, uluna_for_validator - d.amount, &d.denom));
             },
             Ordering::Equal => (),
         }
@@ -188,7 +255,12 @@ pub(crate) fn compute_redelegations_for_rebalancing(
         }
 
         new_redelegations.push(
-            Redelegation::new(&src_delegation.validator, &dst_delegation.validator, uluna_to_redelegate),
+            Redelegation::new(
+                &src_delegation.validator,
+                &dst_delegation.validator,
+                uluna_to_redelegate,
+                &src_delegation.denom,
+            ),
         );
     }
 
@@ -200,21 +272,141 @@ pub(crate) fn compute_redelegations_for_rebalancing(
 //--------------------------------------------------------------------------------------------------
 
 /// If the received uluna amount after the unbonding period is less than expected, e.g. due to rounding
-/// error or the validator(s) being slashed, then deduct the difference in amount evenly from each
-/// unreconciled batch.
+/// error or the validator(s) being slashed, then deduct the difference in amount from the unreconciled
+/// batches.
+///
+/// A batch's expected loss is the sum, over the validators it was undelegated from, of the amount
+/// undelegated times the validator's slash ratio. The deduction is split among batches in proportion
+/// to their expected losses, so that a slash only penalizes batches that were unbonding from the
+/// slashed validator. If no batch is exposed to a slashed validator (e.g. the shortfall is purely due
+/// to rounding errors), the deduction is split evenly. A batch's deduction is capped at the amount it
+/// has, with the excess deducted from the other batches.
 ///
 /// The idea of "reconciling" is based on Stader's implementation:
 /// https://github.com/stader-labs/stader-liquid-token/blob/v0.2.1/contracts/staking/src/contract.rs#L968-L1048
-pub(crate) fn reconcile_batches(batches: &mut [Batch], uluna_to_deduct: Uint128) {
+pub(crate) fn reconcile_batches(
+    batches: &mut [Batch],
+    uluna_to_deduct: Uint128,
+    slash_ratios: &[(String, Decimal)],
+) {
+    let expected_losses = batches
+        .iter()
+        .map(|b| {
+            b.undelegations
+                .iter()
+                .map(|(validator, amount)| {
+                    slash_ratios
+                        .iter()
+                        .find(|(v, _)| v == validator)
+                        .map_or(Uint128::zero(), |(_, ratio)| *amount * *ratio)
+                })
+                .sum::<Uint128>()
+        })
+        .collect::<Vec<_>>();
+
+    let total_expected_loss: Uint128 = expected_losses.iter().sum();
+    if total_expected_loss.is_zero() {
+        return reconcile_batches_evenly(batches, uluna_to_deduct);
+    }
+
+    let mut uluna_for_batches = expected_losses
+        .iter()
+        .map(|loss| uluna_to_deduct.multiply_ratio(*loss, total_expected_loss).u128())
+        .collect::<Vec<_>>();
+
+    // The remainder from rounding down is handed out 1 `uluna` at a time to the exposed batches,
+    // starting from the first one
+    let mut remainder = uluna_to_deduct.u128() - uluna_for_batches.iter().sum::<u128>();
+    for (uluna_for_batch, loss) in uluna_for_batches.iter_mut().zip(&expected_losses) {
+        if remainder == 0 {
+            break;
+        }
+        if !loss.is_zero() {
+            *uluna_for_batch += 1;
+            remainder -= 1;
+        }
+    }
+
+    deduct_from_batches(batches, uluna_for_batches);
+}
+
+fn reconcile_batches_evenly(batches: &mut [Batch], uluna_to_deduct: Uint128) {
     let batch_count = batches.len() as u128;
     let uluna_per_batch = uluna_to_deduct.u128() / batch_count;
     let remainder = uluna_to_deduct.u128() % batch_count;
 
-    for (i, batch) in batches.iter_mut().enumerate() {
-        let remainder_for_batch: u128 = if (i + 1) as u128 <= remainder { 1 } else { 0 };
-        let uluna_for_batch = uluna_per_batch + remainder_for_batch;
+    let uluna_for_batches = (0..batches.len())
+        .map(|i| {
+            let remainder_for_batch: u128 = if (i + 1) as u128 <= remainder { 1 } else { 0 };
+            uluna_per_batch + remainder_for_batch
+        })
+        .collect::<Vec<_>>();
+
+    deduct_from_batches(batches, uluna_for_batches);
+}
+
+/// Deduct the given amounts from the batches. A batch cannot lose more than it has; whatever exceeds
+/// it is deducted from the other batches instead, starting from the first one, and whatever exceeds
+/// all batches combined is ignored
+fn deduct_from_batches(batches: &mut [Batch], uluna_for_batches: Vec<u128>) {
+    let mut uluna_excess: u128 = 0;
+    for (batch, uluna_for_batch) in batches.iter_mut().zip(uluna_for_batches) {
+        let uluna_deducted = cmp::min(uluna_for_batch, batch.uluna_unclaimed.u128());
+        batch.uluna_unclaimed -= Uint128::new(uluna_deducted);
+        uluna_excess += uluna_for_batch - uluna_deducted;
+    }
+
+    for batch in batches.iter_mut() {
+        if uluna_excess == 0 {
+            break;
+        }
+        let uluna_deducted = cmp::min(uluna_excess, batch.uluna_unclaimed.u128());
+        batch.uluna_unclaimed -= Uint128::new(uluna_deducted);
+        uluna_excess -= uluna_deducted;
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Exchange rate logics
+//--------------------------------------------------------------------------------------------------
+
+/// Compute the cumulative exchange rate as of a specific time, given the latest snapshot at or before
+/// that time. The exchange rate only changes when a snapshot is recorded, so it is assumed to have
+/// stayed the same since the latest snapshot.
+pub(crate) fn compute_cumulative_exchange_rate(latest: &ExchangeRateSnapshot, time: u64) -> Decimal {
+    latest.cumulative_exchange_rate + latest.exchange_rate * Decimal::from_ratio(time - latest.time, 1u64)
+}
 
-        batch.uluna_unclaimed -= Uint128::new(uluna_for_batch);
-        batch.reconciled = true;
+/// Compute the time-weighted average exchange rate from the time of the `start` snapshot until
+/// `end_time`, given the latest snapshot at or before `end_time`
+pub(crate) fn compute_twap_exchange_rate(
+    start: &ExchangeRateSnapshot,
+    latest: &ExchangeRateSnapshot,
+    end_time: u64,
+) -> Decimal {
+    if end_time <= start.time {
+        return latest.exchange_rate;
     }
+
+    let cumulative_exchange_rate = compute_cumulative_exchange_rate(latest, end_time);
+    (cumulative_exchange_rate - start.cumulative_exchange_rate) / Uint128::from(end_time - start.time)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Yield logics
+//--------------------------------------------------------------------------------------------------
+
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Compute the annualized yield, given the staking rewards accrued over a period of time and the
+/// amount of `uluna` that earned them
+pub(crate) fn compute_apr(uluna_rewards: Uint128, uluna_total: Uint128, period: u64) -> Decimal {
+    if uluna_total.is_zero() || period == 0 {
+        return Decimal::zero();
+    }
+
+    Decimal::from_ratio(
+        uluna_rewards * Uint128::from(SECONDS_PER_YEAR),
+        uluna_total * Uint128::from(period),
+    )
 }
@@ -0,0 +1,154 @@
+use cosmwasm_std::{Decimal, DepsMut, Event, Response, Storage};
+use cw2::{ContractVersion, CONTRACT};
+use cw_storage_plus::Item;
+use semver::Version;
+
+use steak::hub::{MigrateMsg, MigrateStep};
+
+use crate::error::ContractError;
+use crate::execute::{
+    assert_valid_fee_rate, CONTRACT_NAME, CONTRACT_VERSION, DEFAULT_VALIDATOR_WEIGHT, MIN_KEEPER_COOLDOWN,
+};
+use crate::state::State;
+
+/// Steak Hub v2.0.0 did not store its contract version, so a contract without cw2 info but with the
+/// v2.0.0 storage layout is assumed to be this version
+const LEGACY_CONTRACT_VERSION: &str = "2.0.0";
+
+/// Storage upgrades without which a v2.0.0 contract cannot load its state after migrating
+const LEGACY_REQUIRED_STEPS: [&str; 2] = ["init_denom_and_fee", "init_validator_weights"];
+
+pub fn migrate(mut deps: DepsMut, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous = load_contract_version(deps.as_ref().storage)?;
+
+    if previous.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidContract {
+            contract: previous.contract,
+        });
+    }
+
+    let previous_version = parse_version(&previous.version)?;
+    let new_version = parse_version(CONTRACT_VERSION)?;
+    if previous_version > new_version {
+        return Err(ContractError::Downgrade {
+            previous_version: previous_version.to_string(),
+            new_version: new_version.to_string(),
+        });
+    }
+
+    if previous.version == LEGACY_CONTRACT_VERSION {
+        let steps = msg.steps.iter().map(step_name).collect::<Vec<_>>();
+        if let Some(step) = LEGACY_REQUIRED_STEPS.iter().find(|step| !steps.contains(step)) {
+            return Err(ContractError::StepMissing {
+                step: step.to_string(),
+            });
+        }
+    }
+
+    let mut steps: Vec<&str> = vec![];
+    for step in msg.steps {
+        steps.push(step_name(&step));
+        match step {
+            MigrateStep::InitDenomAndFee {
+                denom,
+                fee_account,
+                fee_rate,
+            } => init_denom_and_fee(deps.branch(), denom, fee_account, fee_rate)?,
+            MigrateStep::InitValidatorWeights {} => init_validator_weights(deps.branch())?,
+        }
+    }
+
+    // Versions before the minimum was enforced may have stored a shorter keeper cooldown
+    let state = State::default();
+    let keeper_cooldown = state.keeper_cooldown.may_load(deps.storage)?.unwrap_or_default();
+    if keeper_cooldown < MIN_KEEPER_COOLDOWN {
+        state.keeper_cooldown.save(deps.storage, &MIN_KEEPER_COOLDOWN)?;
+    }
+
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let event = Event::new("steakhub/migrated")
+        .add_attribute("previous_version", previous_version.to_string())
+        .add_attribute("new_version", new_version.to_string())
+        .add_attribute("steps", steps.join(","));
+
+    Ok(Response::new()
+        .add_event(event)
+        .add_attribute("action", "steakhub/migrate"))
+}
+
+fn load_contract_version(storage: &dyn Storage) -> Result<ContractVersion, ContractError> {
+    if let Some(contract_version) = CONTRACT.may_load(storage)? {
+        return Ok(contract_version);
+    }
+
+    let state = State::default();
+    if state.steak_token.may_load(storage)?.is_none() || state.pending_batch.may_load(storage)?.is_none() {
+        return Err(ContractError::UnknownContract {});
+    }
+
+    Ok(ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: LEGACY_CONTRACT_VERSION.to_string(),
+    })
+}
+
+fn parse_version(version: &str) -> Result<Version, ContractError> {
+    Version::parse(version).map_err(|_| ContractError::InvalidVersion {
+        version: version.to_string(),
+    })
+}
+
+fn step_name(step: &MigrateStep) -> &'static str {
+    match step {
+        MigrateStep::InitDenomAndFee { .. } => "init_denom_and_fee",
+        MigrateStep::InitValidatorWeights {} => "init_validator_weights",
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Storage upgrades
+//--------------------------------------------------------------------------------------------------
+
+/// v2.0.0 -> v2.1.0: the bond denom used to be hard-coded as `uluna`, and there was no protocol fee
+fn init_denom_and_fee(
+    deps: DepsMut,
+    denom: String,
+    fee_account: String,
+    fee_rate: Decimal,
+) -> Result<(), ContractError> {
+    let state = State::default();
+
+    if state.denom.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::StepAlreadyApplied {
+            step: "init_denom_and_fee".to_string(),
+        });
+    }
+
+    assert_valid_fee_rate(fee_rate)?;
+
+    state.denom.save(deps.storage, &denom)?;
+    state.fee_account.save(deps.storage, &deps.api.addr_validate(&fee_account)?)?;
+    state.fee_rate.save(deps.storage, &fee_rate)?;
+
+    Ok(())
+}
+
+/// v2.0.0 -> v2.1.0: validators used to be stored as a plain list of addresses, all receiving an
+/// equal share of the delegations
+fn init_validator_weights(deps: DepsMut) -> Result<(), ContractError> {
+    let state = State::default();
+    let legacy_validators: Item<Vec<String>> = Item::new("validators");
+
+    // Once upgraded, the validators can no longer be parsed as a list of strings
+    let validators = legacy_validators.load(deps.storage).map_err(|_| ContractError::StepAlreadyApplied {
+        step: "init_validator_weights".to_string(),
+    })?;
+
+    state.validators.save(
+        deps.storage,
+        &validators.into_iter().map(|v| (v, DEFAULT_VALIDATOR_WEIGHT)).collect(),
+    )?;
+
+    Ok(())
+}
@@ -1,26 +1,48 @@
-use cosmwasm_std::{Addr, Decimal, Deps, Env, Order, StdResult, Uint128};
+use cosmwasm_std::{Decimal, Deps, Env, Order, StdError, StdResult, Uint128};
 use cw_storage_plus::{Bound, CwIntKey};
 
 use steak::hub::{
-    Batch, ConfigResponse, PendingBatch, StateResponse, UnbondRequestsByBatchResponseItem,
-    UnbondRequestsByUserResponseItem,
+    AprResponse, Batch, BufferResponse, ConfigResponse, ExchangeRateSnapshot, HarvestRecord,
+    InsuranceDraw, InsuranceResponse, KeeperRewardResponse, PendingBatch, SimulateBondResponse, SimulateUnbondResponse, StateResponse,
+    SwapRoute, TwapExchangeRateResponse, UnbondRequest, UnbondRequestsByBatchResponseItem,
+    UnbondRequestsByUserResponseItem, UnbondStatus, UnbondingSummaryItem, UnbondingSummaryResponse,
 };
 
-use crate::helpers::{query_cw20_total_supply, query_delegations};
+use crate::error::ContractError;
+use crate::execute::{prepare_bond, MIN_KEEPER_COOLDOWN};
+use crate::helpers::{
+    parse_unbond_nft_token_id, query_cw20_total_supply, query_delegations, query_unbond_nft_tokens,
+};
+use crate::math::{compute_apr, compute_twap_exchange_rate, compute_unbond_amount};
 use crate::state::State;
 
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
 
+/// Periods over which the realized yield is computed, in seconds
+const APR_PERIOD_7D: u64 = 7 * 24 * 60 * 60;
+const APR_PERIOD_30D: u64 = 30 * 24 * 60 * 60;
+
 pub fn config(deps: Deps) -> StdResult<ConfigResponse> {
     let state = State::default();
     Ok(ConfigResponse {
         owner: state.owner.load(deps.storage)?.into(),
         new_owner: state.new_owner.may_load(deps.storage)?.map(|addr| addr.into()),
         steak_token: state.steak_token.load(deps.storage)?.into(),
+        unbond_nft: state.unbond_nft.may_load(deps.storage)?.map(|addr| addr.into()),
+        denom: state.denom.load(deps.storage)?,
         epoch_period: state.epoch_period.load(deps.storage)?,
         unbond_period: state.unbond_period.load(deps.storage)?,
         validators: state.validators.load(deps.storage)?,
+        fee_account: state.fee_account.load(deps.storage)?.into(),
+        fee_rate: state.fee_rate.load(deps.storage)?,
+        netting: state.netting.may_load(deps.storage)?.unwrap_or_default(),
+        min_bond: state.min_bond.may_load(deps.storage)?.unwrap_or_default(),
+        min_unbond: state.min_unbond.may_load(deps.storage)?.unwrap_or_default(),
+        max_total_bonded: state.max_total_bonded.may_load(deps.storage)?,
+        max_bonded_per_address: state.max_bonded_per_address.may_load(deps.storage)?,
+        guardian: state.guardian.may_load(deps.storage)?.map(|addr| addr.into()),
+        pause_flags: state.pause_flags.may_load(deps.storage)?.unwrap_or_default(),
     })
 }
 
@@ -29,10 +51,7 @@ pub fn state(deps: Deps, env: Env) -> StdResult<StateResponse> {
 
     let steak_token = state.steak_token.load(deps.storage)?;
     let total_usteak = query_cw20_total_supply(&deps.querier, &steak_token)?;
-
-    let validators = state.validators.load(deps.storage)?;
-    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address)?;
-    let total_uluna: u128 = delegations.iter().map(|d| d.amount).sum();
+    let total_uluna = query_total_uluna(deps, &env)?.u128();
 
     let exchange_rate = if total_usteak.is_zero() {
         Decimal::one()
@@ -45,9 +64,30 @@ pub fn state(deps: Deps, env: Env) -> StdResult<StateResponse> {
         total_uluna: Uint128::new(total_uluna),
         exchange_rate,
         unlocked_coins: state.unlocked_coins.load(deps.storage)?,
+        bond_headroom: state
+            .max_total_bonded
+            .may_load(deps.storage)?
+            .map(|max_total_bonded| max_total_bonded.saturating_sub(Uint128::new(total_uluna))),
     })
 }
 
+/// Total amount of `uluna` backing Steak, i.e. delegated and unstaked
+fn query_total_uluna(deps: Deps, env: &Env) -> StdResult<Uint128> {
+    let state = State::default();
+
+    let denom = state.denom.load(deps.storage)?;
+    let validators = state
+        .validators
+        .load(deps.storage)?
+        .into_iter()
+        .map(|(validator, _)| validator)
+        .collect::<Vec<_>>();
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let uluna_unstaked = state.load_uluna_unstaked(deps.storage)?;
+
+    Ok(Uint128::new(delegations.iter().map(|d| d.amount).sum()) + uluna_unstaked)
+}
+
 pub fn pending_batch(deps: Deps) -> StdResult<PendingBatch> {
     let state = State::default();
     state.pending_batch.load(deps.storage)
@@ -83,16 +123,17 @@ pub fn unbond_requests_by_batch(
     deps: Deps,
     id: u64,
     start_after: Option<String>,
+    start_after_seq: Option<u64>,
     limit: Option<u32>,
 ) -> StdResult<Vec<UnbondRequestsByBatchResponseItem>> {
     let state = State::default();
 
-    let addr: Addr;
+    let key: String;
     let start = match start_after {
         None => None,
         Some(addr_str) => {
-            addr = deps.api.addr_validate(&addr_str)?;
-            Some(Bound::exclusive(&addr))
+            key = UnbondRequest::key_of(&deps.api.addr_validate(&addr_str)?, start_after_seq.unwrap_or_default());
+            Some(Bound::exclusive(key.as_str()))
         },
     };
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
@@ -137,3 +178,326 @@ pub fn unbond_requests_by_user(
         })
         .collect()
 }
+
+/// NOTE: The statuses and amounts follow the same rules as `execute::withdraw_unbonded`, such that
+/// `total_withdrawable` is exactly what the user would receive if they withdraw now. This includes
+/// summarizing the requests whose NFTs the user owns if unbond NFTs are enabled.
+pub fn unbonding_summary(deps: Deps, env: Env, user: String) -> StdResult<UnbondingSummaryResponse> {
+    let state = State::default();
+    let current_time = env.block.time.seconds();
+    let pending_batch = state.pending_batch.load(deps.storage)?;
+    let user = deps.api.addr_validate(&user)?;
+
+    let requests = match state.unbond_nft.may_load(deps.storage)? {
+        Some(unbond_nft) => query_unbond_nft_tokens(&deps.querier, &unbond_nft, &user)?
+            .iter()
+            .map(|token_id| {
+                let (id, key) = parse_unbond_nft_token_id(token_id)
+                    .map_err(|err| StdError::generic_err(err.to_string()))?;
+                state.unbond_requests.load(deps.storage, (id, &key))
+            })
+            .collect::<StdResult<Vec<_>>>()?,
+        None => state
+            .unbond_requests
+            .idx
+            .user
+            .prefix(user.into())
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                let (_, v) = item?;
+                Ok(v)
+            })
+            .collect::<StdResult<Vec<_>>>()?,
+    };
+
+    let mut items: Vec<UnbondingSummaryItem> = vec![];
+    let mut total_withdrawable = Uint128::zero();
+    for request in requests {
+        if request.id == pending_batch.id {
+            let simulation = estimate_unbond(deps, env.clone(), request.shares)?;
+            items.push(UnbondingSummaryItem {
+                id: request.id,
+                shares: request.shares,
+                status: UnbondStatus::Pending,
+                est_uluna: simulation.uluna_to_unbond,
+                est_unbond_end_time: simulation.est_unbond_end_time,
+            });
+            continue;
+        }
+
+        let batch = match state.previous_batches.may_load(deps.storage, request.id)? {
+            Some(batch) => batch,
+            None => continue,
+        };
+
+        let status = if batch.reconciled && batch.est_unbond_end_time <= current_time {
+            UnbondStatus::Claimable
+        } else if batch.est_unbond_end_time >= current_time {
+            UnbondStatus::Unbonding
+        } else {
+            UnbondStatus::Unreconciled
+        };
+
+        // The request's part of the Luna held by the contract can be withdrawn regardless of the status
+        let uluna_held = if request.held_claimed || batch.held_shares.is_zero() {
+            Uint128::zero()
+        } else {
+            batch.uluna_held.multiply_ratio(request.shares, batch.held_shares)
+        };
+        let uluna_unbonding = batch.uluna_unclaimed.multiply_ratio(request.shares, batch.total_shares);
+        let est_uluna = uluna_held + uluna_unbonding;
+        total_withdrawable += if status == UnbondStatus::Claimable {
+            est_uluna
+        } else {
+            uluna_held
+        };
+
+        items.push(UnbondingSummaryItem {
+            id: request.id,
+            shares: request.shares,
+            status,
+            est_uluna,
+            est_unbond_end_time: batch.est_unbond_end_time,
+        });
+    }
+
+    Ok(UnbondingSummaryResponse {
+        requests: items,
+        total_withdrawable,
+    })
+}
+
+pub fn insurance(deps: Deps) -> StdResult<InsuranceResponse> {
+    let state = State::default();
+    Ok(InsuranceResponse {
+        balance: state.insurance_reserve.may_load(deps.storage)?.unwrap_or_default(),
+        insurance_rate: state.insurance_rate.may_load(deps.storage)?.unwrap_or_default(),
+    })
+}
+
+pub fn keeper_reward(deps: Deps) -> StdResult<KeeperRewardResponse> {
+    let state = State::default();
+    Ok(KeeperRewardResponse {
+        balance: state.keeper_pool.may_load(deps.storage)?.unwrap_or_default(),
+        rate: state.keeper_rate.may_load(deps.storage)?.unwrap_or_default(),
+        amount: state.keeper_reward.may_load(deps.storage)?.unwrap_or_default(),
+        cooldown: state.keeper_cooldown.may_load(deps.storage)?.unwrap_or(MIN_KEEPER_COOLDOWN),
+    })
+}
+
+pub fn buffer(deps: Deps) -> StdResult<BufferResponse> {
+    let state = State::default();
+    Ok(BufferResponse {
+        balance: state.buffer.may_load(deps.storage)?.unwrap_or_default(),
+        target_rate: state.buffer_target_rate.may_load(deps.storage)?.unwrap_or_default(),
+        fee_rate: state.instant_unbond_fee_rate.may_load(deps.storage)?.unwrap_or_default(),
+    })
+}
+
+pub fn exchange_rates(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<ExchangeRateSnapshot>> {
+    let state = State::default();
+
+    let start = start_after.map(Bound::exclusive);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    state
+        .exchange_rates
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+pub fn twap_exchange_rate(deps: Deps, env: Env, window_seconds: u64) -> StdResult<TwapExchangeRateResponse> {
+    let state = State::default();
+    let current_time = env.block.time.seconds();
+    let window_start = current_time.saturating_sub(window_seconds);
+
+    let latest = state
+        .exchange_rates
+        .range(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map(|(_, v)| v)
+        .ok_or_else(|| StdError::generic_err("no exchange rate snapshot has been recorded"))?;
+
+    // The latest snapshot at or before the start of the window; if none, the earliest snapshot
+    let start = match state
+        .exchange_rates
+        .range(deps.storage, None, Some(Bound::inclusive(window_start)), Order::Descending)
+        .next()
+        .transpose()?
+    {
+        Some((_, v)) => v,
+        None => state
+            .exchange_rates
+            .range(deps.storage, None, None, Order::Ascending)
+            .next()
+            .transpose()?
+            .map(|(_, v)| v)
+            .unwrap_or_else(|| latest.clone()),
+    };
+
+    Ok(TwapExchangeRateResponse {
+        exchange_rate: compute_twap_exchange_rate(&start, &latest, current_time),
+        start_time: start.time,
+        end_time: current_time,
+    })
+}
+
+pub fn harvests(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<HarvestRecord>> {
+    let state = State::default();
+
+    let start = start_after.map(Bound::exclusive);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    state
+        .harvests
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+/// NOTE: The yield is computed against the current amount of `uluna` backing Steak, rather than the
+/// amount at the time of each harvest
+pub fn apr(deps: Deps, env: Env) -> StdResult<AprResponse> {
+    let state = State::default();
+    let current_time = env.block.time.seconds();
+    let total_uluna = query_total_uluna(deps, &env)?;
+
+    let uluna_bonded_since = |period: u64| -> StdResult<Uint128> {
+        let start = Bound::inclusive(current_time.saturating_sub(period));
+        state
+            .harvests
+            .range(deps.storage, Some(start), None, Order::Ascending)
+            .map(|item| {
+                let (_, v) = item?;
+                Ok(v.uluna_bonded)
+            })
+            .sum()
+    };
+
+    Ok(AprResponse {
+        apr_7d: compute_apr(uluna_bonded_since(APR_PERIOD_7D)?, total_uluna, APR_PERIOD_7D),
+        apr_30d: compute_apr(uluna_bonded_since(APR_PERIOD_30D)?, total_uluna, APR_PERIOD_30D),
+    })
+}
+
+pub fn insurance_draws(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<InsuranceDraw>> {
+    let state = State::default();
+
+    let start = start_after.map(Bound::exclusive);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    state
+        .insurance_draws
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+pub fn swap_routes(deps: Deps) -> StdResult<Vec<SwapRoute>> {
+    let state = State::default();
+    state
+        .swap_routes
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (_, v) = item?;
+            Ok(v)
+        })
+        .collect()
+}
+
+/// Follows the same steps as `execute::bond`, through `execute::prepare_bond`. A bond that would be
+/// rejected returns the same error
+pub fn simulate_bond(
+    deps: Deps,
+    env: Env,
+    uluna_to_bond: Uint128,
+    receiver: Option<String>,
+) -> StdResult<SimulateBondResponse> {
+    let receiver = receiver.map(|receiver| deps.api.addr_validate(&receiver)).transpose()?;
+
+    let prepared = prepare_bond(deps, &env, receiver.as_ref(), uluna_to_bond)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+
+    Ok(SimulateBondResponse {
+        usteak_to_mint: prepared.usteak_to_mint,
+        validator: prepared.validator,
+    })
+}
+
+/// Follows the same steps as `execute::queue_unbond` and `execute::submit_batch`, assuming the
+/// exchange rate does not change until the batch is submitted
+pub fn simulate_unbond(deps: Deps, env: Env, usteak_to_burn: Uint128) -> StdResult<SimulateUnbondResponse> {
+    let state = State::default();
+    let min_unbond = state.min_unbond.may_load(deps.storage)?.unwrap_or_default();
+    if usteak_to_burn < min_unbond {
+        return Err(StdError::generic_err(
+            ContractError::UnbondTooSmall {
+                min_unbond,
+            }
+            .to_string(),
+        ));
+    }
+
+    estimate_unbond(deps, env, usteak_to_burn)
+}
+
+/// Estimate the amount of `uluna` an unbonding request in the pending batch will receive. Unlike
+/// `simulate_unbond`, this does not check `min_unbond`, which may have been raised since the request
+/// was queued
+fn estimate_unbond(deps: Deps, env: Env, usteak_to_burn: Uint128) -> StdResult<SimulateUnbondResponse> {
+    let state = State::default();
+    let denom = state.denom.load(deps.storage)?;
+    let steak_token = state.steak_token.load(deps.storage)?;
+    let unbond_period = state.unbond_period.load(deps.storage)?;
+    let pending_batch = state.pending_batch.load(deps.storage)?;
+    let validators = state
+        .validators
+        .load(deps.storage)?
+        .into_iter()
+        .map(|(validator, _)| validator)
+        .collect::<Vec<_>>();
+
+    let usteak_supply = query_cw20_total_supply(&deps.querier, &steak_token)?;
+    if usteak_to_burn > usteak_supply || usteak_supply.is_zero() {
+        return Err(StdError::generic_err("usteak amount exceeds total supply"));
+    }
+
+    let delegations = query_delegations(&deps.querier, &validators, &env.contract.address, &denom)?;
+    let uluna_unstaked = state.load_uluna_unstaked(deps.storage)?;
+    let uluna_to_unbond = compute_unbond_amount(usteak_supply, usteak_to_burn, &delegations, uluna_unstaked);
+
+    // If the pending batch is already due, queuing the request submits the batch right away
+    let unbond_start_time = pending_batch.est_unbond_start_time.max(env.block.time.seconds());
+
+    Ok(SimulateUnbondResponse {
+        uluna_to_unbond,
+        batch_id: pending_batch.id,
+        est_unbond_end_time: unbond_start_time + unbond_period,
+    })
+}
@@ -1,8 +1,9 @@
-use cosmwasm_std::{Addr, Coin, StdError, StdResult, Storage};
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, MultiIndex};
+use cosmwasm_std::{Addr, Coin, Decimal, StdResult, Storage, Uint128};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 
-use steak::hub::{Batch, PendingBatch, UnbondRequest};
+use steak::hub::{Batch, ExchangeRateSnapshot, HarvestRecord, InsuranceDraw, PauseFlags, PendingBatch, SwapRoute, UnbondRequest};
 
+use crate::error::ContractError;
 use crate::types::BooleanKey;
 
 pub(crate) struct State<'a> {
@@ -12,20 +13,75 @@ pub(crate) struct State<'a> {
     pub new_owner: Item<'a, Addr>,
     /// Address of the Steak token
     pub steak_token: Item<'a, Addr>,
+    /// Address of the NFT contract representing unbonding requests; not set if disabled
+    pub unbond_nft: Item<'a, Addr>,
+    /// Account who can pause, but not unpause, actions
+    pub guardian: Item<'a, Addr>,
+    /// Actions that are currently paused; none if not set
+    pub pause_flags: Item<'a, PauseFlags>,
+    /// Denomination of the coin to be staked
+    pub denom: Item<'a, String>,
     /// How often the unbonding queue is to be executed
     pub epoch_period: Item<'a, u64>,
     /// The staking module's unbonding time, in seconds
     pub unbond_period: Item<'a, u64>,
-    /// Validators who will receive the delegations
-    pub validators: Item<'a, Vec<String>>,
+    /// Whether bonds are held until the pending batch is submitted, to be netted against unbonds;
+    /// false if not set
+    pub netting: Item<'a, bool>,
+    /// Minimum amount of `uluna` to be bonded at a time; zero if not set
+    pub min_bond: Item<'a, Uint128>,
+    /// Minimum amount of `usteak` to be queued for unbonding at a time; zero if not set
+    pub min_unbond: Item<'a, Uint128>,
+    /// Maximum amount of `uluna` backing Steak; uncapped if not set
+    pub max_total_bonded: Item<'a, Uint128>,
+    /// Maximum amount of `uluna` that the Steak held by a single address may be worth; uncapped if not set
+    pub max_bonded_per_address: Item<'a, Uint128>,
+    /// Validators who will receive the delegations, and their weights
+    pub validators: Item<'a, Vec<(String, u64)>>,
+    /// Amount of `uluna` the contract expects to have delegated to each validator, used to detect
+    /// which validators have been slashed
+    pub expected_delegations: Map<'a, &'a str, Uint128>,
+    /// Account to receive the protocol fee
+    pub fee_account: Item<'a, Addr>,
+    /// Fraction of harvested staking rewards to be charged as protocol fee
+    pub fee_rate: Item<'a, Decimal>,
+    /// Fraction of harvested staking rewards to be put into the insurance reserve; zero if not set
+    pub insurance_rate: Item<'a, Decimal>,
+    /// Amount of `uluna` held by the contract as the insurance reserve; zero if not set
+    pub insurance_reserve: Item<'a, Uint128>,
+    /// Past draws from the insurance reserve, indexed by ID
+    pub insurance_draws: Map<'a, u64, InsuranceDraw>,
+    /// Amount of `uluna` held by the contract to pay out instant unbonds; zero if not set
+    pub buffer: Item<'a, Uint128>,
+    /// The buffer's target, as a fraction of the total amount of `uluna` backing Steak; zero if not set
+    pub buffer_target_rate: Item<'a, Decimal>,
+    /// Fraction of the unbonded amount to be charged as fee on instant unbonding; zero if not set
+    pub instant_unbond_fee_rate: Item<'a, Decimal>,
+    /// Fraction of harvested staking rewards to be put into the keeper pool; zero if not set
+    pub keeper_rate: Item<'a, Decimal>,
+    /// Amount of `uluna` paid to whoever invokes a maintenance action; zero if not set
+    pub keeper_reward: Item<'a, Uint128>,
+    /// Minimum number of seconds between two rewarded invocations of the same action;
+    /// `MIN_KEEPER_COOLDOWN` if not set
+    pub keeper_cooldown: Item<'a, u64>,
+    /// Amount of `uluna` held by the contract to pay out keeper rewards; zero if not set
+    pub keeper_pool: Item<'a, Uint128>,
+    /// Time when each maintenance action was last rewarded, indexed by action name
+    pub keeper_last_rewarded: Map<'a, &'a str, u64>,
+    /// Routes along which staking rewards are swapped for the bond denom, indexed by reward denom
+    pub swap_routes: Map<'a, &'a str, SwapRoute>,
     /// Coins that can be reinvested
     pub unlocked_coins: Item<'a, Vec<Coin>>,
     /// The current batch of unbonding requests queded to be executed
     pub pending_batch: Item<'a, PendingBatch>,
     /// Previous batches that have started unbonding but not yet finished
     pub previous_batches: IndexedMap<'a, u64, Batch, PreviousBatchesIndexes<'a>>,
-    /// Users' shares in unbonding batches
-    pub unbond_requests: IndexedMap<'a, (u64, &'a Addr), UnbondRequest, UnbondRequestsIndexes<'a>>,
+    /// Snapshots of the exchange rate, indexed by the time when they were recorded
+    pub exchange_rates: Map<'a, u64, ExchangeRateSnapshot>,
+    /// Staking rewards harvested, aggregated by the hour, indexed by the start of the hour
+    pub harvests: Map<'a, u64, HarvestRecord>,
+    /// Users' shares in unbonding batches, indexed by batch ID and `UnbondRequest::key`
+    pub unbond_requests: IndexedMap<'a, (u64, &'a str), UnbondRequest, UnbondRequestsIndexes<'a>>,
 }
 
 impl Default for State<'static> {
@@ -48,24 +104,74 @@ impl Default for State<'static> {
             owner: Item::new("owner"),
             new_owner: Item::new("new_owner"),
             steak_token: Item::new("steak_token"),
+            unbond_nft: Item::new("unbond_nft"),
+            guardian: Item::new("guardian"),
+            pause_flags: Item::new("pause_flags"),
+            denom: Item::new("denom"),
             epoch_period: Item::new("epoch_period"),
             unbond_period: Item::new("unbond_period"),
+            netting: Item::new("netting"),
+            min_bond: Item::new("min_bond"),
+            min_unbond: Item::new("min_unbond"),
+            max_total_bonded: Item::new("max_total_bonded"),
+            max_bonded_per_address: Item::new("max_bonded_per_address"),
             validators: Item::new("validators"),
+            expected_delegations: Map::new("expected_delegations"),
+            fee_account: Item::new("fee_account"),
+            fee_rate: Item::new("fee_rate"),
+            insurance_rate: Item::new("insurance_rate"),
+            insurance_reserve: Item::new("insurance_reserve"),
+            insurance_draws: Map::new("insurance_draws"),
+            buffer: Item::new("buffer"),
+            buffer_target_rate: Item::new("buffer_target_rate"),
+            instant_unbond_fee_rate: Item::new("instant_unbond_fee_rate"),
+            keeper_rate: Item::new("keeper_rate"),
+            keeper_reward: Item::new("keeper_reward"),
+            keeper_cooldown: Item::new("keeper_cooldown"),
+            keeper_pool: Item::new("keeper_pool"),
+            keeper_last_rewarded: Map::new("keeper_last_rewarded"),
+            swap_routes: Map::new("swap_routes"),
             unlocked_coins: Item::new("unlocked_coins"),
             pending_batch: Item::new("pending_batch"),
             previous_batches: IndexedMap::new("previous_batches", pb_indexes),
+            exchange_rates: Map::new("exchange_rates"),
+            harvests: Map::new("harvests"),
             unbond_requests: IndexedMap::new("unbond_requests", ubr_indexes),
         }
     }
 }
 
 impl<'a> State<'a> {
-    pub fn assert_owner(&self, storage: &dyn Storage, sender: &Addr) -> StdResult<()> {
+    pub fn assert_owner(&self, storage: &dyn Storage, sender: &Addr) -> Result<(), ContractError> {
         let owner = self.owner.load(storage)?;
         if *sender == owner {
             Ok(())
         } else {
-            Err(StdError::generic_err("unauthorized: sender is not owner"))
+            Err(ContractError::Unauthorized {})
+        }
+    }
+
+    /// Amount of `uluna` held by the contract that backs Steak without being delegated, i.e. bonds held
+    /// to be netted against unbonds, and the instant unbonding buffer
+    pub fn load_uluna_unstaked(&self, storage: &dyn Storage) -> StdResult<Uint128> {
+        let uluna_held = self.pending_batch.load(storage)?.uluna_to_bond;
+        let uluna_buffer = self.buffer.may_load(storage)?.unwrap_or_default();
+        Ok(uluna_held + uluna_buffer)
+    }
+
+    pub fn assert_not_paused(
+        &self,
+        storage: &dyn Storage,
+        action: &str,
+        is_paused: fn(&PauseFlags) -> bool,
+    ) -> Result<(), ContractError> {
+        let pause_flags = self.pause_flags.may_load(storage)?.unwrap_or_default();
+        if is_paused(&pause_flags) {
+            Err(ContractError::Paused {
+                action: action.to_string(),
+            })
+        } else {
+            Ok(())
         }
     }
 }
@@ -2,21 +2,27 @@ use std::collections::HashMap;
 
 use cosmwasm_std::testing::{BankQuerier, StakingQuerier, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_binary, from_slice, Addr, Coin, Empty, FullDelegation, Querier, QuerierResult,
+    from_binary, from_slice, Addr, Coin, Decimal, Empty, FullDelegation, Querier, QuerierResult,
     QueryRequest, SystemError, WasmQuery,
 };
 use cw20::Cw20QueryMsg;
+use cw721::Cw721QueryMsg;
+use steak::router::QueryMsg as RouterQueryMsg;
 
 use crate::types::Delegation;
 
 use super::cw20_querier::Cw20Querier;
+use super::cw721_querier::Cw721Querier;
 use super::helpers::err_unsupported_query;
+use super::router_querier::RouterQuerier;
 
 #[derive(Default)]
 pub(super) struct CustomQuerier {
     pub cw20_querier: Cw20Querier,
+    pub cw721_querier: Cw721Querier,
     pub bank_querier: BankQuerier,
     pub staking_querier: StakingQuerier,
+    pub router_querier: RouterQuerier,
 }
 
 impl Querier for CustomQuerier {
@@ -56,6 +62,14 @@ impl CustomQuerier {
             .insert(token.to_string(), total_supply);
     }
 
+    pub fn set_cw721_owner(&mut self, token_id: &str, owner: &str) {
+        self.cw721_querier.owners.insert(token_id.to_string(), owner.to_string());
+    }
+
+    pub fn remove_cw721_token(&mut self, token_id: &str) {
+        self.cw721_querier.owners.remove(token_id);
+    }
+
     pub fn set_bank_balances(&mut self, balances: &[Coin]) {
         self.bank_querier = BankQuerier::new(&[(MOCK_CONTRACT_ADDR, balances)]);
     }
@@ -66,8 +80,8 @@ impl CustomQuerier {
             .map(|d| FullDelegation {
                 delegator: Addr::unchecked(MOCK_CONTRACT_ADDR),
                 validator: d.validator.clone(),
-                amount: Coin::new(d.amount, "uluna"),
-                can_redelegate: Coin::new(0, "uluna"),
+                amount: Coin::new(d.amount, &d.denom),
+                can_redelegate: Coin::new(0, &d.denom),
                 accumulated_rewards: vec![],
             })
             .collect::<Vec<_>>();
@@ -75,6 +89,10 @@ impl CustomQuerier {
         self.staking_querier = StakingQuerier::new("uluna", &[], &fds);
     }
 
+    pub fn set_swap_rate(&mut self, denom: &str, rate: Decimal) {
+        self.router_querier.rates.insert(denom.to_string(), rate);
+    }
+
     pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
         match request {
             QueryRequest::Wasm(WasmQuery::Smart {
@@ -82,7 +100,15 @@ impl CustomQuerier {
                 msg,
             }) => {
                 if let Ok(query) = from_binary::<Cw20QueryMsg>(msg) {
-                    return self.cw20_querier.handle_query(&contract_addr, query);
+                    return self.cw20_querier.handle_query(contract_addr, query);
+                }
+
+                if let Ok(query) = from_binary::<Cw721QueryMsg>(msg) {
+                    return self.cw721_querier.handle_query(query);
+                }
+
+                if let Ok(query) = from_binary::<RouterQueryMsg>(msg) {
+                    return self.router_querier.handle_query(query);
                 }
 
                 err_unsupported_query(msg)
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{to_binary, QuerierResult, SystemError};
+use cw721::{Cw721QueryMsg, OwnerOfResponse, TokensResponse};
+
+use super::helpers::err_unsupported_query;
+
+#[derive(Default)]
+pub(super) struct Cw721Querier {
+    /// Mapping token ID to the address of its owner
+    pub owners: BTreeMap<String, String>,
+}
+
+impl Cw721Querier {
+    pub fn handle_query(&self, query: Cw721QueryMsg) -> QuerierResult {
+        match &query {
+            Cw721QueryMsg::OwnerOf {
+                token_id,
+                ..
+            } => {
+                let owner = self
+                    .owners
+                    .get(token_id)
+                    .ok_or_else(|| SystemError::InvalidRequest {
+                        error: format!("[mock] owner not set for token `{}`", token_id),
+                        request: Default::default(),
+                    })
+                    .unwrap();
+
+                Ok(to_binary(&OwnerOfResponse {
+                    owner: owner.clone(),
+                    approvals: vec![],
+                })
+                .into())
+                .into()
+            },
+
+            Cw721QueryMsg::Tokens {
+                owner,
+                start_after,
+                limit,
+            } => {
+                let tokens = self
+                    .owners
+                    .iter()
+                    .filter(|(token_id, token_owner)| {
+                        *token_owner == owner && start_after.as_ref().is_none_or(|s| *token_id > s)
+                    })
+                    .map(|(token_id, _)| token_id.clone())
+                    .take(limit.unwrap_or(10) as usize)
+                    .collect();
+
+                Ok(to_binary(&TokensResponse {
+                    tokens,
+                })
+                .into())
+                .into()
+            },
+
+            other_query => err_unsupported_query(other_query),
+        }
+    }
+}
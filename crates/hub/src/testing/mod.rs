@@ -1,4 +1,6 @@
 mod custom_querier;
 mod cw20_querier;
+mod cw721_querier;
 mod helpers;
+mod router_querier;
 mod tests;
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use cosmwasm_std::{to_binary, Decimal, QuerierResult, SystemError};
+use steak::router::{AssetInfo, QueryMsg as RouterQueryMsg, SimulateSwapOperationsResponse};
+
+use super::helpers::err_unsupported_query;
+
+/// A mock router that swaps any native coin into the bond denom at a fixed rate, regardless of the
+/// operations in between
+#[derive(Default)]
+pub(super) struct RouterQuerier {
+    /// Mapping offer denom to the amount of the bond denom returned per unit offered
+    pub rates: HashMap<String, Decimal>,
+}
+
+impl RouterQuerier {
+    pub fn handle_query(&self, query: RouterQueryMsg) -> QuerierResult {
+        match &query {
+            RouterQueryMsg::SimulateSwapOperations {
+                offer_amount,
+                operations,
+            } => {
+                let offer_denom = match operations[0].offer_asset_info() {
+                    AssetInfo::NativeToken {
+                        denom,
+                    } => denom,
+                    _ => return err_unsupported_query(query),
+                };
+
+                let rate = self
+                    .rates
+                    .get(&offer_denom)
+                    .ok_or_else(|| SystemError::InvalidRequest {
+                        error: format!("[mock] swap rate not set for `{}`", offer_denom),
+                        request: Default::default(),
+                    })
+                    .unwrap();
+
+                Ok(to_binary(&SimulateSwapOperationsResponse {
+                    amount: *offer_amount * *rate,
+                })
+                .into())
+                .into()
+            },
+        }
+    }
+}
@@ -2,22 +2,31 @@ use std::str::FromStr;
 
 use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, DistributionMsg, Event, Order, OwnedDeps,
-    Reply, ReplyOn, StdError, SubMsg, SubMsgResponse, Uint128, WasmMsg,
+    from_binary, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, DistributionMsg, Event, Order, OwnedDeps,
+    Reply, ReplyOn, StdError, StdResult, SubMsg, SubMsgResponse, Uint128, WasmMsg,
 };
 use cw20::{Cw20ExecuteMsg, MinterResponse};
 use cw20_base::msg::InstantiateMsg as Cw20InstantiateMsg;
+use cw721_base::{
+    ExecuteMsg as Cw721ExecuteMsg, Extension, InstantiateMsg as Cw721InstantiateMsg, MintMsg,
+};
+use cw_storage_plus::Item;
 
 use steak::hub::{
-    Batch, CallbackMsg, ConfigResponse, ExecuteMsg, InstantiateMsg, PendingBatch, QueryMsg,
-    ReceiveMsg, StateResponse, UnbondRequest, UnbondRequestsByBatchResponseItem,
-    UnbondRequestsByUserResponseItem,
+    AprResponse, Batch, BufferResponse, CallbackMsg, ConfigResponse, ExchangeRateSnapshot, ExecuteMsg, HarvestRecord, InstantiateMsg, InsuranceDraw,
+    InsuranceResponse, KeeperRewardResponse, MigrateMsg, MigrateStep, PauseFlags, PendingBatch, QueryMsg, ReceiveMsg, SimulateBondResponse, SimulateUnbondResponse,
+    StateResponse, SwapRoute, TwapExchangeRateResponse, UnbondRequest, UnbondRequestsByBatchResponseItem,
+    UnbondRequestsByUserResponseItem, UnbondStatus, UnbondedHookMsg, UnbondingSummaryItem, UnbondingSummaryResponse,
+    WithdrawUnbondedResponse,
 };
+use steak::router::{AssetInfo, ExecuteMsg as RouterExecuteMsg, SwapOperation};
 
-use crate::contract::{execute, instantiate, reply};
+use crate::contract::{execute, instantiate, migrate, query, reply};
+use crate::error::ContractError;
 use crate::helpers::{parse_coin, parse_received_fund};
 use crate::math::{
-    compute_redelegations_for_rebalancing, compute_redelegations_for_removal, compute_undelegations,
+    compute_redelegations_for_rebalancing, compute_redelegations_for_removal, compute_target_amounts,
+    compute_undelegations,
 };
 use crate::state::State;
 use crate::types::{Coins, Delegation, Redelegation, Undelegation};
@@ -38,13 +47,19 @@ fn setup_test() -> OwnedDeps<MockStorage, MockApi, CustomQuerier> {
         mock_info("deployer", &[]),
         InstantiateMsg {
             cw20_code_id: 69420,
+            cw721_code_id: None,
             owner: "larry".to_string(),
             name: "Steak Token".to_string(),
             symbol: "STEAK".to_string(),
             decimals: 6,
+            denom: "uluna".to_string(),
             epoch_period: 259200,   // 3 * 24 * 60 * 60 = 3 days
             unbond_period: 1814400, // 21 * 24 * 60 * 60 = 21 days
             validators: vec!["alice".to_string(), "bob".to_string(), "charlie".to_string()],
+            fee_account: "the_fee_man".to_string(),
+            fee_rate: Decimal::percent(5),
+            max_total_bonded: None,
+            max_bonded_per_address: None,
         },
     )
     .unwrap();
@@ -106,6 +121,15 @@ fn setup_test() -> OwnedDeps<MockStorage, MockApi, CustomQuerier> {
 fn proper_instantiation() {
     let deps = setup_test();
 
+    let res = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        res,
+        cw2::ContractVersion {
+            contract: "crates.io:steak-hub".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    );
+
     let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
     assert_eq!(
         res,
@@ -113,9 +137,24 @@ fn proper_instantiation() {
             owner: "larry".to_string(),
             new_owner: None,
             steak_token: "steak_token".to_string(),
+            unbond_nft: None,
+            denom: "uluna".to_string(),
             epoch_period: 259200,
             unbond_period: 1814400,
-            validators: vec!["alice".to_string(), "bob".to_string(), "charlie".to_string()]
+            validators: vec![
+                ("alice".to_string(), 1),
+                ("bob".to_string(), 1),
+                ("charlie".to_string(), 1),
+            ],
+            fee_account: "the_fee_man".to_string(),
+            fee_rate: Decimal::percent(5),
+            netting: false,
+            min_bond: Uint128::zero(),
+            min_unbond: Uint128::zero(),
+            max_total_bonded: None,
+            max_bonded_per_address: None,
+            guardian: None,
+            pause_flags: PauseFlags::default(),
         }
     );
 
@@ -127,6 +166,7 @@ fn proper_instantiation() {
             total_uluna: Uint128::zero(),
             exchange_rate: Decimal::one(),
             unlocked_coins: vec![],
+            bond_headroom: None,
         },
     );
 
@@ -136,6 +176,7 @@ fn proper_instantiation() {
         PendingBatch {
             id: 1,
             usteak_to_burn: Uint128::zero(),
+            uluna_to_bond: Uint128::zero(),
             est_unbond_start_time: 269200, // 10,000 + 259,200
         },
     );
@@ -145,8 +186,27 @@ fn proper_instantiation() {
 fn bonding() {
     let mut deps = setup_test();
 
+    // The first bond must be greater than the amount of Steak to be locked
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(1000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InitialBondTooSmall {
+            min_amount: Uint128::new(1000)
+        }
+    );
+
     // Bond when no delegation has been made
-    // In this case, the full deposit simply goes to the first validator
+    // In this case, the full deposit simply goes to the first validator. As this is the first bond,
+    // 1000 usteak is minted to the contract itself and locked forever
     let res = execute(
         deps.as_mut(),
         mock_env(),
@@ -157,20 +217,37 @@ fn bonding() {
     )
     .unwrap();
 
-    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages.len(), 3);
     assert_eq!(
         res.messages[0],
-        SubMsg::reply_on_success(Delegation::new("alice", 1000000).to_cosmos_msg(), 2)
+        SubMsg::reply_on_success(Delegation::new("alice", 1000000, "uluna").to_cosmos_msg(), 2)
     );
     assert_eq!(
         res.messages[1],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "steak_token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Mint {
+                    recipient: MOCK_CONTRACT_ADDR.to_string(),
+                    amount: Uint128::new(1000)
+                })
+                .unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never,
+        }
+    );
+    assert_eq!(
+        res.messages[2],
         SubMsg {
             id: 0,
             msg: CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: "steak_token".to_string(),
                 msg: to_binary(&Cw20ExecuteMsg::Mint {
                     recipient: "user_1".to_string(),
-                    amount: Uint128::new(1000000)
+                    amount: Uint128::new(999000)
                 })
                 .unwrap(),
                 funds: vec![]
@@ -183,9 +260,9 @@ fn bonding() {
     // Bond when there are existing delegations, and Luna:Steak exchange rate is >1
     // Previously user 1 delegated 1,000,000 uluna. We assume we have accumulated 2.5% yield at 1025000 staked
     deps.querier.set_staking_delegations(&[
-        Delegation::new("alice", 341667),
-        Delegation::new("bob", 341667),
-        Delegation::new("charlie", 341666),
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
     ]);
     deps.querier.set_cw20_total_supply("steak_token", 1000000);
 
@@ -203,7 +280,7 @@ fn bonding() {
     assert_eq!(res.messages.len(), 2);
     assert_eq!(
         res.messages[0],
-        SubMsg::reply_on_success(Delegation::new("charlie", 12345).to_cosmos_msg(), 2)
+        SubMsg::reply_on_success(Delegation::new("charlie", 12345, "uluna").to_cosmos_msg(), 2)
     );
     assert_eq!(
         res.messages[1],
@@ -225,9 +302,9 @@ fn bonding() {
 
     // Check the state after bonding
     deps.querier.set_staking_delegations(&[
-        Delegation::new("alice", 341667),
-        Delegation::new("bob", 341667),
-        Delegation::new("charlie", 354011),
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 354011, "uluna"),
     ]);
     deps.querier.set_cw20_total_supply("steak_token", 1012043);
 
@@ -239,19 +316,206 @@ fn bonding() {
             total_uluna: Uint128::new(1037345),
             exchange_rate: Decimal::from_ratio(1037345u128, 1012043u128),
             unlocked_coins: vec![],
+            bond_headroom: None,
+        }
+    );
+}
+
+#[test]
+fn bonding_and_sending() {
+    let mut deps = setup_test();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    let hook_msg = Binary::from(br#"{"deposit":{}}"#);
+
+    // The Steak should be minted to the contract itself, and then sent to the vault along with the
+    // hook message
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(12345, "uluna")]),
+        ExecuteMsg::BondAndSend {
+            contract: "vault".to_string(),
+            msg: hook_msg.clone(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 3);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(Delegation::new("charlie", 12345, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::new(12043)
+            })
+            .unwrap(),
+            funds: vec![]
+        }))
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: "vault".to_string(),
+                amount: Uint128::new(12043),
+                msg: hook_msg.clone(),
+            })
+            .unwrap(),
+            funds: vec![]
+        }))
+    );
+
+    // Bonding and sending is paused along with bonding
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: Some(true),
+            queue_unbond: None,
+            instant_unbond: None,
+            withdraw_unbonded: None,
+            harvest: None,
+        },
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(12345, "uluna")]),
+        ExecuteMsg::BondAndSend {
+            contract: "vault".to_string(),
+            msg: hook_msg,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            action: "bond".to_string()
         }
     );
 }
 
+#[test]
+fn preventing_inflation_attack() {
+    let mut deps = setup_test();
+
+    // The attacker makes the first bond with the smallest amount possible. 1000 usteak is locked in
+    // the contract, and the attacker receives 1 usteak
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("attacker", &[Coin::new(1001, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages[2].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "attacker".to_string(),
+                amount: Uint128::new(1)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    // The attacker then donates 1,000,000 uluna through a direct delegation to inflate the exchange
+    // rate. Without the locked Steak, the attacker would hold the entire supply, and a later deposit
+    // of 1,000,000 uluna would be rounded down to zero Steak
+    deps.querier.set_staking_delegations(&[Delegation::new("alice", 1001001, "uluna")]);
+    deps.querier.set_cw20_total_supply("steak_token", 1001);
+
+    // The victim still receives a fair amount of Steak: 1001 * 1000000 / 1001001 = 999
+    let res: SimulateBondResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(1000000),
+            receiver: None,
+        },
+    );
+    assert_eq!(res.usteak_to_mint, Uint128::new(999));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("victim", &[Coin::new(1000000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "victim".to_string(),
+                amount: Uint128::new(999)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    // Most of the donation accrues to the locked Steak, so the attack is a loss for the attacker:
+    // their 1 usteak is now worth 1 * 2001001 / 2000 = 1000 uluna, versus the 1,001,001 uluna spent
+    deps.querier.set_staking_delegations(&[Delegation::new("alice", 2001001, "uluna")]);
+    deps.querier.set_cw20_total_supply("steak_token", 2000);
+
+    let res: SimulateUnbondResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::SimulateUnbond {
+            usteak: Uint128::new(1),
+        },
+    );
+    assert_eq!(res.uluna_to_unbond, Uint128::new(1000));
+
+    // A deposit too small to be worth a single usteak is rejected instead of being rounded down
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("victim", &[Coin::new(999, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::ZeroMintAmount {});
+}
+
 #[test]
 fn harvesting() {
     let mut deps = setup_test();
 
     // Assume users have bonded a total of 1,000,000 uluna and minted the same amount of usteak
     deps.querier.set_staking_delegations(&[
-        Delegation::new("alice", 341667),
-        Delegation::new("bob", 341667),
-        Delegation::new("charlie", 341666),
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
     ]);
     deps.querier.set_cw20_total_supply("steak_token", 1000000);
 
@@ -263,7 +527,7 @@ fn harvesting() {
     )
     .unwrap();
 
-    assert_eq!(res.messages.len(), 4);
+    assert_eq!(res.messages.len(), 5);
     assert_eq!(
         res.messages[0],
         SubMsg::reply_on_success(
@@ -293,6 +557,19 @@ fn harvesting() {
     );
     assert_eq!(
         res.messages[3],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+                msg: to_binary(&ExecuteMsg::Callback(CallbackMsg::Swap {})).unwrap(),
+                funds: vec![]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+    assert_eq!(
+        res.messages[4],
         SubMsg {
             id: 0,
             msg: CosmosMsg::Wasm(WasmMsg::Execute {
@@ -342,15 +619,120 @@ fn registering_unlocked_coins() {
     );
 }
 
+#[test]
+fn swapping() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let min_prices = [
+        ("ukrw", Decimal::from_ratio(4u128, 1000u128)),
+        ("ueur", Decimal::percent(80)),
+        ("uusd", Decimal::percent(45)),
+    ];
+    for (denom, min_price) in min_prices {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("larry", &[]),
+            ExecuteMsg::SetSwapRoute {
+                denom: denom.to_string(),
+                router: "router".to_string(),
+                operations: vec![SwapOperation::NativeSwap {
+                    offer_denom: denom.to_string(),
+                    ask_denom: "uluna".to_string(),
+                }],
+                max_spread: Decimal::percent(1),
+                min_price,
+            },
+        )
+        .unwrap();
+    }
+
+    // ukrw: minimum receive: 123 * 0.004 = 0, too small to be swapped
+    // ueur: minimum receive: 200 * 0.8 = 160; the pool has been manipulated to return 200 * 0.5 = 100,
+    // so it is not swapped
+    // uusd: minimum receive: 345 * 0.45 = 155; the pool returns 345 * 0.5 = 172, so it is swapped
+    deps.querier.set_swap_rate("ukrw", Decimal::from_ratio(5u128, 1000u128));
+    deps.querier.set_swap_rate("ueur", Decimal::from_ratio(1u128, 2u128));
+    deps.querier.set_swap_rate("uusd", Decimal::from_ratio(1u128, 2u128));
+
+    state
+        .unlocked_coins
+        .save(
+            deps.as_mut().storage,
+            &vec![
+                Coin::new(200, "ueur"),
+                Coin::new(123, "ukrw"),
+                Coin::new(234, "uluna"),
+                Coin::new(345, "uusd"),
+                Coin::new(69420, "ibc/0471F1C4E7AFD3F07702BEF6DC365268D64570F7C1FDC98EA6098DD6DE59817B"),
+            ],
+        )
+        .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("worker", &[]),
+        ExecuteMsg::Callback(CallbackMsg::Swap {}),
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::UnauthorizedCallback {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        ExecuteMsg::Callback(CallbackMsg::Swap {}),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "router".to_string(),
+                msg: to_binary(&RouterExecuteMsg::ExecuteSwapOperations {
+                    operations: vec![SwapOperation::NativeSwap {
+                        offer_denom: "uusd".to_string(),
+                        ask_denom: "uluna".to_string(),
+                    }],
+                    minimum_receive: Some(Uint128::new(155)),
+                    to: None,
+                    max_spread: Some(Decimal::percent(1)),
+                })
+                .unwrap(),
+                funds: vec![Coin::new(345, "uusd")],
+            }),
+            2,
+        )
+    );
+
+    // Swapped coins should have been removed from storage; the Luna received from the router will
+    // be registered by the reply
+    let unlocked_coins = state.unlocked_coins.load(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        unlocked_coins,
+        vec![
+            Coin::new(200, "ueur"),
+            Coin::new(123, "ukrw"),
+            Coin::new(234, "uluna"),
+            Coin::new(69420, "ibc/0471F1C4E7AFD3F07702BEF6DC365268D64570F7C1FDC98EA6098DD6DE59817B"),
+        ]
+    );
+}
+
 #[test]
 fn reinvesting() {
     let mut deps = setup_test();
     let state = State::default();
 
     deps.querier.set_staking_delegations(&[
-        Delegation::new("alice", 333334),
-        Delegation::new("bob", 333333),
-        Delegation::new("charlie", 333333),
+        Delegation::new("alice", 333334, "uluna"),
+        Delegation::new("bob", 333333, "uluna"),
+        Delegation::new("charlie", 333333, "uluna"),
     ]);
 
     // After the swaps, `unlocked_coins` should contain only uluna and unknown denoms
@@ -365,7 +747,8 @@ fn reinvesting() {
         )
         .unwrap();
 
-    // Bob has the smallest amount of delegations, so all proceeds go to him
+    // Protocol fee: 234 * 0.05 = 11
+    // Bob has the smallest amount of delegations, so all remaining proceeds go to him
     let res = execute(
         deps.as_mut(),
         mock_env(),
@@ -374,12 +757,24 @@ fn reinvesting() {
     )
     .unwrap();
 
-    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages.len(), 2);
     assert_eq!(
         res.messages[0],
         SubMsg {
             id: 0,
-            msg: Delegation::new("bob", 234).to_cosmos_msg(),
+            msg: Delegation::new("bob", 223, "uluna").to_cosmos_msg(),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Bank(BankMsg::Send {
+                to_address: "the_fee_man".to_string(),
+                amount: vec![Coin::new(11, "uluna")]
+            }),
             gas_limit: None,
             reply_on: ReplyOn::Never
         }
@@ -394,42 +789,288 @@ fn reinvesting() {
 }
 
 #[test]
-fn queuing_unbond() {
+fn rewarding_keepers() {
     let mut deps = setup_test();
     let state = State::default();
 
-    // Only Steak token is accepted for unbonding requests
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 333334, "uluna"),
+        Delegation::new("bob", 333333, "uluna"),
+        Delegation::new("charlie", 333333, "uluna"),
+    ]);
+
+    // Only the owner can update the keeper reward
     let err = execute(
         deps.as_mut(),
         mock_env(),
-        mock_info("random_token", &[]),
-        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: "hacker".to_string(),
-            amount: Uint128::new(69420),
-            msg: to_binary(&ReceiveMsg::QueueUnbond {
-                receiver: None,
-            })
-            .unwrap(),
-        }),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdateKeeperReward {
+            new_rate: Decimal::percent(2),
+            new_amount: Uint128::new(100),
+            new_cooldown: 3600,
+        },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("expecting Steak token, received random_token"));
+    assert_eq!(err, ContractError::Unauthorized {});
 
-    // User 1 creates an unbonding request before `est_unbond_start_time` is reached. The unbond
-    // request is saved, but not the pending batch is not submitted for unbonding
-    let res = execute(
+    let err = execute(
         deps.as_mut(),
-        mock_env_at_timestamp(12345), // est_unbond_start_time = 269200
-        mock_info("steak_token", &[]),
-        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
-            sender: "user_1".to_string(),
-            amount: Uint128::new(23456),
-            msg: to_binary(&ReceiveMsg::QueueUnbond {
-                receiver: None,
-            })
-            .unwrap(),
-        }),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateKeeperReward {
+            new_rate: Decimal::percent(6),
+            new_amount: Uint128::new(100),
+            new_cooldown: 3600,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::KeeperRateTooHigh {
+            max_keeper_rate: Decimal::percent(5),
+        }
+    );
+
+    // A zero cooldown would let the keeper pool be drained by invoking the same action repeatedly
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateKeeperReward {
+            new_rate: Decimal::percent(2),
+            new_amount: Uint128::new(100),
+            new_cooldown: 0,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::KeeperCooldownTooShort {
+            min_cooldown: 3600,
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateKeeperReward {
+            new_rate: Decimal::percent(2),
+            new_amount: Uint128::new(100),
+            new_cooldown: 3600,
+        },
+    )
+    .unwrap();
+
+    // The keeper pool is empty, so no reward is paid yet
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(10000),
+        mock_info("keeper_1", &[]),
+        ExecuteMsg::Harvest {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 5);
+    assert!(res.events.is_empty());
+
+    // Reinvesting puts a slice of the rewards into the keeper pool
+    //
+    // Protocol fee: 10000 * 0.05 = 500
+    // Keeper slice: 10000 * 0.02 = 200
+    // To bond: 10000 - 500 - 200 = 9300
+    state
+        .unlocked_coins
+        .save(deps.as_mut().storage, &vec![Coin::new(10000, "uluna")])
+        .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        ExecuteMsg::Callback(CallbackMsg::Reinvest {}),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(Delegation::new("bob", 9300, "uluna").to_cosmos_msg())
+    );
+
+    let res: KeeperRewardResponse = query_helper(deps.as_ref(), QueryMsg::KeeperReward {});
+    assert_eq!(
+        res,
+        KeeperRewardResponse {
+            balance: Uint128::new(200),
+            rate: Decimal::percent(2),
+            amount: Uint128::new(100),
+            cooldown: 3600,
+        }
+    );
+
+    // The next harvest is rewarded
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("keeper_1", &[]),
+        ExecuteMsg::Harvest {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 6);
+    assert_eq!(
+        res.messages[5],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "keeper_1".to_string(),
+            amount: vec![Coin::new(100, "uluna")],
+        }))
+    );
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/keeper_rewarded")
+            .add_attribute("time", "20000")
+            .add_attribute("height", "12345")
+            .add_attribute("action", "harvest")
+            .add_attribute("keeper", "keeper_1")
+            .add_attribute("uluna_rewarded", "100")]
+    );
+
+    // Harvesting again within the cooldown period is not rewarded
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(23599),
+        mock_info("keeper_2", &[]),
+        ExecuteMsg::Harvest {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 5);
+    assert!(res.events.is_empty());
+
+    // Rebalancing is not rewarded if no Luna has been moved
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(23599),
+        mock_info("keeper_2", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    // Nor is it rewarded if less than 1% of the Luna staked is moved
+    // Target: 1,000,000 / 3 = 333,333; to move: 334,000 - 333,334 = 666 < 10,000
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 334000, "uluna"),
+        Delegation::new("bob", 333000, "uluna"),
+        Delegation::new("charlie", 333000, "uluna"),
+    ]);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(23599),
+        mock_info("keeper_2", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert!(res.events.iter().all(|event| event.ty != "steakhub/keeper_rewarded"));
+
+    // Once the cooldown period has elapsed, harvesting is rewarded again
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(23600),
+        mock_info("keeper_2", &[]),
+        ExecuteMsg::Harvest {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 6);
+    assert_eq!(
+        res.messages[5],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "keeper_2".to_string(),
+            amount: vec![Coin::new(100, "uluna")],
+        }))
+    );
+
+    let keeper_pool = state.keeper_pool.load(deps.as_ref().storage).unwrap();
+    assert_eq!(keeper_pool, Uint128::zero());
+
+    // Rebalancing is rewarded if at least 1% of the Luna staked is moved
+    // Target: 1,000,000 / 3 = 333,333; to move: 360,000 - 333,334 = 26,666 >= 10,000
+    state.keeper_pool.save(deps.as_mut().storage, &Uint128::new(100)).unwrap();
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 360000, "uluna"),
+        Delegation::new("bob", 320000, "uluna"),
+        Delegation::new("charlie", 320000, "uluna"),
+    ]);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(23600),
+        mock_info("keeper_2", &[]),
+        ExecuteMsg::Rebalance {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 3);
+    assert_eq!(
+        res.messages[2],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "keeper_2".to_string(),
+            amount: vec![Coin::new(100, "uluna")],
+        }))
+    );
+}
+
+#[test]
+fn queuing_unbond() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    // Only Steak token is accepted for unbonding requests
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("random_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "hacker".to_string(),
+            amount: Uint128::new(69420),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InvalidToken {
+            sender: "random_token".to_string()
+        }
+    );
+
+    // User 1 creates an unbonding request before `est_unbond_start_time` is reached. The unbond
+    // request is saved, but not the pending batch is not submitted for unbonding
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345), // est_unbond_start_time = 269200
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(23456),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
     )
     .unwrap();
 
@@ -470,11 +1111,11 @@ fn queuing_unbond() {
     // The users' unbonding requests should have been saved
     let ubr1 = state
         .unbond_requests
-        .load(deps.as_ref().storage, (1u64.into(), &Addr::unchecked("user_1")))
+        .load(deps.as_ref().storage, (1u64, "user_1"))
         .unwrap();
     let ubr2 = state
         .unbond_requests
-        .load(deps.as_ref().storage, (1u64.into(), &Addr::unchecked("user_3")))
+        .load(deps.as_ref().storage, (1u64, "user_3"))
         .unwrap();
 
     assert_eq!(
@@ -482,7 +1123,9 @@ fn queuing_unbond() {
         UnbondRequest {
             id: 1,
             user: Addr::unchecked("user_1"),
-            shares: Uint128::new(23456)
+            shares: Uint128::new(23456),
+            seq: 0,
+            held_claimed: false,
         }
     );
     assert_eq!(
@@ -490,7 +1133,9 @@ fn queuing_unbond() {
         UnbondRequest {
             id: 1,
             user: Addr::unchecked("user_3"),
-            shares: Uint128::new(69420)
+            shares: Uint128::new(69420),
+            seq: 0,
+            held_claimed: false,
         }
     );
 
@@ -501,284 +1146,249 @@ fn queuing_unbond() {
         PendingBatch {
             id: 1,
             usteak_to_burn: Uint128::new(92876), // 23,456 + 69,420
+            uluna_to_bond: Uint128::zero(),
             est_unbond_start_time: 269200
         }
     );
 }
 
 #[test]
-fn submitting_batch() {
+fn cancelling_unbond() {
     let mut deps = setup_test();
     let state = State::default();
 
-    // uluna bonded: 1,037,345
-    // usteak supply: 1,012,043
-    // uluna per ustake: 1.025
-    deps.querier.set_staking_delegations(&[
-        Delegation::new("alice", 345782),
-        Delegation::new("bob", 345782),
-        Delegation::new("charlie", 345781),
-    ]);
-    deps.querier.set_cw20_total_supply("steak_token", 1012043);
+    for (user, usteak) in [("user_1", 23456), ("user_2", 10000)] {
+        execute(
+            deps.as_mut(),
+            mock_env_at_timestamp(12345), // est_unbond_start_time = 269200
+            mock_info("steak_token", &[]),
+            ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+                sender: user.to_string(),
+                amount: Uint128::new(usteak),
+                msg: to_binary(&ReceiveMsg::QueueUnbond {
+                    receiver: None,
+                })
+                .unwrap(),
+            }),
+        )
+        .unwrap();
+    }
 
-    // We continue from the contract state at the end of the last test
-    let unbond_requests = vec![
-        UnbondRequest {
-            id: 1,
-            user: Addr::unchecked("user_1"),
-            shares: Uint128::new(23456),
-        },
-        UnbondRequest {
-            id: 1,
-            user: Addr::unchecked("user_3"),
-            shares: Uint128::new(69420),
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::zero(),
+            token_id: None,
         },
-    ];
-
-    for unbond_request in &unbond_requests {
-        state
-            .unbond_requests
-            .save(
-                deps.as_mut().storage,
-                (unbond_request.id.into(), &Addr::unchecked(unbond_request.user.clone())),
-                unbond_request,
-            )
-            .unwrap();
-    }
+    )
+    .unwrap_err();
 
-    state
-        .pending_batch
-        .save(
-            deps.as_mut().storage,
-            &PendingBatch {
-                id: 1,
-                usteak_to_burn: Uint128::new(92876), // 23,456 + 69,420
-                est_unbond_start_time: 269200,
-            },
-        )
-        .unwrap();
+    assert_eq!(err, ContractError::ZeroCancelAmount {});
 
-    // Anyone can invoke `submit_batch`. Here we continue from the previous test and assume it is
-    // invoked automatically as user 2 submits the unbonding request
-    //
-    // usteak to burn: 23,456 + 69,420 = 92,876
-    // uluna to unbond: 1,037,345 * 92,876 / 1,012,043 = 95,197
-    //
-    // Target: (1,037,345 - 95,197) / 3 = 314,049
-    // Remainer: 1
-    // Alice:   345,782 - (314,049 + 1) = 31,732
-    // Bob:     345,782 - (314,049 + 0) = 31,733
-    // Charlie: 345,781 - (314,049 + 0) = 31,732
-    let res = execute(
+    // User 3 has nothing queued
+    let err = execute(
         deps.as_mut(),
-        mock_env_at_timestamp(269201),
-        mock_info(MOCK_CONTRACT_ADDR, &[]),
-        ExecuteMsg::SubmitBatch {},
+        mock_env_at_timestamp(20000),
+        mock_info("user_3", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(1),
+            token_id: None,
+        },
     )
-    .unwrap();
+    .unwrap_err();
 
-    assert_eq!(res.messages.len(), 4);
-    assert_eq!(
-        res.messages[0],
-        SubMsg::reply_on_success(Undelegation::new("alice", 31732).to_cosmos_msg(), 2)
-    );
-    assert_eq!(
-        res.messages[1],
-        SubMsg::reply_on_success(Undelegation::new("bob", 31733).to_cosmos_msg(), 2)
-    );
-    assert_eq!(
-        res.messages[2],
-        SubMsg::reply_on_success(Undelegation::new("charlie", 31732).to_cosmos_msg(), 2)
-    );
     assert_eq!(
-        res.messages[3],
-        SubMsg {
-            id: 0,
-            msg: CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: "steak_token".to_string(),
-                msg: to_binary(&Cw20ExecuteMsg::Burn {
-                    amount: Uint128::new(92876)
-                })
-                .unwrap(),
-                funds: vec![]
-            }),
-            gas_limit: None,
-            reply_on: ReplyOn::Never
+        err,
+        ContractError::CancelExceedsQueued {
+            queued: Uint128::zero()
         }
     );
 
-    // A new pending batch should have been created
-    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(23457),
+            token_id: None,
+        },
+    )
+    .unwrap_err();
+
     assert_eq!(
-        pending_batch,
-        PendingBatch {
-            id: 2,
-            usteak_to_burn: Uint128::zero(),
-            est_unbond_start_time: 528401 // 269,201 + 259,200
+        err,
+        ContractError::CancelExceedsQueued {
+            queued: Uint128::new(23456)
         }
     );
 
-    // Previous batch should have been updated
-    let previous_batch = state.previous_batches.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    // A partial cancellation cannot leave less than the minimum unbond amount queued, while cancelling
+    // the entire request is always allowed
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: None,
+            min_bond: None,
+            min_unbond: Some(Uint128::new(5000)),
+        },
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(20000),
+            token_id: None,
+        },
+    )
+    .unwrap_err();
+
     assert_eq!(
-        previous_batch,
-        Batch {
-            id: 1,
-            reconciled: false,
-            total_shares: Uint128::new(92876),
-            uluna_unclaimed: Uint128::new(95197),
-            est_unbond_end_time: 2083601 // 269,201 + 1,814,400
+        err,
+        ContractError::RemainingUnbondTooSmall {
+            min_unbond: Uint128::new(5000)
         }
     );
-}
-
-#[test]
-fn reconciling() {
-    let mut deps = setup_test();
-    let state = State::default();
 
-    let previous_batches = vec![
-        Batch {
-            id: 1,
-            reconciled: true,
-            total_shares: Uint128::new(92876),
-            uluna_unclaimed: Uint128::new(95197), // 1.025 Luna per Steak
-            est_unbond_end_time: 10000,
-        },
-        Batch {
-            id: 2,
-            reconciled: false,
-            total_shares: Uint128::new(1345),
-            uluna_unclaimed: Uint128::new(1385), // 1.030 Luna per Steak
-            est_unbond_end_time: 20000,
+    // User 1 cancels part of the request
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(3456),
+            token_id: None,
         },
-        Batch {
-            id: 3,
-            reconciled: false,
-            total_shares: Uint128::new(1456),
-            uluna_unclaimed: Uint128::new(1506), // 1.035 Luna per Steak
-            est_unbond_end_time: 30000,
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "user_1".to_string(),
+                amount: Uint128::new(3456)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/unbond_cancelled")
+            .add_attribute("time", "20000")
+            .add_attribute("height", "12345")
+            .add_attribute("id", "1")
+            .add_attribute("user", "user_1")
+            .add_attribute("usteak_cancelled", "3456")]
+    );
+
+    // User 2 cancels the entire request
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_2", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(10000),
+            token_id: None,
         },
-        Batch {
-            id: 4,
-            reconciled: false,
-            total_shares: Uint128::new(1567),
-            uluna_unclaimed: Uint128::new(1629), // 1.040 Luna per Steak
-            est_unbond_end_time: 40000,          // not yet finished unbonding, ignored
+    )
+    .unwrap();
+
+    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByBatch {
+            id: 1,
+            start_after: None,
+            start_after_seq: None,
+            limit: None,
         },
-    ];
+    );
+    assert_eq!(
+        res,
+        vec![UnbondRequestsByBatchResponseItem {
+            user: "user_1".to_string(),
+            shares: Uint128::new(20000),
+            seq: 0,
+        }]
+    );
 
-    for previous_batch in &previous_batches {
-        state
-            .previous_batches
-            .save(deps.as_mut().storage, previous_batch.id.into(), previous_batch)
-            .unwrap();
-    }
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
+    assert_eq!(pending_batch.usteak_to_burn, Uint128::new(20000));
 
+    // Once the batch is submitted, the request can no longer be cancelled
     state
-        .unlocked_coins
+        .pending_batch
         .save(
             deps.as_mut().storage,
-            &vec![
-                Coin::new(10000, "uluna"),
-                Coin::new(234, "ukrw"),
-                Coin::new(345, "uusd"),
-                Coin::new(69420, "ibc/0471F1C4E7AFD3F07702BEF6DC365268D64570F7C1FDC98EA6098DD6DE59817B"),
-            ],
+            &PendingBatch {
+                id: 2,
+                usteak_to_burn: Uint128::zero(),
+                uluna_to_bond: Uint128::zero(),
+                est_unbond_start_time: 528400,
+            },
         )
         .unwrap();
 
-    deps.querier.set_bank_balances(&[
-        Coin::new(12345, "uluna"),
-        Coin::new(234, "ukrw"),
-        Coin::new(345, "uusd"),
-        Coin::new(69420, "ibc/0471F1C4E7AFD3F07702BEF6DC365268D64570F7C1FDC98EA6098DD6DE59817B"),
-    ]);
-
-    execute(
+    let err = execute(
         deps.as_mut(),
-        mock_env_at_timestamp(35000),
-        mock_info("worker", &[]),
-        ExecuteMsg::Reconcile {},
+        mock_env_at_timestamp(300000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(20000),
+            token_id: None,
+        },
     )
-    .unwrap();
-
-    // Expected received: batch 2 + batch 3 = 1385 + 1506 = 2891
-    // Expected unlocked: 10000
-    // Expected: 12891
-    // Actual: 12345
-    // Shortfall: 12891 - 12345 = 456
-    //
-    // uluna per batch: 546 / 2 = 273
-    // remainder: 0
-    // batch 2: 1385 - 273 = 1112
-    // batch 3: 1506 - 273 = 1233
-    let batch = state.previous_batches.load(deps.as_ref().storage, 2u64.into()).unwrap();
-    assert_eq!(
-        batch,
-        Batch {
-            id: 2,
-            reconciled: true,
-            total_shares: Uint128::new(1345),
-            uluna_unclaimed: Uint128::new(1112), // 1385 - 273
-            est_unbond_end_time: 20000,
-        }
-    );
+    .unwrap_err();
 
-    let batch = state.previous_batches.load(deps.as_ref().storage, 3u64.into()).unwrap();
     assert_eq!(
-        batch,
-        Batch {
-            id: 3,
-            reconciled: true,
-            total_shares: Uint128::new(1456),
-            uluna_unclaimed: Uint128::new(1233), // 1506 - 273
-            est_unbond_end_time: 30000,
+        err,
+        ContractError::CancelExceedsQueued {
+            queued: Uint128::zero()
         }
     );
-
-    // Batches 1 and 4 should not have changed
-    let batch = state.previous_batches.load(deps.as_ref().storage, 1u64.into()).unwrap();
-    assert_eq!(batch, previous_batches[0]);
-
-    let batch = state.previous_batches.load(deps.as_ref().storage, 4u64.into()).unwrap();
-    assert_eq!(batch, previous_batches[3]);
 }
 
 #[test]
-fn withdrawing_unbonded() {
+fn submitting_batch() {
     let mut deps = setup_test();
     let state = State::default();
 
-    // We simulate a most general case:
-    // - batches 1 and 2 have finished unbonding
-    // - batch 3 have been submitted for unbonding but have not finished
-    // - batch 4 is still pending
+    // uluna bonded: 1,037,345
+    // usteak supply: 1,012,043
+    // uluna per ustake: 1.025
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 345782, "uluna"),
+        Delegation::new("bob", 345782, "uluna"),
+        Delegation::new("charlie", 345781, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1012043);
+
+    // We continue from the contract state at the end of the last test
     let unbond_requests = vec![
         UnbondRequest {
             id: 1,
             user: Addr::unchecked("user_1"),
             shares: Uint128::new(23456),
+            seq: 0,
+            held_claimed: false,
         },
         UnbondRequest {
             id: 1,
             user: Addr::unchecked("user_3"),
             shares: Uint128::new(69420),
-        },
-        UnbondRequest {
-            id: 2,
-            user: Addr::unchecked("user_1"),
-            shares: Uint128::new(34567),
-        },
-        UnbondRequest {
-            id: 3,
-            user: Addr::unchecked("user_1"),
-            shares: Uint128::new(45678),
-        },
-        UnbondRequest {
-            id: 4,
-            user: Addr::unchecked("user_1"),
-            shares: Uint128::new(56789),
+            seq: 0,
+            held_claimed: false,
         },
     ];
 
@@ -787,195 +1397,3949 @@ fn withdrawing_unbonded() {
             .unbond_requests
             .save(
                 deps.as_mut().storage,
-                (unbond_request.id.into(), &Addr::unchecked(unbond_request.user.clone())),
+                (unbond_request.id, &unbond_request.key()),
                 unbond_request,
             )
             .unwrap();
     }
 
-    let previous_batches = vec![
-        Batch {
-            id: 1,
-            reconciled: true,
-            total_shares: Uint128::new(92876),
-            uluna_unclaimed: Uint128::new(95197), // 1.025 Luna per Steak
-            est_unbond_end_time: 10000,
-        },
-        Batch {
-            id: 2,
-            reconciled: true,
-            total_shares: Uint128::new(34567),
-            uluna_unclaimed: Uint128::new(35604), // 1.030 Luna per Steak
-            est_unbond_end_time: 20000,
-        },
-        Batch {
-            id: 3,
-            reconciled: false, // finished unbonding, but not reconciled; ignored
-            total_shares: Uint128::new(45678),
-            uluna_unclaimed: Uint128::new(47276), // 1.035 Luna per Steak
-            est_unbond_end_time: 20000,
-        },
-        Batch {
-            id: 4,
-            reconciled: true,
-            total_shares: Uint128::new(56789),
-            uluna_unclaimed: Uint128::new(59060), // 1.040 Luna per Steak
-            est_unbond_end_time: 30000, // reconciled, but not yet finished unbonding; ignored
-        },
-    ];
-
-    for previous_batch in &previous_batches {
-        state
-            .previous_batches
-            .save(deps.as_mut().storage, previous_batch.id.into(), previous_batch)
-            .unwrap();
-    }
-
     state
         .pending_batch
         .save(
             deps.as_mut().storage,
             &PendingBatch {
-                id: 4,
-                usteak_to_burn: Uint128::new(56789),
-                est_unbond_start_time: 100000,
+                id: 1,
+                usteak_to_burn: Uint128::new(92876), // 23,456 + 69,420
+                uluna_to_bond: Uint128::zero(),
+                est_unbond_start_time: 269200,
             },
         )
         .unwrap();
 
-    // Attempt to withdraw before any batch has completed unbonding. Should error
-    let err = execute(
-        deps.as_mut(),
-        mock_env_at_timestamp(5000),
-        mock_info("user_1", &[]),
-        ExecuteMsg::WithdrawUnbonded {
-            receiver: None,
-        },
-    )
-    .unwrap_err();
-
-    assert_eq!(err, StdError::generic_err("withdrawable amount is zero"));
-
-    // Attempt to withdraw once batches 1 and 2 have finished unbonding, but 3 has not yet
-    //
-    // Withdrawable from batch 1: 95,197 * 23,456 / 92,876 = 24,042
-    // Withdrawable from batch 2: 35,604
-    // Total withdrawable: 24,042 + 35,604 = 59,646
+    // Anyone can invoke `submit_batch`. Here we continue from the previous test and assume it is
+    // invoked automatically as user 2 submits the unbonding request
     //
-    // Batch 1 should be updated:
-    // Total shares: 92,876 - 23,456 = 69,420
-    // Unclaimed uluna: 95,197 - 24,042 = 71,155
+    // usteak to burn: 23,456 + 69,420 = 92,876
+    // uluna to unbond: 1,037,345 * 92,876 / 1,012,043 = 95,197
     //
-    // Batch 2 is completely withdrawn, should be purged from storage
+    // Target: (1,037,345 - 95,197) / 3 = 314,049
+    // Remainer: 1
+    // Alice:   345,782 - (314,049 + 1) = 31,732
+    // Bob:     345,782 - (314,049 + 0) = 31,733
+    // Charlie: 345,781 - (314,049 + 0) = 31,732
     let res = execute(
         deps.as_mut(),
-        mock_env_at_timestamp(25000),
-        mock_info("user_1", &[]),
-        ExecuteMsg::WithdrawUnbonded {
-            receiver: None,
-        },
+        mock_env_at_timestamp(269201),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        ExecuteMsg::SubmitBatch {},
     )
     .unwrap();
 
-    assert_eq!(res.messages.len(), 1);
+    assert_eq!(res.messages.len(), 4);
     assert_eq!(
         res.messages[0],
+        SubMsg::reply_on_success(Undelegation::new("alice", 31732, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::reply_on_success(Undelegation::new("bob", 31733, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg::reply_on_success(Undelegation::new("charlie", 31732, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[3],
         SubMsg {
             id: 0,
-            msg: CosmosMsg::Bank(BankMsg::Send {
-                to_address: "user_1".to_string(),
-                amount: vec![Coin::new(59646, "uluna")]
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "steak_token".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Burn {
+                    amount: Uint128::new(92876)
+                })
+                .unwrap(),
+                funds: vec![]
             }),
             gas_limit: None,
             reply_on: ReplyOn::Never
         }
     );
 
-    // Previous batches should have been updated
-    let batch = state.previous_batches.load(deps.as_ref().storage, 1u64.into()).unwrap();
+    // A new pending batch should have been created
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
     assert_eq!(
-        batch,
+        pending_batch,
+        PendingBatch {
+            id: 2,
+            usteak_to_burn: Uint128::zero(),
+            uluna_to_bond: Uint128::zero(),
+            est_unbond_start_time: 528401 // 269,201 + 259,200
+        }
+    );
+
+    // Previous batch should have been updated
+    let previous_batch = state.previous_batches.load(deps.as_ref().storage, 1u64).unwrap();
+    assert_eq!(
+        previous_batch,
+        Batch {
+            id: 1,
+            reconciled: false,
+            total_shares: Uint128::new(92876),
+            uluna_unclaimed: Uint128::new(95197),
+            est_unbond_end_time: 2083601, // 269,201 + 1,814,400
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![
+                ("alice".to_string(), Uint128::new(31732)),
+                ("bob".to_string(), Uint128::new(31733)),
+                ("charlie".to_string(), Uint128::new(31732)),
+            ],
+        }
+    );
+
+    // The contract should expect its delegations to have decreased by the amounts undelegated
+    let expected_delegations = state
+        .expected_delegations
+        .range(deps.as_ref().storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        expected_delegations,
+        vec![
+            ("alice".to_string(), Uint128::new(314050)),
+            ("bob".to_string(), Uint128::new(314049)),
+            ("charlie".to_string(), Uint128::new(314049)),
+        ]
+    );
+}
+
+#[test]
+fn netting_bonds_against_unbonds() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: Some(true),
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap();
+
+    // uluna bonded: 1,020,000
+    // usteak supply: 1,000,000
+    // uluna per ustake: 1.02
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 340000, "uluna"),
+        Delegation::new("bob", 340000, "uluna"),
+        Delegation::new("charlie", 340000, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    // With netting enabled, the deposit is held instead of delegated
+    // usteak to mint: 1,000,000 * 51,000 / 1,020,000 = 50,000
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_1", &[Coin::new(51000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "user_1".to_string(),
+                amount: Uint128::new(50000)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
+    assert_eq!(pending_batch.uluna_to_bond, Uint128::new(51000));
+
+    // The held Luna is accounted for in the exchange rate
+    deps.querier.set_cw20_total_supply("steak_token", 1050000);
+
+    let res: StateResponse = query_helper(deps.as_ref(), QueryMsg::State {});
+    assert_eq!(res.total_uluna, Uint128::new(1071000));
+    assert_eq!(res.exchange_rate, Decimal::from_ratio(1071000u128, 1050000u128));
+
+    for (user, usteak) in [("user_2", 30000), ("user_3", 10000)] {
+        execute(
+            deps.as_mut(),
+            mock_env_at_timestamp(30000),
+            mock_info("steak_token", &[]),
+            ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+                sender: user.to_string(),
+                amount: Uint128::new(usteak),
+                msg: to_binary(&ReceiveMsg::QueueUnbond {
+                    receiver: None,
+                })
+                .unwrap(),
+            }),
+        )
+        .unwrap();
+    }
+
+    // uluna to unbond: 1,071,000 * 40,000 / 1,050,000 = 40,800
+    // All of it is matched by the 51,000 uluna held, so nothing is undelegated, and the remaining
+    // 51,000 - 40,800 = 10,200 uluna is delegated. The matched Luna is kept for the unbonders to claim
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(269201),
+        mock_info("worker", &[]),
+        ExecuteMsg::SubmitBatch {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(Delegation::new("alice", 10200, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                amount: Uint128::new(40000)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    // Since nothing was undelegated, the batch is claimable right away
+    let previous_batch = state.previous_batches.load(deps.as_ref().storage, 1u64).unwrap();
+    assert_eq!(
+        previous_batch,
         Batch {
             id: 1,
             reconciled: true,
-            total_shares: Uint128::new(69420),
-            uluna_unclaimed: Uint128::new(71155),
-            est_unbond_end_time: 10000,
+            total_shares: Uint128::new(40000),
+            uluna_unclaimed: Uint128::zero(),
+            est_unbond_end_time: 269201,
+            uluna_held: Uint128::new(40800),
+            held_shares: Uint128::new(40000),
+            undelegations: vec![],
         }
     );
 
-    let err = state.previous_batches.load(deps.as_ref().storage, 2u64.into()).unwrap_err();
+    for (user, uluna) in [("user_2", 30600), ("user_3", 10200)] {
+        let res = execute(
+            deps.as_mut(),
+            mock_env_at_timestamp(269201),
+            mock_info(user, &[]),
+            ExecuteMsg::WithdrawUnbonded {
+                receiver: None,
+                batch_ids: None,
+                limit: None,
+                hook: false,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: user.to_string(),
+                amount: vec![Coin::new(uluna, "uluna")]
+            })
+        );
+    }
+
+    assert!(state.previous_batches.may_load(deps.as_ref().storage, 1u64).unwrap().is_none());
+    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByBatch {
+            id: 1,
+            start_after: None,
+            start_after_seq: None,
+            limit: None,
+        },
+    );
+    assert!(res.is_empty());
+
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
     assert_eq!(
-        err,
-        StdError::NotFound { kind: "steak::hub::Batch".to_string() }
+        pending_batch,
+        PendingBatch {
+            id: 2,
+            usteak_to_burn: Uint128::zero(),
+            uluna_to_bond: Uint128::zero(),
+            est_unbond_start_time: 528401, // 269,201 + 259,200
+        }
     );
 
-    // User 1's unbond requests in batches 1 and 2 should have been deleted
-    let err1 = state
-        .unbond_requests
-        .load(deps.as_ref().storage, (1u64.into(), &Addr::unchecked("user_1")))
-        .unwrap_err();
-    let err2 = state
-        .unbond_requests
-        .load(deps.as_ref().storage, (1u64.into(), &Addr::unchecked("user_1")))
-        .unwrap_err();
+    // In the next epoch, less is bonded than unbonded
+    // uluna bonded: 1,030,200
+    // usteak supply: 1,010,000
+    // usteak to mint: 1,010,000 * 10,200 / 1,030,200 = 10,000
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 350200, "uluna"),
+        Delegation::new("bob", 340000, "uluna"),
+        Delegation::new("charlie", 340000, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1010000);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(300000),
+        mock_info("user_1", &[Coin::new(10200, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    deps.querier.set_cw20_total_supply("steak_token", 1020000);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(300000),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_2".to_string(),
+            amount: Uint128::new(50000),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    // uluna to unbond: (1,030,200 + 10,200) * 50,000 / 1,020,000 = 51,000
+    // Matched: 10,200, kept in the batch
+    // To undelegate: 51,000 - 10,200 = 40,800
+    //
+    // Target: (1,030,200 - 40,800) / 3 = 329,800
+    // Alice:   350,200 - 329,800 = 20,400
+    // Bob:     340,000 - 329,800 = 10,200
+    // Charlie: 340,000 - 329,800 = 10,200
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(528401),
+        mock_info("worker", &[]),
+        ExecuteMsg::SubmitBatch {},
+    )
+    .unwrap();
 
+    assert_eq!(res.messages.len(), 4);
     assert_eq!(
-        err1,
-        StdError::NotFound { kind: "steak::hub::UnbondRequest".to_string() }
+        res.messages[0],
+        SubMsg::reply_on_success(Undelegation::new("alice", 20400, "uluna").to_cosmos_msg(), 2)
     );
     assert_eq!(
-        err2,
-        StdError::NotFound { kind: "steak::hub::UnbondRequest".to_string() }
+        res.messages[1],
+        SubMsg::reply_on_success(Undelegation::new("bob", 10200, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg::reply_on_success(Undelegation::new("charlie", 10200, "uluna").to_cosmos_msg(), 2)
     );
 
-    // User 3 attempt to withdraw; also specifying a receiver
+    // The matched Luna is claimable right away, and the undelegated Luna once the batch finishes
+    // unbonding
+    let previous_batch = state.previous_batches.load(deps.as_ref().storage, 2u64).unwrap();
+    assert_eq!(
+        previous_batch,
+        Batch {
+            id: 2,
+            reconciled: false,
+            total_shares: Uint128::new(50000),
+            uluna_unclaimed: Uint128::new(40800),
+            est_unbond_end_time: 2342801, // 528,401 + 1,814,400
+            uluna_held: Uint128::new(10200),
+            held_shares: Uint128::new(50000),
+            undelegations: vec![
+                ("alice".to_string(), Uint128::new(20400)),
+                ("bob".to_string(), Uint128::new(10200)),
+                ("charlie".to_string(), Uint128::new(10200)),
+            ],
+        }
+    );
+
+    let res: UnbondingSummaryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(528401),
+            QueryMsg::UnbondingSummary {
+                user: "user_2".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.requests[0].status, UnbondStatus::Unbonding);
+    assert_eq!(res.requests[0].est_uluna, Uint128::new(51000));
+    assert_eq!(res.total_withdrawable, Uint128::new(10200));
+
     let res = execute(
         deps.as_mut(),
-        mock_env_at_timestamp(25000),
-        mock_info("user_3", &[]),
+        mock_env_at_timestamp(528401),
+        mock_info("user_2", &[]),
         ExecuteMsg::WithdrawUnbonded {
-            receiver: Some("user_2".to_string()),
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
         },
     )
     .unwrap();
 
     assert_eq!(res.messages.len(), 1);
     assert_eq!(
-        res.messages[0],
-        SubMsg {
-            id: 0,
-            msg: CosmosMsg::Bank(BankMsg::Send {
-                to_address: "user_2".to_string(),
-                amount: vec![Coin::new(71155, "uluna")]
-            }),
-            gas_limit: None,
-            reply_on: ReplyOn::Never
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_2".to_string(),
+            amount: vec![Coin::new(10200, "uluna")]
+        })
+    );
+
+    // The request stays outstanding for the undelegated Luna
+    let data: WithdrawUnbondedResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        WithdrawUnbondedResponse {
+            batch_ids: vec![2],
+            batch_ids_remaining: vec![2],
+            has_more: false,
+            amount: Uint128::new(10200),
         }
     );
 
-    // Batch 1 and user 2's unbonding request should have been purged from storage
-    let err = state.previous_batches.load(deps.as_ref().storage, 1u64.into()).unwrap_err();
+    let request = state.unbond_requests.load(deps.as_ref().storage, (2u64, "user_2")).unwrap();
+    assert!(request.held_claimed);
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(528401),
+        mock_info("user_2", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::NothingToWithdraw {});
+
+    // Once the batch finishes unbonding and is reconciled, the rest is claimable
+    deps.querier.set_bank_balances(&[Coin::new(40800, "uluna")]);
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(2342802),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(2342802),
+        mock_info("user_2", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_2".to_string(),
+            amount: vec![Coin::new(40800, "uluna")]
+        })
+    );
+    assert!(state.previous_batches.may_load(deps.as_ref().storage, 2u64).unwrap().is_none());
+    assert!(!state.unbond_requests.has(deps.as_ref().storage, (2u64, "user_2")));
+}
+
+#[test]
+fn instant_unbonding() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate: Decimal::percent(10),
+            new_fee_rate: Decimal::percent(1),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate: Decimal::percent(21),
+            new_fee_rate: Decimal::percent(1),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::BufferTargetRateTooHigh {
+            max_target_rate: Decimal::percent(20)
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate: Decimal::percent(10),
+            new_fee_rate: Decimal::percent(11),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InstantUnbondFeeRateTooHigh {
+            max_fee_rate: Decimal::percent(10)
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate: Decimal::percent(10),
+            new_fee_rate: Decimal::percent(1),
+        },
+    )
+    .unwrap();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 300000, "uluna"),
+        Delegation::new("bob", 300000, "uluna"),
+        Delegation::new("charlie", 300000, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 900000);
+
+    // Buffer target: (900,000 + 100,000) * 10% = 100,000
+    // The entire deposit goes to the buffer, so nothing is delegated
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(100000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "user_1".to_string(),
+                amount: Uint128::new(100000)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    let res: BufferResponse = query_helper(deps.as_ref(), QueryMsg::Buffer {});
+    assert_eq!(
+        res,
+        BufferResponse {
+            balance: Uint128::new(100000),
+            target_rate: Decimal::percent(10),
+            fee_rate: Decimal::percent(1),
+        }
+    );
+
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    // Only Steak token is accepted for instant unbonding
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("random_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "hacker".to_string(),
+            amount: Uint128::new(10000),
+            msg: to_binary(&ReceiveMsg::InstantUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InvalidToken {
+            sender: "random_token".to_string()
+        }
+    );
+
+    // uluna to unbond: 1,000,000 * 10,000 / 1,000,000 = 10,000
+    // fee: 10,000 * 1% = 100
+    // uluna to refund: 10,000 - 100 = 9,900
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_2".to_string(),
+            amount: Uint128::new(10000),
+            msg: to_binary(&ReceiveMsg::InstantUnbond {
+                receiver: Some("user_3".to_string()),
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                amount: Uint128::new(10000)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_3".to_string(),
+            amount: vec![Coin::new(9900, "uluna")]
+        })
+    );
+
+    // The fee stays in the buffer, so it accrues to the remaining stakers
+    let uluna_buffer = state.buffer.load(deps.as_ref().storage).unwrap();
+    assert_eq!(uluna_buffer, Uint128::new(90100));
+
+    deps.querier.set_cw20_total_supply("steak_token", 990000);
+
+    let res: StateResponse = query_helper(deps.as_ref(), QueryMsg::State {});
+    assert_eq!(res.total_uluna, Uint128::new(990100));
+    assert_eq!(res.exchange_rate, Decimal::from_ratio(990100u128, 990000u128));
+
+    // uluna to unbond: 990,100 * 100,000 / 990,000 = 100,010
+    // fee: 100,010 * 1% = 1,000
+    // uluna to refund: 100,010 - 1,000 = 99,010, more than the buffer holds
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_2".to_string(),
+            amount: Uint128::new(100000),
+            msg: to_binary(&ReceiveMsg::InstantUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InsufficientBuffer {
+            available: Uint128::new(90100),
+            required: Uint128::new(99010),
+        }
+    );
+
+    // Buffer target: (990,100 + 20,000) * 10% = 101,010
+    // To buffer: 101,010 - 90,100 = 10,910
+    // To delegate: 20,000 - 10,910 = 9,090
+    // usteak to mint: 990,000 * 20,000 / 990,100 = 19,997
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(20000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(Delegation::new("alice", 9090, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "user_1".to_string(),
+                amount: Uint128::new(19997)
+            })
+            .unwrap(),
+            funds: vec![]
+        })
+    );
+
+    let uluna_buffer = state.buffer.load(deps.as_ref().storage).unwrap();
+    assert_eq!(uluna_buffer, Uint128::new(101010));
+}
+
+#[test]
+fn submitting_batch_larger_than_staked() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate: Decimal::percent(10),
+            new_fee_rate: Decimal::percent(1),
+        },
+    )
+    .unwrap();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 300000, "uluna"),
+        Delegation::new("bob", 300000, "uluna"),
+        Delegation::new("charlie", 300000, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 900000);
+
+    // Buffer target: (900,000 + 100,000) * 10% = 100,000
+    // The entire deposit goes to the buffer
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(10000),
+        mock_info("user_1", &[Coin::new(100000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(950000),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    // uluna to unbond: (900,000 + 100,000) * 950,000 / 1,000,000 = 950,000, more than the 900,000 staked
+    // From buffer: 100,000 * 950,000 / 1,000,000 = 95,000
+    // To undelegate: 950,000 - 95,000 = 855,000
+    //
+    // Target: (900,000 - 855,000) / 3 = 15,000
+    // Alice, Bob, Charlie: 300,000 - 15,000 = 285,000
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(269201),
+        mock_info("worker", &[]),
+        ExecuteMsg::SubmitBatch {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 4);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(Undelegation::new("alice", 285000, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::reply_on_success(Undelegation::new("bob", 285000, "uluna").to_cosmos_msg(), 2)
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg::reply_on_success(Undelegation::new("charlie", 285000, "uluna").to_cosmos_msg(), 2)
+    );
+
+    let previous_batch = state.previous_batches.load(deps.as_ref().storage, 1u64).unwrap();
+    assert_eq!(
+        previous_batch,
+        Batch {
+            id: 1,
+            reconciled: false,
+            total_shares: Uint128::new(950000),
+            uluna_unclaimed: Uint128::new(855000),
+            est_unbond_end_time: 2083601, // 269,201 + 1,814,400
+            uluna_held: Uint128::new(95000),
+            held_shares: Uint128::new(950000),
+            undelegations: vec![
+                ("alice".to_string(), Uint128::new(285000)),
+                ("bob".to_string(), Uint128::new(285000)),
+                ("charlie".to_string(), Uint128::new(285000)),
+            ],
+        }
+    );
+
+    let uluna_buffer = state.buffer.load(deps.as_ref().storage).unwrap();
+    assert_eq!(uluna_buffer, Uint128::new(5000));
+
+    // The remaining stakers keep their share of both the delegations and the buffer
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 15000, "uluna"),
+        Delegation::new("bob", 15000, "uluna"),
+        Delegation::new("charlie", 15000, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 50000);
+
+    let res: StateResponse = query_helper(deps.as_ref(), QueryMsg::State {});
+    assert_eq!(res.total_uluna, Uint128::new(50000));
+    assert_eq!(res.exchange_rate, Decimal::one());
+}
+
+#[test]
+fn reconciling() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let previous_batches = vec![
+        Batch {
+            id: 1,
+            reconciled: true,
+            total_shares: Uint128::new(92876),
+            uluna_unclaimed: Uint128::new(95197), // 1.025 Luna per Steak
+            est_unbond_end_time: 10000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 2,
+            reconciled: false,
+            total_shares: Uint128::new(1345),
+            uluna_unclaimed: Uint128::new(1385), // 1.030 Luna per Steak
+            est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 3,
+            reconciled: false,
+            total_shares: Uint128::new(1456),
+            uluna_unclaimed: Uint128::new(1506), // 1.035 Luna per Steak
+            est_unbond_end_time: 30000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 4,
+            reconciled: false,
+            total_shares: Uint128::new(1567),
+            uluna_unclaimed: Uint128::new(1629), // 1.040 Luna per Steak
+            est_unbond_end_time: 40000,          // not yet finished unbonding, ignored
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+    ];
+
+    for previous_batch in &previous_batches {
+        state
+            .previous_batches
+            .save(deps.as_mut().storage, previous_batch.id, previous_batch)
+            .unwrap();
+    }
+
+    state
+        .unlocked_coins
+        .save(
+            deps.as_mut().storage,
+            &vec![
+                Coin::new(10000, "uluna"),
+                Coin::new(234, "ukrw"),
+                Coin::new(345, "uusd"),
+                Coin::new(69420, "ibc/0471F1C4E7AFD3F07702BEF6DC365268D64570F7C1FDC98EA6098DD6DE59817B"),
+            ],
+        )
+        .unwrap();
+
+    deps.querier.set_bank_balances(&[
+        Coin::new(12345, "uluna"),
+        Coin::new(234, "ukrw"),
+        Coin::new(345, "uusd"),
+        Coin::new(69420, "ibc/0471F1C4E7AFD3F07702BEF6DC365268D64570F7C1FDC98EA6098DD6DE59817B"),
+    ]);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(35000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    // Expected received: batch 2 + batch 3 = 1385 + 1506 = 2891
+    // Expected unlocked: 10000
+    // Expected: 12891
+    // Actual: 12345
+    // Shortfall: 12891 - 12345 = 456
+    //
+    // uluna per batch: 546 / 2 = 273
+    // remainder: 0
+    // batch 2: 1385 - 273 = 1112
+    // batch 3: 1506 - 273 = 1233
+    let batch = state.previous_batches.load(deps.as_ref().storage, 2u64).unwrap();
+    assert_eq!(
+        batch,
+        Batch {
+            id: 2,
+            reconciled: true,
+            total_shares: Uint128::new(1345),
+            uluna_unclaimed: Uint128::new(1112), // 1385 - 273
+            est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        }
+    );
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 3u64).unwrap();
+    assert_eq!(
+        batch,
+        Batch {
+            id: 3,
+            reconciled: true,
+            total_shares: Uint128::new(1456),
+            uluna_unclaimed: Uint128::new(1233), // 1506 - 273
+            est_unbond_end_time: 30000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        }
+    );
+
+    // Batches 1 and 4 should not have changed
+    let batch = state.previous_batches.load(deps.as_ref().storage, 1u64).unwrap();
+    assert_eq!(batch, previous_batches[0]);
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 4u64).unwrap();
+    assert_eq!(batch, previous_batches[3]);
+}
+
+#[test]
+fn reconciling_with_insurance() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let previous_batches = vec![
+        Batch {
+            id: 2,
+            reconciled: false,
+            total_shares: Uint128::new(1345),
+            uluna_unclaimed: Uint128::new(1385),
+            est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 3,
+            reconciled: false,
+            total_shares: Uint128::new(1456),
+            uluna_unclaimed: Uint128::new(1506),
+            est_unbond_end_time: 30000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+    ];
+
+    for previous_batch in &previous_batches {
+        state
+            .previous_batches
+            .save(deps.as_mut().storage, previous_batch.id, previous_batch)
+            .unwrap();
+    }
+
+    state.unlocked_coins.save(deps.as_mut().storage, &vec![Coin::new(10000, "uluna")]).unwrap();
+    state.insurance_reserve.save(deps.as_mut().storage, &Uint128::new(300)).unwrap();
+
+    deps.querier.set_bank_balances(&[Coin::new(12645, "uluna")]);
+
+    // Expected: 1385 + 1506 + 10000 + 300 = 13191
+    // Actual: 12645
+    // Shortfall: 13191 - 12645 = 546
+    // Drawn from the reserve: 300
+    // Deducted from batches: 546 - 300 = 246, i.e. 123 per batch
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(35000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/reconciled")
+            .add_attribute("ids", "2,3")
+            .add_attribute("uluna_insurance_drawn", "300")
+            .add_attribute("uluna_deducted", "246")]
+    );
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 2u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(1262)); // 1385 - 123
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 3u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(1383)); // 1506 - 123
+
+    // A new batch matures with a shortfall small enough to be fully covered by the reserve
+    state
+        .previous_batches
+        .save(
+            deps.as_mut().storage,
+            4u64,
+            &Batch {
+                id: 4,
+                reconciled: false,
+                total_shares: Uint128::new(1567),
+                uluna_unclaimed: Uint128::new(1629),
+                est_unbond_end_time: 40000,
+                uluna_held: Uint128::zero(),
+                held_shares: Uint128::zero(),
+                undelegations: vec![],
+            },
+        )
+        .unwrap();
+    state.insurance_reserve.save(deps.as_mut().storage, &Uint128::new(500)).unwrap();
+
+    // Expected: 1629 + 10000 + 500 = 12129 (batches 2 and 3 are already reconciled)
+    // Actual: 12029
+    // Shortfall: 12129 - 12029 = 100, fully covered by the reserve
+    deps.querier.set_bank_balances(&[Coin::new(12029, "uluna")]);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(45000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 4u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(1629));
+
+    let res: InsuranceResponse = query_helper(deps.as_ref(), QueryMsg::Insurance {});
+    assert_eq!(
+        res,
+        InsuranceResponse {
+            balance: Uint128::new(400),
+            insurance_rate: Decimal::zero(),
+        }
+    );
+
+    let res: Vec<InsuranceDraw> = query_helper(
+        deps.as_ref(),
+        QueryMsg::InsuranceDraws {
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![
+            InsuranceDraw {
+                id: 1,
+                time: 35000,
+                batch_ids: vec![2, 3],
+                uluna_drawn: Uint128::new(300),
+                uluna_deducted: Uint128::new(246),
+            },
+            InsuranceDraw {
+                id: 2,
+                time: 45000,
+                batch_ids: vec![4],
+                uluna_drawn: Uint128::new(100),
+                uluna_deducted: Uint128::zero(),
+            },
+        ]
+    );
+
+    let res: Vec<InsuranceDraw> = query_helper(
+        deps.as_ref(),
+        QueryMsg::InsuranceDraws {
+            start_after: Some(1),
+            limit: None,
+        },
+    );
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].id, 2);
+}
+
+#[test]
+fn reconciling_with_slashed_validators() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let previous_batches = vec![
+        Batch {
+            id: 2,
+            reconciled: false,
+            total_shares: Uint128::new(1950),
+            uluna_unclaimed: Uint128::new(2000),
+            est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![
+                ("alice".to_string(), Uint128::new(1000)),
+                ("bob".to_string(), Uint128::new(1000)),
+            ],
+        },
+        Batch {
+            id: 3,
+            reconciled: false,
+            total_shares: Uint128::new(1460),
+            uluna_unclaimed: Uint128::new(1500),
+            est_unbond_end_time: 25000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![
+                ("bob".to_string(), Uint128::new(500)),
+                ("charlie".to_string(), Uint128::new(1000)),
+            ],
+        },
+        Batch {
+            id: 4,
+            reconciled: false,
+            total_shares: Uint128::new(1170),
+            uluna_unclaimed: Uint128::new(1200),
+            est_unbond_end_time: 30000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![("charlie".to_string(), Uint128::new(1200))],
+        },
+        Batch {
+            id: 5,
+            reconciled: false,
+            total_shares: Uint128::new(780),
+            uluna_unclaimed: Uint128::new(800),
+            est_unbond_end_time: 40000, // not yet finished unbonding, ignored
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![("alice".to_string(), Uint128::new(800))],
+        },
+    ];
+
+    for previous_batch in &previous_batches {
+        state
+            .previous_batches
+            .save(deps.as_mut().storage, previous_batch.id, previous_batch)
+            .unwrap();
+    }
+
+    // Alice is slashed by 10%, Charlie by 5%, Bob is not slashed
+    for (validator, amount) in [("alice", 100000), ("bob", 50000), ("charlie", 40000)] {
+        state
+            .expected_delegations
+            .save(deps.as_mut().storage, validator, &Uint128::new(amount))
+            .unwrap();
+    }
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 90000, "uluna"),
+        Delegation::new("bob", 50000, "uluna"),
+        Delegation::new("charlie", 38000, "uluna"),
+    ]);
+
+    // Expected: 2000 + 1500 + 1200 = 4700
+    // Actual: 4489
+    // Shortfall: 4700 - 4489 = 211
+    deps.querier.set_bank_balances(&[Coin::new(4489, "uluna")]);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(35000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    // Expected losses:
+    // batch 2: 1000 * 10% = 100 (Bob was not slashed)
+    // batch 3: 1000 * 5% = 50 (Bob was not slashed)
+    // batch 4: 1200 * 5% = 60
+    // Total: 210
+    //
+    // batch 2: 211 * 100 / 210 = 100, plus remainder 1 = 101
+    // batch 3: 211 * 50 / 210 = 50
+    // batch 4: 211 * 60 / 210 = 60
+    let batch = state.previous_batches.load(deps.as_ref().storage, 2u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(1899)); // 2000 - 101
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 3u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(1450)); // 1500 - 50
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 4u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(1140)); // 1200 - 60
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 5u64).unwrap();
+    assert_eq!(batch, previous_batches[3]);
+
+    // Charlie's slash has been fully accounted for, so his expected delegation is reset. Batch 5 is still
+    // unbonding from Alice, so her slash is kept to be attributed when batch 5 is reconciled
+    let uluna_expected = state.expected_delegations.load(deps.as_ref().storage, "alice").unwrap();
+    assert_eq!(uluna_expected, Uint128::new(100000));
+
+    let uluna_expected = state.expected_delegations.load(deps.as_ref().storage, "charlie").unwrap();
+    assert_eq!(uluna_expected, Uint128::new(38000));
+
+    // Batch 5 finishes unbonding, 80 short due to Alice's slash. Only batch 5 was unbonding, so it
+    // takes the entire deduction
+    deps.querier.set_bank_balances(&[Coin::new(720, "uluna")]); // 800 - 80
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(45000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 5u64).unwrap();
+    assert!(batch.reconciled);
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(720)); // 800 - 80
+
+    let uluna_expected = state.expected_delegations.load(deps.as_ref().storage, "alice").unwrap();
+    assert_eq!(uluna_expected, Uint128::new(90000));
+}
+
+#[test]
+fn reconciling_with_large_shortfalls() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let unreconciled_batch = |id: u64, validator: &str, amount: u128, est_unbond_end_time: u64| Batch {
+    uluna_held: Uint128::zero(),
+    held_shares: Uint128::zero(),
+        id,
+        reconciled: false,
+        total_shares: Uint128::new(amount),
+        uluna_unclaimed: Uint128::new(amount),
+        est_unbond_end_time,
+        undelegations: vec![(validator.to_string(), Uint128::new(amount))],
+    };
+
+    for batch in [unreconciled_batch(2, "alice", 1000, 20000), unreconciled_batch(3, "bob", 1000, 20000)] {
+        state.previous_batches.save(deps.as_mut().storage, batch.id, &batch).unwrap();
+    }
+
+    // Alice is slashed by 50%. Charlie is slashed by 10%, but no batch was unbonding from him
+    for (validator, amount) in [("alice", 2000), ("bob", 5000), ("charlie", 10000)] {
+        state
+            .expected_delegations
+            .save(deps.as_mut().storage, validator, &Uint128::new(amount))
+            .unwrap();
+    }
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 1000, "uluna"),
+        Delegation::new("bob", 5000, "uluna"),
+        Delegation::new("charlie", 9000, "uluna"),
+    ]);
+
+    // Expected: 1000 + 1000 = 2000
+    // Actual: 800
+    // Shortfall: 2000 - 800 = 1200
+    deps.querier.set_bank_balances(&[Coin::new(800, "uluna")]);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    // Only batch 2 is exposed to Alice, but it cannot lose more than the 1000 it has. The remaining
+    // 200 is deducted from batch 3
+    let batch = state.previous_batches.load(deps.as_ref().storage, 2u64).unwrap();
+    assert_eq!(batch.uluna_unclaimed, Uint128::zero());
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 3u64).unwrap();
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(800));
+
+    // Both Alice's and Charlie's slashes have been accounted for, so their expected delegations are
+    // reset, even though no batch was exposed to Charlie
+    let uluna_expected = state.expected_delegations.load(deps.as_ref().storage, "alice").unwrap();
+    assert_eq!(uluna_expected, Uint128::new(1000));
+
+    let uluna_expected = state.expected_delegations.load(deps.as_ref().storage, "charlie").unwrap();
+    assert_eq!(uluna_expected, Uint128::new(9000));
+
+    // Batches 4 and 5, unbonding from Charlie and Bob respectively, finish 20 short due to rounding.
+    // Charlie's earlier slash is not attributed to batch 4, so the deduction is split evenly
+    for batch in [unreconciled_batch(4, "charlie", 500, 30000), unreconciled_batch(5, "bob", 500, 30000)] {
+        state.previous_batches.save(deps.as_mut().storage, batch.id, &batch).unwrap();
+    }
+    deps.querier.set_bank_balances(&[Coin::new(980, "uluna")]);
+
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(35000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 4u64).unwrap();
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(490));
+
+    let batch = state.previous_batches.load(deps.as_ref().storage, 5u64).unwrap();
+    assert_eq!(batch.uluna_unclaimed, Uint128::new(490));
+}
+
+#[test]
+fn recording_exchange_rates() {
+    let mut deps = setup_test();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 333334, "uluna"),
+        Delegation::new("bob", 333333, "uluna"),
+        Delegation::new("charlie", 333333, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    // No snapshot has been recorded yet
+    let err = query(
+        deps.as_ref(),
+        mock_env_at_timestamp(10000),
+        QueryMsg::TwapExchangeRate {
+            window_seconds: 3600,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, StdError::generic_err("no exchange rate snapshot has been recorded"));
+
+    // Record snapshots at:
+    // - 10000, exchange rate 1
+    // - 11000, exchange rate 1.25
+    // - 12000, exchange rate 1; less than 1 hour after the first snapshot, so it replaces the second
+    for (timestamp, usteak_supply) in [(10000, 1000000), (11000, 800000), (12000, 1000000)] {
+        deps.querier.set_cw20_total_supply("steak_token", usteak_supply);
+        execute(
+            deps.as_mut(),
+            mock_env_at_timestamp(timestamp),
+            mock_info("worker", &[]),
+            ExecuteMsg::Reconcile {},
+        )
+        .unwrap();
+    }
+
+    let res: Vec<ExchangeRateSnapshot> = query_helper(
+        deps.as_ref(),
+        QueryMsg::ExchangeRates {
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![
+            ExchangeRateSnapshot {
+                time: 10000,
+                total_uluna: Uint128::new(1000000),
+                total_usteak: Uint128::new(1000000),
+                exchange_rate: Decimal::one(),
+                cumulative_exchange_rate: Decimal::zero(),
+            },
+            ExchangeRateSnapshot {
+                time: 12000,
+                total_uluna: Uint128::new(1000000),
+                total_usteak: Uint128::new(1000000),
+                exchange_rate: Decimal::one(),
+                cumulative_exchange_rate: Decimal::from_ratio(2250u128, 1u128), // 1 * 1000 + 1.25 * 1000
+            },
+        ]
+    );
+
+    // Time-weighted average over the last 4000 seconds:
+    // (1 * 1000 + 1.25 * 1000 + 1 * 2000) / 4000 = 1.0625
+    let res: TwapExchangeRateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(14000),
+            QueryMsg::TwapExchangeRate {
+                window_seconds: 4000,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        TwapExchangeRateResponse {
+            exchange_rate: Decimal::from_str("1.0625").unwrap(),
+            start_time: 10000,
+            end_time: 14000,
+        }
+    );
+
+    // A window going further back than all snapshots starts from the earliest one
+    let res: TwapExchangeRateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(14000),
+            QueryMsg::TwapExchangeRate {
+                window_seconds: 100000,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.exchange_rate, Decimal::from_str("1.0625").unwrap());
+    assert_eq!(res.start_time, 10000);
+
+    // A window starting after the latest snapshot gives the latest exchange rate
+    let res: TwapExchangeRateResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(14000),
+            QueryMsg::TwapExchangeRate {
+                window_seconds: 1000,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.exchange_rate, Decimal::one());
+    assert_eq!(res.start_time, 12000);
+
+    // Snapshots older than 30 days are pruned when a new one is recorded
+    execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(2700000),
+        mock_info("worker", &[]),
+        ExecuteMsg::Reconcile {},
+    )
+    .unwrap();
+
+    let res: Vec<ExchangeRateSnapshot> = query_helper(
+        deps.as_ref(),
+        QueryMsg::ExchangeRates {
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![ExchangeRateSnapshot {
+            time: 2700000,
+            total_uluna: Uint128::new(1000000),
+            total_usteak: Uint128::new(1000000),
+            exchange_rate: Decimal::one(),
+            cumulative_exchange_rate: Decimal::from_ratio(2690250u128, 1u128), // 2250 + 1 * 2688000
+        }]
+    );
+}
+
+#[test]
+fn querying_apr() {
+    let mut deps = setup_test();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 333334, "uluna"),
+        Delegation::new("bob", 333333, "uluna"),
+        Delegation::new("charlie", 333333, "uluna"),
+    ]);
+
+    // Harvest 10000 uluna 20 days ago, then 2000 uluna twice within the same hour 1 day ago. After the
+    // 5% protocol fee, 9500 and 3800 uluna accrue to Steak holders
+    for (timestamp, amount) in [(1272000, 10000), (2913600, 2000), (2913700, 2000)] {
+        State::default()
+            .unlocked_coins
+            .save(deps.as_mut().storage, &vec![Coin::new(amount, "uluna")])
+            .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env_at_timestamp(timestamp),
+            mock_info(MOCK_CONTRACT_ADDR, &[]),
+            ExecuteMsg::Callback(CallbackMsg::Reinvest {}),
+        )
+        .unwrap();
+    }
+
+    let res: Vec<HarvestRecord> = query_helper(
+        deps.as_ref(),
+        QueryMsg::Harvests {
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![
+            HarvestRecord {
+                time: 1270800,
+                uluna_harvested: Uint128::new(10000),
+                uluna_bonded: Uint128::new(9500),
+            },
+            HarvestRecord {
+                time: 2912400,
+                uluna_harvested: Uint128::new(4000),
+                uluna_bonded: Uint128::new(3800),
+            },
+        ]
+    );
+
+    // 7-day yield: 3800 / 1000000 * 365 / 7
+    // 30-day yield: (9500 + 3800) / 1000000 * 365 / 30
+    let res: AprResponse = from_binary(
+        &query(deps.as_ref(), mock_env_at_timestamp(3000000), QueryMsg::Apr {}).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        AprResponse {
+            apr_7d: Decimal::from_ratio(3800u128 * 365, 1000000u128 * 7),
+            apr_30d: Decimal::from_ratio(13300u128 * 365, 1000000u128 * 30),
+        }
+    );
+
+    // Once all harvests are older than 30 days, the yield is zero
+    let res: AprResponse = from_binary(
+        &query(deps.as_ref(), mock_env_at_timestamp(6000000), QueryMsg::Apr {}).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        AprResponse {
+            apr_7d: Decimal::zero(),
+            apr_30d: Decimal::zero(),
+        }
+    );
+}
+
+#[test]
+fn funding_insurance() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdateInsuranceRate {
+            new_insurance_rate: Decimal::percent(2),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateInsuranceRate {
+            new_insurance_rate: Decimal::percent(11),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InsuranceRateTooHigh {
+            max_insurance_rate: Decimal::percent(10)
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateInsuranceRate {
+            new_insurance_rate: Decimal::percent(2),
+        },
+    )
+    .unwrap();
+
+    // Anyone can deposit into the reserve, but only in the bond denom
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[Coin::new(1000, "uatom")]),
+        ExecuteMsg::DepositInsurance {},
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InvalidDenom {
+            expected: "uluna".to_string(),
+            received: "uatom".to_string()
+        }
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("jake", &[Coin::new(1000, "uluna")]),
+        ExecuteMsg::DepositInsurance {},
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/insurance_deposited")
+            .add_attribute("time", "20000")
+            .add_attribute("height", "12345")
+            .add_attribute("depositor", "jake")
+            .add_attribute("uluna_deposited", "1000")]
+    );
+
+    // Harvest 234 uluna
+    // Protocol fee: 234 * 0.05 = 11
+    // Insurance: 234 * 0.02 = 4
+    // Bonded: 234 - 11 - 4 = 219
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 333334, "uluna"),
+        Delegation::new("bob", 333333, "uluna"),
+        Delegation::new("charlie", 333333, "uluna"),
+    ]);
+    state.unlocked_coins.save(deps.as_mut().storage, &vec![Coin::new(234, "uluna")]).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(MOCK_CONTRACT_ADDR, &[]),
+        ExecuteMsg::Callback(CallbackMsg::Reinvest {}),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages[0].msg, Delegation::new("bob", 219, "uluna").to_cosmos_msg());
+    assert_eq!(
+        res.messages[1].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "the_fee_man".to_string(),
+            amount: vec![Coin::new(11, "uluna")]
+        })
+    );
+
+    let res: InsuranceResponse = query_helper(deps.as_ref(), QueryMsg::Insurance {});
+    assert_eq!(
+        res,
+        InsuranceResponse {
+            balance: Uint128::new(1004),
+            insurance_rate: Decimal::percent(2),
+        }
+    );
+}
+
+#[test]
+fn withdrawing_unbonded() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    // We simulate a most general case:
+    // - batches 1 and 2 have finished unbonding
+    // - batch 3 have been submitted for unbonding but have not finished
+    // - batch 4 is still pending
+    let unbond_requests = vec![
+        UnbondRequest {
+            id: 1,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(23456),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 1,
+            user: Addr::unchecked("user_3"),
+            shares: Uint128::new(69420),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 2,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(34567),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 3,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(45678),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 4,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(56789),
+            seq: 0,
+            held_claimed: false,
+        },
+    ];
+
+    for unbond_request in &unbond_requests {
+        state
+            .unbond_requests
+            .save(
+                deps.as_mut().storage,
+                (unbond_request.id, &unbond_request.key()),
+                unbond_request,
+            )
+            .unwrap();
+    }
+
+    let previous_batches = vec![
+        Batch {
+            id: 1,
+            reconciled: true,
+            total_shares: Uint128::new(92876),
+            uluna_unclaimed: Uint128::new(95197), // 1.025 Luna per Steak
+            est_unbond_end_time: 10000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 2,
+            reconciled: true,
+            total_shares: Uint128::new(34567),
+            uluna_unclaimed: Uint128::new(35604), // 1.030 Luna per Steak
+            est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 3,
+            reconciled: false, // finished unbonding, but not reconciled; ignored
+            total_shares: Uint128::new(45678),
+            uluna_unclaimed: Uint128::new(47276), // 1.035 Luna per Steak
+            est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 4,
+            reconciled: true,
+            total_shares: Uint128::new(56789),
+            uluna_unclaimed: Uint128::new(59060), // 1.040 Luna per Steak
+            est_unbond_end_time: 30000, // reconciled, but not yet finished unbonding; ignored
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+    ];
+
+    for previous_batch in &previous_batches {
+        state
+            .previous_batches
+            .save(deps.as_mut().storage, previous_batch.id, previous_batch)
+            .unwrap();
+    }
+
+    state
+        .pending_batch
+        .save(
+            deps.as_mut().storage,
+            &PendingBatch {
+                id: 4,
+                usteak_to_burn: Uint128::new(56789),
+                uluna_to_bond: Uint128::zero(),
+                est_unbond_start_time: 100000,
+            },
+        )
+        .unwrap();
+
+    // Attempt to withdraw before any batch has completed unbonding. Should error
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(5000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::NothingToWithdraw {});
+
+    // Attempt to withdraw once batches 1 and 2 have finished unbonding, but 3 has not yet
+    //
+    // Withdrawable from batch 1: 95,197 * 23,456 / 92,876 = 24,042
+    // Withdrawable from batch 2: 35,604
+    // Total withdrawable: 24,042 + 35,604 = 59,646
+    //
+    // Batch 1 should be updated:
+    // Total shares: 92,876 - 23,456 = 69,420
+    // Unclaimed uluna: 95,197 - 24,042 = 71,155
+    //
+    // Batch 2 is completely withdrawn, should be purged from storage
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user_1".to_string(),
+                amount: vec![Coin::new(59646, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+
+    // Previous batches should have been updated
+    let batch = state.previous_batches.load(deps.as_ref().storage, 1u64).unwrap();
+    assert_eq!(
+        batch,
+        Batch {
+            id: 1,
+            reconciled: true,
+            total_shares: Uint128::new(69420),
+            uluna_unclaimed: Uint128::new(71155),
+            est_unbond_end_time: 10000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        }
+    );
+
+    let err = state.previous_batches.load(deps.as_ref().storage, 2u64).unwrap_err();
+    assert_eq!(
+        err,
+        StdError::NotFound { kind: "steak::hub::Batch".to_string() }
+    );
+
+    // User 1's unbond requests in batches 1 and 2 should have been deleted
+    let err1 = state
+        .unbond_requests
+        .load(deps.as_ref().storage, (1u64, "user_1"))
+        .unwrap_err();
+    let err2 = state
+        .unbond_requests
+        .load(deps.as_ref().storage, (1u64, "user_1"))
+        .unwrap_err();
+
+    assert_eq!(
+        err1,
+        StdError::NotFound { kind: "steak::hub::UnbondRequest".to_string() }
+    );
+    assert_eq!(
+        err2,
+        StdError::NotFound { kind: "steak::hub::UnbondRequest".to_string() }
+    );
+
+    // User 3 attempt to withdraw; also specifying a receiver
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_3", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: Some("user_2".to_string()),
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user_2".to_string(),
+                amount: vec![Coin::new(71155, "uluna")]
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Never
+        }
+    );
+
+    // Batch 1 and user 2's unbonding request should have been purged from storage
+    let err = state.previous_batches.load(deps.as_ref().storage, 1u64).unwrap_err();
+    assert_eq!(
+        err,
+        StdError::NotFound {
+            kind: "steak::hub::Batch".to_string()
+        }
+    );
+
+    let err = state
+        .unbond_requests
+        .load(deps.as_ref().storage, (1u64, "user_3"))
+        .unwrap_err();
+
+    assert_eq!(
+        err,
+        StdError::NotFound { kind: "steak::hub::UnbondRequest".to_string() }
+    );
+}
+
+#[test]
+fn withdrawing_unbonded_selectively() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    // User 1 has requests in batches 1, 2 and 3, which have all finished unbonding, and batch 4, which
+    // is still pending
+    for id in 1u64..=4 {
+        state
+            .unbond_requests
+            .save(
+                deps.as_mut().storage,
+                (id, "user_1"),
+                &UnbondRequest {
+                    id,
+                    user: Addr::unchecked("user_1"),
+                    shares: Uint128::new(100),
+                    seq: 0,
+                    held_claimed: false,
+                },
+            )
+            .unwrap();
+    }
+
+    for id in 1u64..=3 {
+        state
+            .previous_batches
+            .save(
+                deps.as_mut().storage,
+                id,
+                &Batch {
+                    id,
+                    reconciled: true,
+                    total_shares: Uint128::new(100),
+                    uluna_unclaimed: Uint128::new(100 + id as u128),
+                    est_unbond_end_time: 20000,
+                    uluna_held: Uint128::zero(),
+                    held_shares: Uint128::zero(),
+                    undelegations: vec![],
+                },
+            )
+            .unwrap();
+    }
+
+    // Withdraw only batch 2. Duplicate IDs are ignored
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: Some(vec![2, 2]),
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_1".to_string(),
+            amount: vec![Coin::new(102, "uluna")],
+        }))
+    );
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/unbonded_withdrawn")
+            .add_attribute("time", "25000")
+            .add_attribute("height", "12345")
+            .add_attribute("ids", "2")
+            .add_attribute("ids_remaining", "")
+            .add_attribute("user", "user_1")
+            .add_attribute("receiver", "user_1")
+            .add_attribute("uluna_refunded", "102")]
+    );
+
+    // Withdraw at most one request. Batch 1 is withdrawn, and batches 3 and 4 are left unexamined
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: Some(1),
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_1".to_string(),
+            amount: vec![Coin::new(101, "uluna")],
+        }))
+    );
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/unbonded_withdrawn")
+            .add_attribute("time", "25000")
+            .add_attribute("height", "12345")
+            .add_attribute("ids", "1")
+            .add_attribute("ids_remaining", "")
+            .add_attribute("user", "user_1")
+            .add_attribute("receiver", "user_1")
+            .add_attribute("uluna_refunded", "101")]
+    );
+
+    let data: WithdrawUnbondedResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        WithdrawUnbondedResponse {
+            batch_ids: vec![1],
+            batch_ids_remaining: vec![],
+            has_more: true,
+            amount: Uint128::new(101),
+        }
+    );
+
+    // Requesting a batch that is not yet withdrawable fails
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: Some(vec![4]),
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::NothingToWithdraw {});
+
+    let requests: Vec<UnbondRequestsByUserResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByUser {
+            user: "user_1".to_string(),
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(requests.iter().map(|r| r.id).collect::<Vec<_>>(), vec![3, 4]);
+}
+
+#[test]
+fn withdrawing_unbonded_with_hook() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    // A vault contract has queued requests in batches 1 and 2, which have both finished unbonding
+    for id in 1u64..=2 {
+        state
+            .unbond_requests
+            .save(
+                deps.as_mut().storage,
+                (id, "vault"),
+                &UnbondRequest {
+                    id,
+                    user: Addr::unchecked("vault"),
+                    shares: Uint128::new(100),
+                    seq: 0,
+                    held_claimed: false,
+                },
+            )
+            .unwrap();
+        state
+            .previous_batches
+            .save(
+                deps.as_mut().storage,
+                id,
+                &Batch {
+                    id,
+                    reconciled: true,
+                    total_shares: Uint128::new(100),
+                    uluna_unclaimed: Uint128::new(100 + id as u128),
+                    est_unbond_end_time: 20000,
+                    uluna_held: Uint128::zero(),
+                    held_shares: Uint128::zero(),
+                    undelegations: vec![],
+                },
+            )
+            .unwrap();
+    }
+
+    // The vault should be executed with the hook message, along with the Luna
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("vault", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: true,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "vault".to_string(),
+            msg: to_binary(&UnbondedHookMsg::SteakUnbondedHook {
+                batch_ids: vec![1, 2],
+                amount: Uint128::new(203),
+            })
+            .unwrap(),
+            funds: vec![Coin::new(203, "uluna")],
+        }))
+    );
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "vault".to_string(),
+            msg: Binary::from(br#"{"steak_unbonded_hook":{"batch_ids":[1,2],"amount":"203"}}"#),
+            funds: vec![Coin::new(203, "uluna")],
+        })
+    );
+}
+
+#[test]
+fn unbonding_with_nfts() {
+    let mut deps = mock_dependencies();
+    let state = State::default();
+
+    let res = instantiate(
+        deps.as_mut(),
+        mock_env_at_timestamp(10000),
+        mock_info("deployer", &[]),
+        InstantiateMsg {
+            cw20_code_id: 69420,
+            cw721_code_id: Some(69421),
+            owner: "larry".to_string(),
+            name: "Steak Token".to_string(),
+            symbol: "STEAK".to_string(),
+            decimals: 6,
+            denom: "uluna".to_string(),
+            epoch_period: 259200,
+            unbond_period: 1814400,
+            validators: vec!["alice".to_string(), "bob".to_string(), "charlie".to_string()],
+            fee_account: "the_fee_man".to_string(),
+            fee_rate: Decimal::percent(5),
+            max_total_bonded: None,
+            max_bonded_per_address: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[1],
+        SubMsg::reply_on_success(
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                admin: Some("larry".to_string()),
+                code_id: 69421,
+                msg: to_binary(&Cw721InstantiateMsg {
+                    name: "Steak Token Unbonding Request".to_string(),
+                    symbol: "STEAK-UNBOND".to_string(),
+                    minter: MOCK_CONTRACT_ADDR.to_string(),
+                })
+                .unwrap(),
+                funds: vec![],
+                label: "steak_unbond_nft".to_string(),
+            }),
+            3
+        )
+    );
+
+    for (id, contract_addr) in [(1, "steak_token"), (3, "unbond_nft")] {
+        reply(
+            deps.as_mut(),
+            mock_env_at_timestamp(10000),
+            Reply {
+                id,
+                result: cosmwasm_std::SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![Event::new("instantiate")
+                        .add_attribute("code_id", "69420")
+                        .add_attribute("_contract_address", contract_addr)],
+                    data: None,
+                }),
+            },
+        )
+        .unwrap();
+    }
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(res.unbond_nft, Some("unbond_nft".to_string()));
+
+    // Queuing an unbonding request mints an NFT to the receiver
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "unbond_nft".to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::<Extension>::Mint(MintMsg {
+                token_id: "1/user_1".to_string(),
+                owner: "user_1".to_string(),
+                token_uri: None,
+                extension: None,
+            }))
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    deps.querier.set_cw721_owner("1/user_1", "user_1");
+
+    // Adding to the request while still owning the NFT does not mint another one
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(50),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    // User 1 transfers the NFT to user 2. User 1 can no longer cancel the request
+    deps.querier.set_cw721_owner("1/user_1", "user_2");
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(150),
+            token_id: Some("1/user_1".to_string()),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::UnbondNftNotOwned {
+            token_id: "1/user_1".to_string(),
+        }
+    );
+
+    // A token ID that does not identify a request is rejected
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(150),
+            token_id: Some("user_1".to_string()),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::InvalidTokenId {
+            token_id: "user_1".to_string(),
+        }
+    );
+
+    // If user 1 queues more Steak, it goes into a new request with a new NFT, rather than the one now
+    // owned by user 2
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(70),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "unbond_nft".to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::<Extension>::Mint(MintMsg {
+                token_id: "1/user_1/1".to_string(),
+                owner: "user_1".to_string(),
+                token_uri: None,
+                extension: None,
+            }))
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    deps.querier.set_cw721_owner("1/user_1/1", "user_1");
+
+    let request = state.unbond_requests.load(deps.as_ref().storage, (1u64, "user_1")).unwrap();
+    assert_eq!(request.shares, Uint128::new(150));
+    let request = state.unbond_requests.load(deps.as_ref().storage, (1u64, "user_1/1")).unwrap();
+    assert_eq!(
+        request,
+        UnbondRequest {
+            id: 1,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(70),
+            seq: 1,
+            held_claimed: false,
+        }
+    );
+
+    // The new request is listed under user 1, and can be paged past by its sequence number
+    let res: Vec<UnbondRequestsByUserResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByUser {
+            user: "user_1".to_string(),
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![
+            UnbondRequestsByUserResponseItem {
+                id: 1,
+                shares: Uint128::new(150),
+                seq: 0,
+            },
+            UnbondRequestsByUserResponseItem {
+                id: 1,
+                shares: Uint128::new(70),
+                seq: 1,
+            },
+        ]
+    );
+
+    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByBatch {
+            id: 1,
+            start_after: Some("user_1".to_string()),
+            start_after_seq: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![UnbondRequestsByBatchResponseItem {
+            user: "user_1".to_string(),
+            shares: Uint128::new(70),
+            seq: 1,
+        }]
+    );
+
+    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByBatch {
+            id: 1,
+            start_after: Some("user_1".to_string()),
+            start_after_seq: Some(1),
+            limit: None,
+        },
+    );
+    assert!(res.is_empty());
+
+    // User 1 cancels the new request, which defaults to the one they own
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("user_1", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(70),
+            token_id: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "user_1".to_string(),
+                amount: Uint128::new(70),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "unbond_nft".to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::<Extension>::Burn {
+                token_id: "1/user_1/1".to_string(),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    deps.querier.remove_cw721_token("1/user_1/1");
+    assert!(!state.unbond_requests.has(deps.as_ref().storage, (1u64, "user_1/1")));
+
+    // User 2, the current owner of the transferred NFT, can cancel part of the request and receives the
+    // Steak
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("user_2", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(50),
+            token_id: Some("0/user_1".to_string()),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::UnbondRequestNotPending {
+            token_id: "0/user_1".to_string(),
+        }
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(12345),
+        mock_info("user_2", &[]),
+        ExecuteMsg::CancelUnbond {
+            amount: Uint128::new(50),
+            token_id: Some("1/user_1".to_string()),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 1);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "steak_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "user_2".to_string(),
+                amount: Uint128::new(50),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    let request = state.unbond_requests.load(deps.as_ref().storage, (1u64, "user_1")).unwrap();
+    assert_eq!(request.shares, Uint128::new(100));
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
+    assert_eq!(pending_batch.usteak_to_burn, Uint128::new(100));
+
+    // The batch is submitted and finishes unbonding
+    state
+        .pending_batch
+        .save(
+            deps.as_mut().storage,
+            &PendingBatch {
+                id: 2,
+                usteak_to_burn: Uint128::zero(),
+                uluna_to_bond: Uint128::zero(),
+                est_unbond_start_time: 528401,
+            },
+        )
+        .unwrap();
+    state
+        .previous_batches
+        .save(
+            deps.as_mut().storage,
+            1u64,
+            &Batch {
+                id: 1,
+                reconciled: true,
+                total_shares: Uint128::new(100),
+                uluna_unclaimed: Uint128::new(102),
+                est_unbond_end_time: 20000,
+                uluna_held: Uint128::zero(),
+                held_shares: Uint128::zero(),
+                undelegations: vec![],
+            },
+        )
+        .unwrap();
+
+    // The unbonding summary follows NFT ownership as well
+    let res: UnbondingSummaryResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondingSummary {
+            user: "user_1".to_string(),
+        },
+    );
+    assert!(res.requests.is_empty());
+    assert_eq!(res.total_withdrawable, Uint128::zero());
+
+    let res: UnbondingSummaryResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondingSummary {
+            user: "user_2".to_string(),
+        },
+    );
+    assert_eq!(
+        res,
+        UnbondingSummaryResponse {
+            requests: vec![UnbondingSummaryItem {
+                id: 1,
+                shares: Uint128::new(100),
+                status: UnbondStatus::Claimable,
+                est_uluna: Uint128::new(102),
+                est_unbond_end_time: 20000,
+            }],
+            total_withdrawable: Uint128::new(102),
+        }
+    );
+
+    // User 1 no longer owns the NFT, so has nothing to withdraw
+    let err = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::NothingToWithdraw {});
+
+    // User 2 withdraws the unbonded Luna; the NFT is burned
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_2", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_2".to_string(),
+            amount: vec![Coin::new(102, "uluna")],
+        }))
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "unbond_nft".to_string(),
+            msg: to_binary(&Cw721ExecuteMsg::<Extension>::Burn {
+                token_id: "1/user_1".to_string(),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    assert!(!state.previous_batches.has(deps.as_ref().storage, 1u64));
+    assert!(!state.unbond_requests.has(deps.as_ref().storage, (1u64, "user_1")));
+}
+
+#[test]
+fn withdrawing_unbonded_nfts_in_pages() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    state.unbond_nft.save(deps.as_mut().storage, &Addr::unchecked("unbond_nft")).unwrap();
+
+    // User 1 queued requests in batches 1-13, and sold all of the NFTs to user 2. Batch 13 has not been
+    // reconciled yet
+    for id in 1u64..=13 {
+        let request = UnbondRequest {
+            id,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(100),
+            seq: 0,
+            held_claimed: false,
+        };
+        state.unbond_requests.save(deps.as_mut().storage, (id, &request.key()), &request).unwrap();
+        state
+            .previous_batches
+            .save(
+                deps.as_mut().storage,
+                id,
+                &Batch {
+                    id,
+                    reconciled: id != 13,
+                    total_shares: Uint128::new(100),
+                    uluna_unclaimed: Uint128::new(101),
+                    est_unbond_end_time: 20000,
+                    uluna_held: Uint128::zero(),
+                    held_shares: Uint128::zero(),
+                    undelegations: vec![],
+                },
+            )
+            .unwrap();
+        deps.querier.set_cw721_owner(&request.token_id(), "user_2");
+    }
+
+    // The NFTs are paged through in the order of their token IDs, 10 per page:
+    // Page 1: 1, 10, 11, 12, 13, 2, 3, 4, 5, 6
+    // Page 2: 7, 8, 9
+    // Batch 13 is skipped, and paging stops once 11 requests are withdrawn, before batch 9
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_2", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: Some(11),
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 12);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_2".to_string(),
+            amount: vec![Coin::new(1111, "uluna")],
+        }))
+    );
+
+    let data: WithdrawUnbondedResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        WithdrawUnbondedResponse {
+            batch_ids: vec![1, 10, 11, 12, 2, 3, 4, 5, 6, 7, 8],
+            batch_ids_remaining: vec![13],
+            has_more: true,
+            amount: Uint128::new(1111),
+        }
+    );
+
+    for id in data.batch_ids {
+        deps.querier.remove_cw721_token(&format!("{}/user_1", id));
+    }
+
+    // Only the requests in the specified batches are withdrawn
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_2", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: Some(vec![9, 13]),
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    let data: WithdrawUnbondedResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        WithdrawUnbondedResponse {
+            batch_ids: vec![9],
+            batch_ids_remaining: vec![13],
+            has_more: false,
+            amount: Uint128::new(101),
+        }
+    );
+}
+
+#[test]
+fn withdrawing_unbonded_in_pages() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    // User 1 queued requests in batches 1-35, more than are loaded in one page
+    for id in 1u64..=35 {
+        let request = UnbondRequest {
+            id,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(100),
+            seq: 0,
+            held_claimed: false,
+        };
+        state.unbond_requests.save(deps.as_mut().storage, (id, &request.key()), &request).unwrap();
+        state
+            .previous_batches
+            .save(
+                deps.as_mut().storage,
+                id,
+                &Batch {
+                    id,
+                    reconciled: true,
+                    total_shares: Uint128::new(100),
+                    uluna_unclaimed: Uint128::new(101),
+                    est_unbond_end_time: 20000,
+                    uluna_held: Uint128::zero(),
+                    held_shares: Uint128::zero(),
+                    undelegations: vec![],
+                },
+            )
+            .unwrap();
+    }
+
+    // Paging stops once 31 requests are withdrawn
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: Some(31),
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    let data: WithdrawUnbondedResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        WithdrawUnbondedResponse {
+            batch_ids: (1u64..=31).collect(),
+            batch_ids_remaining: vec![],
+            has_more: true,
+            amount: Uint128::new(3131),
+        }
+    );
+
+    // Without a limit, the rest are withdrawn
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(25000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    let data: WithdrawUnbondedResponse = from_binary(&res.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        WithdrawUnbondedResponse {
+            batch_ids: vec![32, 33, 34, 35],
+            batch_ids_remaining: vec![],
+            has_more: false,
+            amount: Uint128::new(404),
+        }
+    );
+}
+
+#[test]
+fn adding_validator() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::AddValidator {
+            validator: "dave".to_string(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::AddValidator {
+            validator: "alice".to_string(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::ValidatorAlreadyWhitelisted {
+            validator: "alice".to_string()
+        }
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::AddValidator {
+            validator: "dave".to_string(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    let validators = state.validators.load(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        validators,
+        vec![
+            (String::from("alice"), 1),
+            (String::from("bob"), 1),
+            (String::from("charlie"), 1),
+            (String::from("dave"), 1)
+        ],
+    );
+}
+
+#[test]
+fn removing_validator() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
+    ]);
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::RemoveValidator {
+            validator: "charlie".to_string(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::RemoveValidator {
+            validator: "dave".to_string(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::ValidatorNotWhitelisted {
+            validator: "dave".to_string()
+        }
+    );
+
+    // Target: (341667 + 341667 + 341666) / 2 = 512500
+    // Remainder: 0
+    // Alice:   512500 + 0 - 341667 = 170833
+    // Bob:     512500 + 0 - 341667 = 170833
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::RemoveValidator {
+            validator: "charlie".to_string(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 2);
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(Redelegation::new("charlie", "alice", 170833, "uluna").to_cosmos_msg(), 2),
+    );
+    assert_eq!(
+        res.messages[1],
+        SubMsg::reply_on_success(Redelegation::new("charlie", "bob", 170833, "uluna").to_cosmos_msg(), 2),
+    );
+
+    let validators = state.validators.load(deps.as_ref().storage).unwrap();
+    assert_eq!(validators, vec![(String::from("alice"), 1), (String::from("bob"), 1)],);
+}
+
+#[test]
+fn setting_validator_weights() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::SetValidatorWeights {
+            weights: vec![("alice".to_string(), 2)],
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetValidatorWeights {
+            weights: vec![("alice".to_string(), 2), ("dave".to_string(), 3)],
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::ValidatorNotWhitelisted {
+            validator: "dave".to_string()
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetValidatorWeights {
+            weights: vec![("alice".to_string(), 0)],
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::ZeroWeight {
+            validator: "alice".to_string()
+        }
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetValidatorWeights {
+            weights: vec![("alice".to_string(), 2), ("charlie".to_string(), 5)],
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/validator_weights_updated")
+            .add_attribute("alice", "2")
+            .add_attribute("charlie", "5")]
+    );
+
+    let validators = state.validators.load(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        validators,
+        vec![(String::from("alice"), 2), (String::from("bob"), 1), (String::from("charlie"), 5)],
+    );
+
+    // Total after bonding: 1000 + 1200 + 4000 + 800 = 7000
+    // Alice:   7000 * 2 / 8 - 1000 = 750
+    // Bob:     7000 * 1 / 8 - 1200 = -325
+    // Charlie: 7000 * 5 / 8 - 4000 = 375
+    // Alice is the furthest below her target, even though Bob has a smaller delegation
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 1000, "uluna"),
+        Delegation::new("bob", 1200, "uluna"),
+        Delegation::new("charlie", 4000, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 6200);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(800, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages[0],
+        SubMsg::reply_on_success(Delegation::new("alice", 800, "uluna").to_cosmos_msg(), 2)
+    );
+}
+
+#[test]
+fn setting_swap_route() {
+    let mut deps = setup_test();
+
+    let operations = vec![
+        SwapOperation::AstroSwap {
+            offer_asset_info: AssetInfo::NativeToken {
+                denom: "uatom".to_string(),
+            },
+            ask_asset_info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+        },
+        SwapOperation::AstroSwap {
+            offer_asset_info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            ask_asset_info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+        },
+    ];
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::SetSwapRoute {
+            denom: "uatom".to_string(),
+            router: "router".to_string(),
+            operations: operations.clone(),
+            max_spread: Decimal::percent(1),
+            min_price: Decimal::percent(40),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // The route must start from the reward denom, end with the bond denom, and be connected
+    let invalid_routes = vec![
+        vec![],
+        vec![operations[1].clone()],
+        vec![operations[0].clone()],
+        vec![operations[1].clone(), operations[0].clone()],
+    ];
+    for invalid_operations in invalid_routes {
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("larry", &[]),
+            ExecuteMsg::SetSwapRoute {
+                denom: "uatom".to_string(),
+                router: "router".to_string(),
+                operations: invalid_operations,
+                max_spread: Decimal::percent(1),
+                min_price: Decimal::percent(40),
+            },
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err,
+            ContractError::InvalidSwapRoute {
+                denom: "uatom".to_string()
+            }
+        );
+    }
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetSwapRoute {
+            denom: "uatom".to_string(),
+            router: "router".to_string(),
+            operations: operations.clone(),
+            max_spread: Decimal::percent(101),
+            min_price: Decimal::percent(40),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::MaxSpreadTooHigh {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetSwapRoute {
+            denom: "uatom".to_string(),
+            router: "router".to_string(),
+            operations: operations.clone(),
+            max_spread: Decimal::percent(1),
+            min_price: Decimal::zero(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::ZeroMinPrice {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetSwapRoute {
+            denom: "uatom".to_string(),
+            router: "router".to_string(),
+            operations: operations.clone(),
+            max_spread: Decimal::percent(1),
+            min_price: Decimal::percent(40),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    let res: Vec<SwapRoute> = query_helper(deps.as_ref(), QueryMsg::SwapRoutes {});
+    assert_eq!(
+        res,
+        vec![SwapRoute {
+            denom: "uatom".to_string(),
+            router: "router".to_string(),
+            operations,
+            max_spread: Decimal::percent(1),
+            min_price: Decimal::percent(40),
+        }]
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::RemoveSwapRoute {
+            denom: "uusd".to_string(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::SwapRouteNotFound {
+            denom: "uusd".to_string()
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::RemoveSwapRoute {
+            denom: "uatom".to_string(),
+        },
+    )
+    .unwrap();
+
+    let res: Vec<SwapRoute> = query_helper(deps.as_ref(), QueryMsg::SwapRoutes {});
+    assert_eq!(res, vec![]);
+}
+
+#[test]
+fn pausing() {
+    let mut deps = setup_test();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::SetGuardian {
+            guardian: Some("gary".to_string()),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetGuardian {
+            guardian: Some("gary".to_string()),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/guardian_set").add_attribute("guardian", "gary")]
+    );
+
+    // Neither can a random account pause
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: Some(true),
+            queue_unbond: None,
+            instant_unbond: None,
+            withdraw_unbonded: None,
+            harvest: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // The guardian pauses bonding and harvesting
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gary", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: Some(true),
+            queue_unbond: None,
+            instant_unbond: None,
+            withdraw_unbonded: None,
+            harvest: Some(true),
+        },
+    )
+    .unwrap();
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(res.guardian, Some("gary".to_string()));
+    assert_eq!(
+        res.pause_flags,
+        PauseFlags {
+            bond: true,
+            queue_unbond: false,
+            instant_unbond: false,
+            withdraw_unbonded: false,
+            harvest: true,
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(1000000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            action: "bond".to_string()
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("worker", &[]),
+        ExecuteMsg::Harvest {},
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            action: "harvest".to_string()
+        }
+    );
+
+    // The guardian pauses unbonding and withdrawals, but cannot unpause anything
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gary", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: None,
+            queue_unbond: Some(true),
+            instant_unbond: None,
+            withdraw_unbonded: Some(true),
+            harvest: None,
+        },
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(23456),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            action: "queue_unbond".to_string()
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
+            limit: None,
+            hook: false,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::Paused {
+            action: "withdraw_unbonded".to_string()
+        }
+    );
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gary", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: Some(false),
+            queue_unbond: Some(true),
+            instant_unbond: None,
+            withdraw_unbonded: None,
+            harvest: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::UnauthorizedGuardianUnpause {});
+
+    // Only the owner can unpause
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: Some(false),
+            queue_unbond: Some(false),
+            instant_unbond: None,
+            withdraw_unbonded: Some(false),
+            harvest: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.events,
+        vec![Event::new("steakhub/pause_flags_updated")
+            .add_attribute("sender", "larry")
+            .add_attribute("bond", "false")
+            .add_attribute("queue_unbond", "false")
+            .add_attribute("instant_unbond", "false")
+            .add_attribute("withdraw_unbonded", "false")
+            .add_attribute("harvest", "true")]
+    );
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(
+        res.pause_flags,
+        PauseFlags {
+            bond: false,
+            queue_unbond: false,
+            instant_unbond: false,
+            withdraw_unbonded: false,
+            harvest: true,
+        }
+    );
+
+    // Once the guardian is removed, they can no longer pause
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::SetGuardian {
+            guardian: None,
+        },
+    )
+    .unwrap();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gary", &[]),
+        ExecuteMsg::UpdatePauseFlags {
+            bond: Some(true),
+            queue_unbond: None,
+            instant_unbond: None,
+            withdraw_unbonded: None,
+            harvest: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+#[test]
+fn transferring_ownership() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::TransferOwnership {
+            new_owner: "jake".to_string(),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::TransferOwnership {
+            new_owner: "jake".to_string(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    let owner = state.owner.load(deps.as_ref().storage).unwrap();
+    assert_eq!(owner, Addr::unchecked("larry"));
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("pumpkin", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::UnauthorizedNotNewOwner {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::AcceptOwnership {}
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    let owner = state.owner.load(deps.as_ref().storage).unwrap();
+    assert_eq!(owner, Addr::unchecked("jake"));
+}
+
+#[test]
+fn updating_config() {
+    let mut deps = setup_test();
+    let state = State::default();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: Some(86400),
+            unbond_period: None,
+            netting: None,
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: Some(0),
+            unbond_period: None,
+            netting: None,
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::ZeroPeriod {});
+
+    // 86400 * 7 = 604800 < 1814400; more than 7 batches would be unbonding at the same time
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: Some(86400),
+            unbond_period: None,
+            netting: None,
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::EpochPeriodTooShort {
+            max_entries: 7
+        }
+    );
+
+    // An epoch period so long that checking it against the unbonding period would overflow is rejected
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: Some(u64::MAX),
+            unbond_period: None,
+            netting: None,
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::PeriodTooLong {});
+
+    // The chain reduces the unbonding time to 7 days, and we shorten the epoch to 1 day
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: Some(86400),
+            unbond_period: Some(604800),
+            netting: None,
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(res.messages.len(), 0);
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(res.epoch_period, 86400);
+    assert_eq!(res.unbond_period, 604800);
+
+    // The pending batch should have been rescheduled
+    // 269,200 - 259,200 + 86,400 = 96,400
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
+    assert_eq!(
+        pending_batch,
+        PendingBatch {
+            id: 1,
+            usteak_to_burn: Uint128::zero(),
+            uluna_to_bond: Uint128::zero(),
+            est_unbond_start_time: 96400,
+        }
+    );
+
+    // Updating only the unbonding period should not reschedule the pending batch
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: Some(259200),
+            netting: None,
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap();
+
+    let pending_batch = state.pending_batch.load(deps.as_ref().storage).unwrap();
+    assert_eq!(pending_batch.est_unbond_start_time, 96400);
+}
+
+#[test]
+fn enforcing_min_amounts() {
+    let mut deps = setup_test();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: None,
+            min_bond: Some(Uint128::new(10000)),
+            min_unbond: Some(Uint128::new(5000)),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: None,
+            min_bond: Some(Uint128::new(10000)),
+            min_unbond: Some(Uint128::new(5000)),
+        },
+    )
+    .unwrap();
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(res.min_bond, Uint128::new(10000));
+    assert_eq!(res.min_unbond, Uint128::new(5000));
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    // Bonds below the minimum are rejected
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(9999, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::BondTooSmall {
+            min_bond: Uint128::new(10000)
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(10000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    // Unbonding requests below the minimum are rejected
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(4999),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::UnbondTooSmall {
+            min_unbond: Uint128::new(5000)
+        }
+    );
+
+    // Simulations reject them as well
+    let err = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::SimulateUnbond {
+            usteak: Uint128::new(4999),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err(
+            ContractError::UnbondTooSmall {
+                min_unbond: Uint128::new(5000)
+            }
+            .to_string()
+        )
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("steak_token", &[]),
+        ExecuteMsg::Receive(cw20::Cw20ReceiveMsg {
+            sender: "user_1".to_string(),
+            amount: Uint128::new(5000),
+            msg: to_binary(&ReceiveMsg::QueueUnbond {
+                receiver: None,
+            })
+            .unwrap(),
+        }),
+    )
+    .unwrap();
+
+    // Updating other parameters leaves the minimums unchanged
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: Some(true),
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap();
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(res.min_bond, Uint128::new(10000));
+    assert_eq!(res.min_unbond, Uint128::new(5000));
+}
+
+#[test]
+fn capping_bonds() {
+    let mut deps = setup_test();
+
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("jake", &[]),
+        ExecuteMsg::UpdateBondCaps {
+            max_total_bonded: Some(Uint128::new(1100000)),
+            max_bonded_per_address: Some(Uint128::new(50000)),
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBondCaps {
+            max_total_bonded: Some(Uint128::new(1100000)),
+            max_bonded_per_address: Some(Uint128::new(50000)),
+        },
+    )
+    .unwrap();
+
+    let res: ConfigResponse = query_helper(deps.as_ref(), QueryMsg::Config {});
+    assert_eq!(res.max_total_bonded, Some(Uint128::new(1100000)));
+    assert_eq!(res.max_bonded_per_address, Some(Uint128::new(50000)));
+
+    // uluna bonded: 1,025,000; usteak supply: 1,000,000
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+    deps.querier.set_cw20_balance("steak_token", "user_1", 40000);
+    deps.querier.set_cw20_balance("steak_token", "user_2", 0);
+
+    // 1,100,000 - 1,025,000 = 75,000
+    let res: StateResponse = query_helper(deps.as_ref(), QueryMsg::State {});
+    assert_eq!(res.bond_headroom, Some(Uint128::new(75000)));
+
+    // Bonds that would push the total above the cap are rejected
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_2", &[Coin::new(75001, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::BondCapExceeded {
+            headroom: Uint128::new(75000)
+        }
+    );
+
+    // User 1's Steak is worth 40,000 * 1,025,000 / 1,000,000 = 41,000 uluna, so they can bond at most
+    // 50,000 - 41,000 = 9,000 uluna
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(9001, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::AddressBondCapExceeded {
+            headroom: Uint128::new(9000)
+        }
+    );
+
+    // The per-address cap applies to the receiver, not the sender
+    let err = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_2", &[Coin::new(9001, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: Some("user_1".to_string()),
+        },
+    )
+    .unwrap_err();
+
     assert_eq!(
         err,
-        StdError::NotFound {
-            kind: "steak::hub::Batch".to_string()
+        ContractError::AddressBondCapExceeded {
+            headroom: Uint128::new(9000)
         }
     );
 
-    let err = state
-        .unbond_requests
-        .load(deps.as_ref().storage, (1u64.into(), &Addr::unchecked("user_3")))
-        .unwrap_err();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_1", &[Coin::new(9000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
 
-    assert_eq!(
-        err,
-        StdError::NotFound { kind: "steak::hub::UnbondRequest".to_string() }
-    );
+    // Once the caps are removed, any amount can be bonded
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBondCaps {
+            max_total_bonded: None,
+            max_bonded_per_address: None,
+        },
+    )
+    .unwrap();
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("user_2", &[Coin::new(1000000, "uluna")]),
+        ExecuteMsg::Bond {
+            receiver: None,
+        },
+    )
+    .unwrap();
+
+    let res: StateResponse = query_helper(deps.as_ref(), QueryMsg::State {});
+    assert_eq!(res.bond_headroom, None);
 }
 
 #[test]
-fn adding_validator() {
+fn updating_fee() {
     let mut deps = setup_test();
     let state = State::default();
 
@@ -983,167 +5347,285 @@ fn adding_validator() {
         deps.as_mut(),
         mock_env(),
         mock_info("jake", &[]),
-        ExecuteMsg::AddValidator {
-            validator: "dave".to_string(),
+        ExecuteMsg::UpdateFee {
+            new_fee_rate: Decimal::percent(1),
+            new_fee_account: None,
         },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("unauthorized: sender is not owner"));
+    assert_eq!(err, ContractError::Unauthorized {});
 
     let err = execute(
         deps.as_mut(),
         mock_env(),
         mock_info("larry", &[]),
-        ExecuteMsg::AddValidator {
-            validator: "alice".to_string(),
+        ExecuteMsg::UpdateFee {
+            new_fee_rate: Decimal::percent(11),
+            new_fee_account: None,
         },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("validator is already whitelisted"));
+    assert_eq!(
+        err,
+        ContractError::FeeRateTooHigh {
+            max_fee_rate: Decimal::percent(10)
+        }
+    );
 
     let res = execute(
         deps.as_mut(),
         mock_env(),
         mock_info("larry", &[]),
-        ExecuteMsg::AddValidator {
-            validator: "dave".to_string(),
+        ExecuteMsg::UpdateFee {
+            new_fee_rate: Decimal::percent(10),
+            new_fee_account: Some("the_new_fee_man".to_string()),
         },
     )
     .unwrap();
 
     assert_eq!(res.messages.len(), 0);
 
-    let validators = state.validators.load(deps.as_ref().storage).unwrap();
-    assert_eq!(
-        validators,
-        vec![
-            String::from("alice"),
-            String::from("bob"),
-            String::from("charlie"),
-            String::from("dave")
-        ],
-    );
+    let fee_rate = state.fee_rate.load(deps.as_ref().storage).unwrap();
+    assert_eq!(fee_rate, Decimal::percent(10));
+
+    let fee_account = state.fee_account.load(deps.as_ref().storage).unwrap();
+    assert_eq!(fee_account, Addr::unchecked("the_new_fee_man"));
 }
 
-#[test]
-fn removing_validator() {
+//--------------------------------------------------------------------------------------------------
+// Migrations
+//--------------------------------------------------------------------------------------------------
+
+/// Seed the storage the way Steak Hub v2.0.0 would have left it: no cw2 version info, no denom, no
+/// protocol fee, and validators without weights
+fn setup_legacy_test() -> OwnedDeps<MockStorage, MockApi, CustomQuerier> {
     let mut deps = setup_test();
     let state = State::default();
 
-    deps.querier.set_staking_delegations(&[
-        Delegation::new("alice", 341667),
-        Delegation::new("bob", 341667),
-        Delegation::new("charlie", 341666),
-    ]);
+    cw2::CONTRACT.remove(deps.as_mut().storage);
+    state.denom.remove(deps.as_mut().storage);
+    state.fee_account.remove(deps.as_mut().storage);
+    state.fee_rate.remove(deps.as_mut().storage);
 
-    let err = execute(
+    let legacy_validators: Item<Vec<String>> = Item::new("validators");
+    legacy_validators
+        .save(deps.as_mut().storage, &vec!["alice".to_string(), "bob".to_string(), "charlie".to_string()])
+        .unwrap();
+
+    deps
+}
+
+#[test]
+fn migrating_from_legacy_version() {
+    let mut deps = setup_legacy_test();
+    let state = State::default();
+
+    // The fee rate is still subject to the hard cap
+    let err = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("jake", &[]),
-        ExecuteMsg::RemoveValidator {
-            validator: "charlie".to_string(),
+        MigrateMsg {
+            steps: vec![
+                MigrateStep::InitDenomAndFee {
+                    denom: "uluna".to_string(),
+                    fee_account: "the_fee_man".to_string(),
+                    fee_rate: Decimal::percent(20),
+                },
+                MigrateStep::InitValidatorWeights {},
+            ],
         },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("unauthorized: sender is not owner"));
+    assert_eq!(
+        err,
+        ContractError::FeeRateTooHigh {
+            max_fee_rate: Decimal::percent(10)
+        }
+    );
 
-    let err = execute(
+    // Both storage upgrades are required, otherwise the state cannot be loaded after migrating
+    let err = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("larry", &[]),
-        ExecuteMsg::RemoveValidator {
-            validator: "dave".to_string(),
+        MigrateMsg {
+            steps: vec![],
         },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("validator is not already whitelisted"));
+    assert_eq!(
+        err,
+        ContractError::StepMissing {
+            step: "init_denom_and_fee".to_string()
+        }
+    );
 
-    // Target: (341667 + 341667 + 341666) / 2 = 512500
-    // Remainder: 0
-    // Alice:   512500 + 0 - 341667 = 170833
-    // Bob:     512500 + 0 - 341667 = 170833
-    let res = execute(
+    let err = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("larry", &[]),
-        ExecuteMsg::RemoveValidator {
-            validator: "charlie".to_string(),
+        MigrateMsg {
+            steps: vec![MigrateStep::InitDenomAndFee {
+                denom: "uluna".to_string(),
+                fee_account: "the_fee_man".to_string(),
+                fee_rate: Decimal::percent(5),
+            }],
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::StepMissing {
+            step: "init_validator_weights".to_string()
+        }
+    );
+
+    let res = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            steps: vec![
+                MigrateStep::InitDenomAndFee {
+                    denom: "uluna".to_string(),
+                    fee_account: "the_fee_man".to_string(),
+                    fee_rate: Decimal::percent(5),
+                },
+                MigrateStep::InitValidatorWeights {},
+            ],
         },
     )
     .unwrap();
 
-    assert_eq!(res.messages.len(), 2);
+    assert_eq!(res.messages.len(), 0);
     assert_eq!(
-        res.messages[0],
-        SubMsg::reply_on_success(Redelegation::new("charlie", "alice", 170833).to_cosmos_msg(), 2),
+        res.events,
+        vec![Event::new("steakhub/migrated")
+            .add_attribute("previous_version", "2.0.0")
+            .add_attribute("new_version", env!("CARGO_PKG_VERSION"))
+            .add_attribute("steps", "init_denom_and_fee,init_validator_weights")]
     );
+
+    assert_eq!(state.denom.load(deps.as_ref().storage).unwrap(), "uluna");
+    assert_eq!(state.fee_account.load(deps.as_ref().storage).unwrap(), Addr::unchecked("the_fee_man"));
+    assert_eq!(state.fee_rate.load(deps.as_ref().storage).unwrap(), Decimal::percent(5));
     assert_eq!(
-        res.messages[1],
-        SubMsg::reply_on_success(Redelegation::new("charlie", "bob", 170833).to_cosmos_msg(), 2),
+        state.validators.load(deps.as_ref().storage).unwrap(),
+        vec![(String::from("alice"), 1), (String::from("bob"), 1), (String::from("charlie"), 1)],
     );
+    assert_eq!(state.keeper_cooldown.load(deps.as_ref().storage).unwrap(), 3600);
 
-    let validators = state.validators.load(deps.as_ref().storage).unwrap();
-    assert_eq!(validators, vec![String::from("alice"), String::from("bob")],);
-}
+    let res = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+    assert_eq!(res.version, env!("CARGO_PKG_VERSION"));
 
-#[test]
-fn transferring_ownership() {
-    let mut deps = setup_test();
-    let state = State::default();
+    // The same storage upgrade cannot be applied twice
+    let err = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            steps: vec![MigrateStep::InitDenomAndFee {
+                denom: "uatom".to_string(),
+                fee_account: "the_fee_man".to_string(),
+                fee_rate: Decimal::percent(5),
+            }],
+        },
+    )
+    .unwrap_err();
 
-    let err = execute(
+    assert_eq!(
+        err,
+        ContractError::StepAlreadyApplied {
+            step: "init_denom_and_fee".to_string()
+        }
+    );
+
+    let err = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("jake", &[]),
-        ExecuteMsg::TransferOwnership {
-            new_owner: "jake".to_string(),
+        MigrateMsg {
+            steps: vec![MigrateStep::InitValidatorWeights {}],
         },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("unauthorized: sender is not owner"));
+    assert_eq!(
+        err,
+        ContractError::StepAlreadyApplied {
+            step: "init_validator_weights".to_string()
+        }
+    );
 
-    let res = execute(
+    // Migrating without any storage upgrade simply bumps the version
+    let res = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("larry", &[]),
-        ExecuteMsg::TransferOwnership {
-            new_owner: "jake".to_string(),
+        MigrateMsg {
+            steps: vec![],
         },
     )
     .unwrap();
 
     assert_eq!(res.messages.len(), 0);
+}
 
-    let owner = state.owner.load(deps.as_ref().storage).unwrap();
-    assert_eq!(owner, Addr::unchecked("larry"));
+#[test]
+fn migrating_from_unknown_contract() {
+    let mut deps = mock_dependencies();
 
-    let err = execute(
+    let err = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("pumpkin", &[]),
-        ExecuteMsg::AcceptOwnership {},
+        MigrateMsg {
+            steps: vec![],
+        },
     )
     .unwrap_err();
 
-    assert_eq!(err, StdError::generic_err("unauthorized: sender is not new owner"));
+    assert_eq!(err, ContractError::UnknownContract {});
 
-    let res = execute(
+    cw2::set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.13.4").unwrap();
+
+    let err = migrate(
         deps.as_mut(),
         mock_env(),
-        mock_info("jake", &[]),
-        ExecuteMsg::AcceptOwnership {}
+        MigrateMsg {
+            steps: vec![],
+        },
     )
-    .unwrap();
+    .unwrap_err();
 
-    assert_eq!(res.messages.len(), 0);
+    assert_eq!(
+        err,
+        ContractError::InvalidContract {
+            contract: "crates.io:cw20-base".to_string()
+        }
+    );
+}
 
-    let owner = state.owner.load(deps.as_ref().storage).unwrap();
-    assert_eq!(owner, Addr::unchecked("jake"));
+#[test]
+fn migrating_from_newer_version() {
+    let mut deps = setup_test();
+
+    cw2::set_contract_version(deps.as_mut().storage, "crates.io:steak-hub", "99.0.0").unwrap();
+
+    let err = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            steps: vec![],
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err,
+        ContractError::Downgrade {
+            previous_version: "99.0.0".to_string(),
+            new_version: env!("CARGO_PKG_VERSION").to_string()
+        }
+    );
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -1161,6 +5643,9 @@ fn querying_previous_batches() {
             total_shares: Uint128::new(123),
             uluna_unclaimed: Uint128::new(678),
             est_unbond_end_time: 10000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
         },
         Batch {
             id: 2,
@@ -1168,6 +5653,9 @@ fn querying_previous_batches() {
             total_shares: Uint128::new(234),
             uluna_unclaimed: Uint128::new(789),
             est_unbond_end_time: 15000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
         },
         Batch {
             id: 3,
@@ -1175,6 +5663,9 @@ fn querying_previous_batches() {
             total_shares: Uint128::new(345),
             uluna_unclaimed: Uint128::new(890),
             est_unbond_end_time: 20000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
         },
         Batch {
             id: 4,
@@ -1182,12 +5673,15 @@ fn querying_previous_batches() {
             total_shares: Uint128::new(456),
             uluna_unclaimed: Uint128::new(999),
             est_unbond_end_time: 25000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
         },
     ];
 
     let state = State::default();
     for batch in &batches {
-        state.previous_batches.save(deps.as_mut().storage, batch.id.into(), batch).unwrap();
+        state.previous_batches.save(deps.as_mut().storage, batch.id, batch).unwrap();
     }
 
     // Querying a single batch
@@ -1256,30 +5750,153 @@ fn querying_previous_batches() {
 }
 
 #[test]
-fn querying_unbond_requests() {
-    let mut deps = mock_dependencies();
+fn querying_unbond_requests() {
+    let mut deps = mock_dependencies();
+    let state = State::default();
+
+    let unbond_requests = vec![
+        UnbondRequest {
+            id: 1,
+            user: Addr::unchecked("alice"),
+            shares: Uint128::new(123),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 1,
+            user: Addr::unchecked("bob"),
+            shares: Uint128::new(234),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 1,
+            user: Addr::unchecked("charlie"),
+            shares: Uint128::new(345),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 2,
+            user: Addr::unchecked("alice"),
+            shares: Uint128::new(456),
+            seq: 0,
+            held_claimed: false,
+        },
+    ];
+
+    for unbond_request in &unbond_requests {
+        state
+            .unbond_requests
+            .save(
+                deps.as_mut().storage,
+                (unbond_request.id, &unbond_request.key()),
+                unbond_request,
+            )
+            .unwrap();
+    }
+
+    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByBatch {
+            id: 1,
+            start_after: None,
+            start_after_seq: None,
+            limit: None,
+        },
+    );
+    assert_eq!(
+        res,
+        vec![
+            unbond_requests[0].clone().into(),
+            unbond_requests[1].clone().into(),
+            unbond_requests[2].clone().into(),
+        ]
+    );
+
+    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByBatch {
+            id: 2,
+            start_after: None,
+            start_after_seq: None,
+            limit: None,
+        },
+    );
+    assert_eq!(res, vec![unbond_requests[3].clone().into()]);
+
+    let res: Vec<UnbondRequestsByUserResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByUser {
+            user: "alice".to_string(),
+            start_after: None,
+            limit: None,
+        },
+    );
+    assert_eq!(res, vec![unbond_requests[0].clone().into(), unbond_requests[3].clone().into()]);
+
+    let res: Vec<UnbondRequestsByUserResponseItem> = query_helper(
+        deps.as_ref(),
+        QueryMsg::UnbondRequestsByUser {
+            user: "alice".to_string(),
+            start_after: Some(2),
+            limit: None,
+        },
+    );
+    assert_eq!(res, vec![unbond_requests[3].clone().into()]);
+}
+
+#[test]
+fn querying_unbonding_summary() {
+    let mut deps = setup_test();
     let state = State::default();
 
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    // - batch 1 has finished unbonding and is reconciled
+    // - batch 2 has finished unbonding but is not reconciled
+    // - batch 3 has been submitted for unbonding but has not finished
+    // - batch 4 is still pending
     let unbond_requests = vec![
         UnbondRequest {
             id: 1,
-            user: Addr::unchecked("alice"),
-            shares: Uint128::new(123),
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(23456),
+            seq: 0,
+            held_claimed: false,
         },
         UnbondRequest {
             id: 1,
-            user: Addr::unchecked("bob"),
-            shares: Uint128::new(234),
+            user: Addr::unchecked("user_3"),
+            shares: Uint128::new(69420),
+            seq: 0,
+            held_claimed: false,
         },
         UnbondRequest {
-            id: 1,
-            user: Addr::unchecked("charlie"),
-            shares: Uint128::new(345),
+            id: 2,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(34567),
+            seq: 0,
+            held_claimed: false,
         },
         UnbondRequest {
-            id: 2,
-            user: Addr::unchecked("alice"),
-            shares: Uint128::new(456),
+            id: 3,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(45678),
+            seq: 0,
+            held_claimed: false,
+        },
+        UnbondRequest {
+            id: 4,
+            user: Addr::unchecked("user_1"),
+            shares: Uint128::new(56789),
+            seq: 0,
+            held_claimed: false,
         },
     ];
 
@@ -1288,58 +5905,345 @@ fn querying_unbond_requests() {
             .unbond_requests
             .save(
                 deps.as_mut().storage,
-                (unbond_request.id.into(), &Addr::unchecked(unbond_request.user.clone())),
+                (unbond_request.id, &unbond_request.key()),
                 unbond_request,
             )
             .unwrap();
     }
 
-    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
-        deps.as_ref(),
-        QueryMsg::UnbondRequestsByBatch {
+    let previous_batches = vec![
+        Batch {
             id: 1,
-            start_after: None,
+            reconciled: true,
+            total_shares: Uint128::new(92876),
+            uluna_unclaimed: Uint128::new(95197),
+            est_unbond_end_time: 10000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 2,
+            reconciled: false,
+            total_shares: Uint128::new(34567),
+            uluna_unclaimed: Uint128::new(35604),
+            est_unbond_end_time: 10000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+        Batch {
+            id: 3,
+            reconciled: false,
+            total_shares: Uint128::new(45678),
+            uluna_unclaimed: Uint128::new(47276),
+            est_unbond_end_time: 30000,
+            uluna_held: Uint128::zero(),
+            held_shares: Uint128::zero(),
+            undelegations: vec![],
+        },
+    ];
+
+    for previous_batch in &previous_batches {
+        state
+            .previous_batches
+            .save(deps.as_mut().storage, previous_batch.id, previous_batch)
+            .unwrap();
+    }
+
+    state
+        .pending_batch
+        .save(
+            deps.as_mut().storage,
+            &PendingBatch {
+                id: 4,
+                usteak_to_burn: Uint128::new(56789),
+                uluna_to_bond: Uint128::zero(),
+                est_unbond_start_time: 100000,
+            },
+        )
+        .unwrap();
+
+    // Batch 1:    95197 * 23456 / 92876 = 24042
+    // Batch 4:    1025000 * 56789 / 1000000 = 58208
+    let res: UnbondingSummaryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(20000),
+            QueryMsg::UnbondingSummary {
+                user: "user_1".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        res,
+        UnbondingSummaryResponse {
+            requests: vec![
+                UnbondingSummaryItem {
+                    id: 1,
+                    shares: Uint128::new(23456),
+                    status: UnbondStatus::Claimable,
+                    est_uluna: Uint128::new(24042),
+                    est_unbond_end_time: 10000,
+                },
+                UnbondingSummaryItem {
+                    id: 2,
+                    shares: Uint128::new(34567),
+                    status: UnbondStatus::Unreconciled,
+                    est_uluna: Uint128::new(35604),
+                    est_unbond_end_time: 10000,
+                },
+                UnbondingSummaryItem {
+                    id: 3,
+                    shares: Uint128::new(45678),
+                    status: UnbondStatus::Unbonding,
+                    est_uluna: Uint128::new(47276),
+                    est_unbond_end_time: 30000,
+                },
+                UnbondingSummaryItem {
+                    id: 4,
+                    shares: Uint128::new(56789),
+                    status: UnbondStatus::Pending,
+                    est_uluna: Uint128::new(58208),
+                    est_unbond_end_time: 1914400, // 100,000 + 1,814,400
+                },
+            ],
+            total_withdrawable: Uint128::new(24042),
+        }
+    );
+
+    // The total withdrawable amount should match what is actually withdrawn
+    let res = execute(
+        deps.as_mut(),
+        mock_env_at_timestamp(20000),
+        mock_info("user_1", &[]),
+        ExecuteMsg::WithdrawUnbonded {
+            receiver: None,
+            batch_ids: None,
             limit: None,
+            hook: false,
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "user_1".to_string(),
+            amount: vec![Coin::new(24042, "uluna")]
+        })
+    );
+}
+
+#[test]
+fn querying_simulations() {
+    let mut deps = setup_test();
+
+    deps.querier.set_staking_delegations(&[
+        Delegation::new("alice", 341667, "uluna"),
+        Delegation::new("bob", 341667, "uluna"),
+        Delegation::new("charlie", 341666, "uluna"),
+    ]);
+    deps.querier.set_cw20_total_supply("steak_token", 1000000);
+
+    // Same as in the `bonding` test: the full deposit goes to Charlie, and 12043 usteak are minted
+    let res: SimulateBondResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(12345),
+            receiver: None,
         },
     );
     assert_eq!(
         res,
-        vec![
-            unbond_requests[0].clone().into(),
-            unbond_requests[1].clone().into(),
-            unbond_requests[2].clone().into(),
-        ]
+        SimulateBondResponse {
+            usteak_to_mint: Uint128::new(12043),
+            validator: Some("charlie".to_string()),
+        }
     );
 
-    let res: Vec<UnbondRequestsByBatchResponseItem> = query_helper(
+    // A bond that would be rejected fails with the same error
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: None,
+            min_bond: Some(Uint128::new(10000)),
+            min_unbond: None,
+        },
+    )
+    .unwrap();
+
+    let err = query(
         deps.as_ref(),
-        QueryMsg::UnbondRequestsByBatch {
-            id: 2,
-            start_after: None,
-            limit: None,
+        mock_env(),
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(9999),
+            receiver: None,
         },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err(
+            ContractError::BondTooSmall {
+                min_bond: Uint128::new(10000)
+            }
+            .to_string()
+        )
     );
-    assert_eq!(res, vec![unbond_requests[3].clone().into()]);
 
-    let res: Vec<UnbondRequestsByUserResponseItem> = query_helper(
+    // The per-address cap is only checked if the receiver is provided
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBondCaps {
+            max_total_bonded: None,
+            max_bonded_per_address: Some(Uint128::new(12000)),
+        },
+    )
+    .unwrap();
+    deps.querier.set_cw20_balance("steak_token", "user_1", 0);
+
+    let err = query(
         deps.as_ref(),
-        QueryMsg::UnbondRequestsByUser {
-            user: "alice".to_string(),
-            start_after: None,
-            limit: None,
+        mock_env(),
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(12345),
+            receiver: Some("user_1".to_string()),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        StdError::generic_err(
+            ContractError::AddressBondCapExceeded {
+                headroom: Uint128::new(12000)
+            }
+            .to_string()
+        )
+    );
+
+    let res: SimulateBondResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(12345),
+            receiver: None,
         },
     );
-    assert_eq!(res, vec![unbond_requests[0].clone().into(), unbond_requests[3].clone().into()]);
+    assert_eq!(res.usteak_to_mint, Uint128::new(12043));
 
-    let res: Vec<UnbondRequestsByUserResponseItem> = query_helper(
+    // Buffer target: (1,025,000 + 12,345) * 10% = 103,734
+    // The entire deposit goes to the buffer, so nothing is delegated
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateBuffer {
+            new_target_rate: Decimal::percent(10),
+            new_fee_rate: Decimal::percent(1),
+        },
+    )
+    .unwrap();
+
+    let res: SimulateBondResponse = query_helper(
         deps.as_ref(),
-        QueryMsg::UnbondRequestsByUser {
-            user: "alice".to_string(),
-            start_after: Some(2),
-            limit: None,
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(12345),
+            receiver: None,
         },
     );
-    assert_eq!(res, vec![unbond_requests[3].clone().into()]);
+    assert_eq!(
+        res,
+        SimulateBondResponse {
+            usteak_to_mint: Uint128::new(12043),
+            validator: None,
+        }
+    );
+
+    // With netting enabled, the rest of the deposit is held rather than delegated
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("larry", &[]),
+        ExecuteMsg::UpdateConfig {
+            epoch_period: None,
+            unbond_period: None,
+            netting: Some(true),
+            min_bond: None,
+            min_unbond: None,
+        },
+    )
+    .unwrap();
+
+    // usteak to mint: 1,000,000 * 500,000 / 1,025,000 = 487,804
+    let res: SimulateBondResponse = query_helper(
+        deps.as_ref(),
+        QueryMsg::SimulateBond {
+            amount: Uint128::new(500000),
+            receiver: None,
+        },
+    );
+    assert_eq!(
+        res,
+        SimulateBondResponse {
+            usteak_to_mint: Uint128::new(487804),
+            validator: None,
+        }
+    );
+
+    // 1025000 * 100000 / 1000000 = 102500
+    // The pending batch is not due yet, so it will start unbonding at 269200
+    let res: SimulateUnbondResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(20000),
+            QueryMsg::SimulateUnbond {
+                usteak: Uint128::new(100000),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        SimulateUnbondResponse {
+            uluna_to_unbond: Uint128::new(102500),
+            batch_id: 1,
+            est_unbond_end_time: 2083600, // 269,200 + 1,814,400
+        }
+    );
+
+    // The pending batch is overdue, so it will be submitted right away
+    let res: SimulateUnbondResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env_at_timestamp(300000),
+            QueryMsg::SimulateUnbond {
+                usteak: Uint128::new(100000),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.est_unbond_end_time, 2114400); // 300,000 + 1,814,400
+
+    let err = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::SimulateUnbond {
+            usteak: Uint128::new(1000001),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, StdError::generic_err("usteak amount exceeds total supply"));
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -1349,9 +6253,9 @@ fn querying_unbond_requests() {
 #[test]
 fn computing_undelegations() {
     let current_delegations = vec![
-        Delegation::new("alice", 400),
-        Delegation::new("bob", 300),
-        Delegation::new("charlie", 200),
+        Delegation::new("alice", 400, "uluna"),
+        Delegation::new("bob", 300, "uluna"),
+        Delegation::new("charlie", 200, "uluna"),
     ];
 
     // Target: (400 + 300 + 200 - 451) / 3 = 149
@@ -1359,22 +6263,32 @@ fn computing_undelegations() {
     // Alice:   400 - (149 + 1) = 250
     // Bob:     300 - (149 + 1) = 150
     // Charlie: 200 - (149 + 0) = 51
-    let new_undelegations = compute_undelegations(Uint128::new(451), &current_delegations);
+    let new_undelegations = compute_undelegations(Uint128::new(451), &current_delegations, &[1, 1, 1]);
     let expected = vec![
-        Undelegation::new("alice", 250),
-        Undelegation::new("bob", 150),
-        Undelegation::new("charlie", 51),
+        Undelegation::new("alice", 250, "uluna"),
+        Undelegation::new("bob", 150, "uluna"),
+        Undelegation::new("charlie", 51, "uluna"),
     ];
     assert_eq!(new_undelegations, expected);
+
+    // Weights: 1, 2, 3
+    // Target: (400 + 300 + 200 - 300) = 600
+    // Alice:   400 - 600 * 1 / 6 = 300
+    // Bob:     300 - 600 * 2 / 6 = 100
+    // Charlie: 200 - 600 * 3 / 6 = -100 => 0
+    // Bob is only undelegated 0, as the 300 available are used up by Alice
+    let new_undelegations = compute_undelegations(Uint128::new(300), &current_delegations, &[1, 2, 3]);
+    let expected = vec![Undelegation::new("alice", 300, "uluna")];
+    assert_eq!(new_undelegations, expected);
 }
 
 #[test]
 fn computing_redelegations_for_removal() {
-    let current_delegations = vec![
-        Delegation::new("alice", 13000),
-        Delegation::new("bob", 12000),
-        Delegation::new("charlie", 11000),
-        Delegation::new("dave", 10000),
+    let current_delegations = [
+        Delegation::new("alice", 13000, "uluna"),
+        Delegation::new("bob", 12000, "uluna"),
+        Delegation::new("charlie", 11000, "uluna"),
+        Delegation::new("dave", 10000, "uluna"),
     ];
 
     // Suppose Dave will be removed
@@ -1384,13 +6298,28 @@ fn computing_redelegations_for_removal() {
     // to Bob:     15333 + 0 - 12000 = 3333
     // to Charlie: 15333 + 0 - 11000 = 4333
     let expected = vec![
-        Redelegation::new("dave", "alice", 2334),
-        Redelegation::new("dave", "bob", 3333),
-        Redelegation::new("dave", "charlie", 4333),
+        Redelegation::new("dave", "alice", 2334, "uluna"),
+        Redelegation::new("dave", "bob", 3333, "uluna"),
+        Redelegation::new("dave", "charlie", 4333, "uluna"),
+    ];
+
+    assert_eq!(
+        compute_redelegations_for_removal(&current_delegations[3], &current_delegations[..3], &[1, 1, 1]),
+        expected,
+    );
+
+    // Weights: 1, 2, 3
+    // uluna_to_distribute = 13000 + 12000 + 11000 + 10000 = 46000
+    // to Alice:   46000 * 1 / 6 + 1 - 13000 = -5333 => 0
+    // to Bob:     46000 * 2 / 6 - 12000 = 3333
+    // to Charlie: 46000 * 3 / 6 - 11000 = 12000 => capped at the remaining 6667
+    let expected = vec![
+        Redelegation::new("dave", "bob", 3333, "uluna"),
+        Redelegation::new("dave", "charlie", 6667, "uluna"),
     ];
 
     assert_eq!(
-        compute_redelegations_for_removal(&current_delegations[3], &current_delegations[..3]),
+        compute_redelegations_for_removal(&current_delegations[3], &current_delegations[..3], &[1, 2, 3]),
         expected,
     );
 }
@@ -1398,11 +6327,11 @@ fn computing_redelegations_for_removal() {
 #[test]
 fn computing_redelegations_for_rebalancing() {
     let current_delegations = vec![
-        Delegation::new("alice", 69420),
-        Delegation::new("bob", 1234),
-        Delegation::new("charlie", 88888),
-        Delegation::new("dave", 40471),
-        Delegation::new("evan", 2345),
+        Delegation::new("alice", 69420, "uluna"),
+        Delegation::new("bob", 1234, "uluna"),
+        Delegation::new("charlie", 88888, "uluna"),
+        Delegation::new("dave", 40471, "uluna"),
+        Delegation::new("evan", 2345, "uluna"),
     ];
 
     // uluna_per_validator = (69420 + 88888 + 1234 + 40471 + 2345) / 4 = 40471
@@ -1430,12 +6359,40 @@ fn computing_redelegations_for_rebalancing() {
     // Round 3: charlie --(38126)--> evan
     // Queues are emptied
     let expected = vec![
-        Redelegation::new("alice", "bob", 28948),
-        Redelegation::new("charlie", "bob", 10290),
-        Redelegation::new("charlie", "evan", 38126),
+        Redelegation::new("alice", "bob", 28948, "uluna"),
+        Redelegation::new("charlie", "bob", 10290, "uluna"),
+        Redelegation::new("charlie", "evan", 38126, "uluna"),
+    ];
+
+    assert_eq!(compute_redelegations_for_rebalancing(&current_delegations, &[1, 1, 1, 1, 1]), expected,);
+
+    // Weights: 1, 1, 1, 1, 6
+    // uluna_staked = 202358
+    // targets = 20235 (+1), 20235 (+1), 20235 (+1), 20235 (+1), 121414 (+0)
+    // src_delegations:
+    //  - alice:   69420 - 20236 = 49184
+    //  - charlie: 88888 - 20236 = 68652
+    //  - dave:    40471 - 20236 = 20235
+    // dst_delegations:
+    //  - bob:     20236 - 1234   = 19002
+    //  - evan:    121414 - 2345  = 119069
+    let expected = vec![
+        Redelegation::new("alice", "bob", 19002, "uluna"),
+        Redelegation::new("alice", "evan", 30182, "uluna"),
+        Redelegation::new("charlie", "evan", 68652, "uluna"),
+        Redelegation::new("dave", "evan", 20235, "uluna"),
     ];
 
-    assert_eq!(compute_redelegations_for_rebalancing(&current_delegations), expected,);
+    assert_eq!(compute_redelegations_for_rebalancing(&current_delegations, &[1, 1, 1, 1, 6]), expected,);
+}
+
+#[test]
+fn computing_target_amounts() {
+    // 1000 / 3 = 333, remainder 1 goes to the first validator
+    assert_eq!(compute_target_amounts(1000, &[1, 1, 1]), vec![334, 333, 333]);
+
+    // 1000 * 1 / 6 = 166, 1000 * 2 / 6 = 333, 1000 * 3 / 6 = 500; remainder 1 goes to the first validator
+    assert_eq!(compute_target_amounts(1000, &[1, 2, 3]), vec![167, 333, 500]);
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -1486,16 +6443,32 @@ fn adding_coins() {
 #[test]
 fn receiving_funds() {
     let err = parse_received_fund(&[], "uluna").unwrap_err();
-    assert_eq!(err, StdError::generic_err("must deposit exactly one coin; received 0"));
+    assert_eq!(
+        err,
+        ContractError::InvalidFundsCount {
+            received: 0
+        }
+    );
 
     let err = parse_received_fund(&[Coin::new(12345, "uatom"), Coin::new(23456, "uluna")], "uluna").unwrap_err();
-    assert_eq!(err, StdError::generic_err("must deposit exactly one coin; received 2"));
+    assert_eq!(
+        err,
+        ContractError::InvalidFundsCount {
+            received: 2
+        }
+    );
 
     let err = parse_received_fund(&[Coin::new(12345, "uatom")], "uluna").unwrap_err();
-    assert_eq!(err, StdError::generic_err("expected uluna deposit, received uatom"));
+    assert_eq!(
+        err,
+        ContractError::InvalidDenom {
+            expected: "uluna".to_string(),
+            received: "uatom".to_string()
+        }
+    );
 
     let err = parse_received_fund(&[Coin::new(0, "uluna")], "uluna").unwrap_err();
-    assert_eq!(err, StdError::generic_err("deposit amount must be non-zero"));
+    assert_eq!(err, ContractError::ZeroDeposit {});
 
     let amount = parse_received_fund(&[Coin::new(69420, "uluna")], "uluna").unwrap();
     assert_eq!(amount, Uint128::new(69420));
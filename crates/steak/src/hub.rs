@@ -1,12 +1,18 @@
-use cosmwasm_std::{to_binary, Addr, Coin, CosmosMsg, Decimal, Empty, StdResult, Uint128, WasmMsg};
+use cosmwasm_std::{to_binary, Addr, Binary, Coin, CosmosMsg, Decimal, StdResult, Uint128, WasmMsg};
 use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::router::SwapOperation;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     /// Code ID of the CW20 token contract
     pub cw20_code_id: u64,
+    /// Code ID of the CW721 contract representing unbonding requests; if not provided, unbonding
+    /// requests are bound to the user's address and cannot be transferred
+    #[serde(default)]
+    pub cw721_code_id: Option<u64>,
     /// Account who can call certain privileged functions
     pub owner: String,
     /// Name of the liquid staking token
@@ -15,12 +21,25 @@ pub struct InstantiateMsg {
     pub symbol: String,
     /// Number of decimals of the liquid staking token
     pub decimals: u8,
+    /// Denomination of the coin to be staked, e.g. `uluna`
+    pub denom: String,
     /// How often the unbonding queue is to be executed, in seconds
     pub epoch_period: u64,
     /// The staking module's unbonding time, in seconds
     pub unbond_period: u64,
-    /// Initial set of validators who will receive the delegations
+    /// Initial set of validators who will receive the delegations, each starting with a weight of 1
     pub validators: Vec<String>,
+    /// Account to receive the protocol fee
+    pub fee_account: String,
+    /// Fraction of harvested staking rewards to be charged as protocol fee
+    pub fee_rate: Decimal,
+    /// Maximum amount of `uluna` backing Steak, beyond which bonds are rejected; uncapped if not provided
+    #[serde(default)]
+    pub max_total_bonded: Option<Uint128>,
+    /// Maximum amount of `uluna` that the Steak held by a single address may be worth, beyond which
+    /// bonds to that address are rejected; uncapped if not provided
+    #[serde(default)]
+    pub max_bonded_per_address: Option<Uint128>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -32,11 +51,38 @@ pub enum ExecuteMsg {
     Bond {
         receiver: Option<String>,
     },
-    /// Withdraw Luna that have finished unbonding in previous batches
+    /// Bond specified amount of Luna, and send the minted Steak to a contract along with a hook message,
+    /// the same way as CW20 `Send` does, such that the Steak can be used in the same transaction
+    BondAndSend {
+        contract: String,
+        msg: Binary,
+    },
+    /// Cancel part or all of the sender's unbonding request in the pending batch, and get the Steak back.
+    /// If unbond NFTs are enabled, `token_id` specifies the request to cancel, whose NFT must be owned
+    /// by the sender; defaults to the sender's own request
+    CancelUnbond {
+        amount: Uint128,
+        #[serde(default)]
+        token_id: Option<String>,
+    },
+    /// Withdraw Luna that have finished unbonding in previous batches, as well as the part of previous
+    /// batches paid from Luna held by the contract, which is withdrawable right away. If unbond NFTs
+    /// are enabled, withdraws the requests whose NFTs are owned by the sender. If `batch_ids` is
+    /// provided, only requests in these batches are withdrawn; if `limit` is provided, at most this
+    /// many requests are withdrawn. Requests that remain outstanding are reported in the response data,
+    /// as `WithdrawUnbondedResponse`. If `hook` is true, the receiver must be a contract, which is
+    /// executed with `UnbondedHookMsg::SteakUnbondedHook` along with the Luna, instead of the Luna
+    /// being simply transferred
     WithdrawUnbonded {
         receiver: Option<String>,
+        #[serde(default)]
+        batch_ids: Option<Vec<u64>>,
+        #[serde(default)]
+        limit: Option<u32>,
+        #[serde(default)]
+        hook: bool,
     },
-    /// Add a validator to the whitelist; callable by the owner
+    /// Add a validator to the whitelist with a weight of 1; callable by the owner
     AddValidator {
         validator: String,
     },
@@ -44,15 +90,91 @@ pub enum ExecuteMsg {
     RemoveValidator {
         validator: String,
     },
+    /// Update the weights of whitelisted validators, which determine the share of the total delegation
+    /// each of them is targeted to receive; callable by the owner
+    SetValidatorWeights {
+        weights: Vec<(String, u64)>,
+    },
+    /// Set the route along which staking rewards of the given denom are to be swapped for Luna during
+    /// harvest, replacing the existing one if any; callable by the owner. `min_price` is the minimum
+    /// amount of Luna to be received per unit of the reward denom, below which the rewards are not
+    /// swapped
+    SetSwapRoute {
+        denom: String,
+        router: String,
+        operations: Vec<SwapOperation>,
+        max_spread: Decimal,
+        min_price: Decimal,
+    },
+    /// Remove the swap route of the given denom, such that rewards of this denom are no longer swapped;
+    /// callable by the owner
+    RemoveSwapRoute {
+        denom: String,
+    },
+    /// Pause or unpause user-facing actions. The owner may do either; the guardian may only pause
+    UpdatePauseFlags {
+        bond: Option<bool>,
+        queue_unbond: Option<bool>,
+        instant_unbond: Option<bool>,
+        withdraw_unbonded: Option<bool>,
+        harvest: Option<bool>,
+    },
+    /// Set or unset the guardian, who can pause but never unpause actions; callable by the owner
+    SetGuardian {
+        guardian: Option<String>,
+    },
     /// Transfer ownership to another account; will not take effect unless the new owner accepts
     TransferOwnership {
         new_owner: String,
     },
     /// Accept an ownership transfer
     AcceptOwnership {},
-    /// Claim staking rewards, swap all for Luna, and restake
+    /// Update the epoch and/or unbonding periods, enable or disable netting, and/or update the minimum
+    /// amounts to bond and to queue for unbonding; callable by the owner
+    UpdateConfig {
+        epoch_period: Option<u64>,
+        unbond_period: Option<u64>,
+        netting: Option<bool>,
+        min_bond: Option<Uint128>,
+        min_unbond: Option<Uint128>,
+    },
+    /// Update the protocol fee rate, and optionally the account to receive it; callable by the owner
+    UpdateFee {
+        new_fee_rate: Decimal,
+        new_fee_account: Option<String>,
+    },
+    /// Deposit Luna into the insurance reserve, which covers shortfalls in unbonding batches, e.g. due to
+    /// slashing. Deposits are donations and cannot be withdrawn
+    DepositInsurance {},
+    /// Update the fraction of harvested staking rewards to be put into the insurance reserve; callable
+    /// by the owner
+    UpdateInsuranceRate {
+        new_insurance_rate: Decimal,
+    },
+    /// Update the instant unbonding buffer's target, as a fraction of the total amount of Luna backing
+    /// Steak, and the fee charged on instant unbonding; callable by the owner
+    UpdateBuffer {
+        new_target_rate: Decimal,
+        new_fee_rate: Decimal,
+    },
+    /// Update the keeper reward, i.e. the fraction of harvested staking rewards to be put into the
+    /// keeper pool, the fixed amount paid from the pool to whoever invokes a maintenance action, and
+    /// how often each action can be rewarded, at least once an hour; callable by the owner
+    UpdateKeeperReward {
+        new_rate: Decimal,
+        new_amount: Uint128,
+        new_cooldown: u64,
+    },
+    /// Set or unset the caps on the total amount of `uluna` backing Steak, and on the amount of `uluna`
+    /// that the Steak held by a single address may be worth; callable by the owner
+    UpdateBondCaps {
+        max_total_bonded: Option<Uint128>,
+        max_bonded_per_address: Option<Uint128>,
+    },
+    /// Claim staking rewards, swap those with a configured route for Luna, and restake
     Harvest {},
-    /// Use redelegations to balance the amounts of Luna delegated to validators
+    /// Use redelegations to balance the amounts of Luna delegated to validators. The keeper is only
+    /// rewarded if at least 1% of the Luna staked is moved
     Rebalance {},
     /// Update Luna amounts in unbonding batches to reflect any slashing or rounding errors
     Reconcile {},
@@ -70,11 +192,18 @@ pub enum ReceiveMsg {
     QueueUnbond {
         receiver: Option<String>,
     },
+    /// Burn Steak and immediately receive Luna from the instant unbonding buffer, minus a fee which
+    /// accrues to the remaining stakers
+    InstantUnbond {
+        receiver: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum CallbackMsg {
+    /// Swap staking rewards of denoms with a configured route for Luna
+    Swap {},
     /// Following the swaps, stake the Luna acquired to the whitelisted validators
     Reinvest {},
 }
@@ -89,6 +218,28 @@ impl CallbackMsg {
     }
 }
 
+/// Message to be implemented by contracts receiving unbonded Luna through `ExecuteMsg::WithdrawUnbonded`
+/// with `hook` enabled
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UnbondedHookMsg {
+    /// Luna unbonded in the given batches has been withdrawn, and the given amount is attached
+    SteakUnbondedHook {
+        batch_ids: Vec<u64>,
+        amount: Uint128,
+    },
+}
+
+impl UnbondedHookMsg {
+    pub fn into_cosmos_msg(&self, contract_addr: &Addr, funds: Vec<Coin>) -> StdResult<CosmosMsg> {
+        Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(self)?,
+            funds,
+        }))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
@@ -108,9 +259,12 @@ pub enum QueryMsg {
         limit: Option<u32>,
     },
     /// Enumerate all outstanding unbonding requests in a given batch. Response: `Vec<UnbondRequestsResponseByBatchItem>`
+    ///
+    /// To page past a user's follow-up request, `start_after_seq` is set to the last item's `seq`
     UnbondRequestsByBatch {
         id: u64,
         start_after: Option<String>,
+        start_after_seq: Option<u64>,
         limit: Option<u32>,
     },
     /// Enumreate all outstanding unbonding requests from given a user. Response: `Vec<UnbondRequestsByUserResponseItem>`
@@ -119,6 +273,56 @@ pub enum QueryMsg {
         start_after: Option<u64>,
         limit: Option<u32>,
     },
+    /// Summarize the status of all outstanding unbonding requests from a given user, and the total
+    /// amount they can withdraw right now. If unbond NFTs are enabled, these are the requests whose
+    /// NFTs the user owns. Response: `UnbondingSummaryResponse`
+    UnbondingSummary {
+        user: String,
+    },
+    /// The insurance reserve's current balance and funding rate. Response: `InsuranceResponse`
+    Insurance {},
+    /// The instant unbonding buffer's current balance, target and fee rate. Response: `BufferResponse`
+    Buffer {},
+    /// The keeper pool's current balance, and the keeper reward's parameters. Response: `KeeperRewardResponse`
+    KeeperReward {},
+    /// Enumerate snapshots of the exchange rate, recorded whenever rewards are reinvested, a batch is
+    /// submitted or batches are reconciled. Response: `Vec<ExchangeRateSnapshot>`
+    ExchangeRates {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// The time-weighted average exchange rate over the specified number of seconds until now.
+    /// Response: `TwapExchangeRateResponse`
+    TwapExchangeRate {
+        window_seconds: u64,
+    },
+    /// Enumerate staking rewards harvested in the past 30 days, aggregated by the hour.
+    /// Response: `Vec<HarvestRecord>`
+    Harvests {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// The annualized yield realized over the trailing 7 and 30 days, derived from the staking rewards
+    /// harvested during these periods. Response: `AprResponse`
+    Apr {},
+    /// Enumerate past draws from the insurance reserve. Response: `Vec<InsuranceDraw>`
+    InsuranceDraws {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Enumerate the routes along which staking rewards are swapped for Luna. Response: `Vec<SwapRoute>`
+    SwapRoutes {},
+    /// Simulate the outcome of bonding the given amount of `uluna`. The per-address bond cap is only
+    /// checked if `receiver` is provided. Response: `SimulateBondResponse`
+    SimulateBond {
+        amount: Uint128,
+        receiver: Option<String>,
+    },
+    /// Simulate the outcome of queuing the given amount of `usteak` for unbonding at the current
+    /// exchange rate. Response: `SimulateUnbondResponse`
+    SimulateUnbond {
+        usteak: Uint128,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -129,24 +333,205 @@ pub struct ConfigResponse {
     pub new_owner: Option<String>,
     /// Address of the Steak token
     pub steak_token: String,
+    /// Address of the NFT contract representing unbonding requests, if enabled
+    pub unbond_nft: Option<String>,
+    /// Denomination of the coin to be staked
+    pub denom: String,
     /// How often the unbonding queue is to be executed, in seconds
     pub epoch_period: u64,
     /// The staking module's unbonding time, in seconds
     pub unbond_period: u64,
-    /// Initial set of validators who will receive the delegations
-    pub validators: Vec<String>,
+    /// Validators who will receive the delegations, and their weights
+    pub validators: Vec<(String, u64)>,
+    /// Account to receive the protocol fee
+    pub fee_account: String,
+    /// Fraction of harvested staking rewards to be charged as protocol fee
+    pub fee_rate: Decimal,
+    /// Whether bonds are held until the pending batch is submitted, to be netted against unbonds
+    pub netting: bool,
+    /// Minimum amount of `uluna` to be bonded at a time
+    pub min_bond: Uint128,
+    /// Minimum amount of `usteak` to be queued for unbonding at a time
+    pub min_unbond: Uint128,
+    /// Maximum amount of `uluna` backing Steak, if capped
+    pub max_total_bonded: Option<Uint128>,
+    /// Maximum amount of `uluna` that the Steak held by a single address may be worth, if capped
+    pub max_bonded_per_address: Option<Uint128>,
+    /// Account who can pause, but not unpause, actions
+    pub guardian: Option<String>,
+    /// Actions that are currently paused
+    pub pause_flags: PauseFlags,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseFlags {
+    /// Whether bonding is paused
+    pub bond: bool,
+    /// Whether queuing unbonding requests is paused
+    pub queue_unbond: bool,
+    /// Whether instant unbonding is paused
+    #[serde(default)]
+    pub instant_unbond: bool,
+    /// Whether withdrawing unbonded Luna is paused
+    pub withdraw_unbonded: bool,
+    /// Whether harvesting staking rewards is paused
+    pub harvest: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct StateResponse {
     /// Total supply to the Steak token
     pub total_usteak: Uint128,
-    /// Total amount of uluna staked
+    /// Total amount of uluna staked, including bonds held to be netted against unbonds and the instant
+    /// unbonding buffer
     pub total_uluna: Uint128,
     /// The exchange rate between usteak and uluna, in terms of uluna per usteak
     pub exchange_rate: Decimal,
     /// Staking rewards currently held by the contract that are ready to be reinvested
     pub unlocked_coins: Vec<Coin>,
+    /// Amount of `uluna` that can still be bonded before reaching `max_total_bonded`; none if uncapped
+    pub bond_headroom: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InsuranceResponse {
+    /// Amount of `uluna` currently held in the insurance reserve
+    pub balance: Uint128,
+    /// Fraction of harvested staking rewards to be put into the insurance reserve
+    pub insurance_rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BufferResponse {
+    /// Amount of `uluna` currently held in the instant unbonding buffer
+    pub balance: Uint128,
+    /// The buffer's target, as a fraction of the total amount of `uluna` backing Steak
+    pub target_rate: Decimal,
+    /// Fraction of the unbonded amount to be charged as fee on instant unbonding
+    pub fee_rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeeperRewardResponse {
+    /// Amount of `uluna` currently held in the keeper pool
+    pub balance: Uint128,
+    /// Fraction of harvested staking rewards to be put into the keeper pool
+    pub rate: Decimal,
+    /// Amount of `uluna` paid from the keeper pool to whoever invokes a maintenance action
+    pub amount: Uint128,
+    /// Minimum number of seconds between two rewarded invocations of the same action
+    pub cooldown: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExchangeRateSnapshot {
+    /// Time when the snapshot was recorded, in seconds
+    pub time: u64,
+    /// Total amount of uluna backing Steak
+    pub total_uluna: Uint128,
+    /// Total supply of the Steak token
+    pub total_usteak: Uint128,
+    /// The exchange rate between usteak and uluna, in terms of uluna per usteak
+    pub exchange_rate: Decimal,
+    /// The exchange rate integrated over time since the first snapshot, in uluna per usteak times seconds
+    pub cumulative_exchange_rate: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapExchangeRateResponse {
+    /// The time-weighted average exchange rate, in terms of uluna per usteak
+    pub exchange_rate: Decimal,
+    /// Start of the period the average is taken over, in seconds. This is the time of the latest
+    /// snapshot at or before the start of the requested window, or the earliest snapshot if the
+    /// requested window goes further back than all snapshots
+    pub start_time: u64,
+    /// End of the period the average is taken over, i.e. the current time, in seconds
+    pub end_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HarvestRecord {
+    /// Start of the hour during which the rewards were harvested, in seconds
+    pub time: u64,
+    /// Amount of `uluna` harvested, including the fees and slices deducted from it
+    pub uluna_harvested: Uint128,
+    /// Amount of `uluna` that accrued to Steak holders, i.e. after fees and slices were deducted
+    pub uluna_bonded: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AprResponse {
+    /// Annualized yield realized by Steak holders over the trailing 7 days
+    pub apr_7d: Decimal,
+    /// Annualized yield realized by Steak holders over the trailing 30 days
+    pub apr_30d: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InsuranceDraw {
+    /// ID of this draw
+    pub id: u64,
+    /// Time when the draw was made, in seconds
+    pub time: u64,
+    /// IDs of the batches reconciled with the help of this draw
+    pub batch_ids: Vec<u64>,
+    /// Amount of `uluna` drawn from the insurance reserve
+    pub uluna_drawn: Uint128,
+    /// Part of the shortfall the reserve could not cover, which was deducted from the batches
+    pub uluna_deducted: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateBondResponse {
+    /// Amount of `usteak` to be minted
+    pub usteak_to_mint: Uint128,
+    /// The validator to receive the delegation. `None` if netting is enabled, in which case the Luna is
+    /// held until the pending batch is submitted, or if the entire amount goes to the instant unbonding
+    /// buffer
+    pub validator: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateUnbondResponse {
+    /// Estimated amount of `uluna` to be unbonded. The actual amount is determined by the exchange rate
+    /// when the batch is submitted
+    pub uluna_to_unbond: Uint128,
+    /// ID of the batch the unbonding request will be added to
+    pub batch_id: u64,
+    /// Estimated time when the batch will finish unbonding
+    pub est_unbond_end_time: u64,
+}
+
+/// Returned as the response data of `ExecuteMsg::WithdrawUnbonded`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WithdrawUnbondedResponse {
+    /// IDs of the batches whose requests were withdrawn, fully or only the part paid from Luna held
+    /// by the contract
+    pub batch_ids: Vec<u64>,
+    /// IDs of the batches whose requests remain outstanding after being examined, as they have not
+    /// finished unbonding
+    pub batch_ids_remaining: Vec<u64>,
+    /// Whether the limit was reached before all of the user's requests were examined. If so, the rest
+    /// are listed by `QueryMsg::UnbondingSummary`
+    pub has_more: bool,
+    /// Amount of `uluna` withdrawn
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapRoute {
+    /// Denomination of the staking rewards to be swapped
+    pub denom: String,
+    /// Address of the router contract that executes the swaps
+    pub router: String,
+    /// Swap operations to be executed in order, the last of which must return Luna
+    pub operations: Vec<SwapOperation>,
+    /// Maximum spread allowed for each operation
+    pub max_spread: Decimal,
+    /// Minimum amount of Luna to be received per unit of the reward denom, as set by the owner. As
+    /// harvest is permissionless, the router's simulation could be manipulated within the same
+    /// transaction, so it cannot be used to derive the minimum amount to receive
+    pub min_price: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -155,6 +540,10 @@ pub struct PendingBatch {
     pub id: u64,
     /// Total amount of `usteak` to be burned in this batch
     pub usteak_to_burn: Uint128,
+    /// Total amount of `uluna` bonded during this epoch with netting enabled, held to be netted against
+    /// `usteak_to_burn` when this batch is submitted
+    #[serde(default)]
+    pub uluna_to_bond: Uint128,
     /// Estimated time when this batch will be submitted for unbonding
     pub est_unbond_start_time: u64,
 }
@@ -167,10 +556,23 @@ pub struct Batch {
     pub reconciled: bool,
     /// Total amount of shares remaining this batch. Each `usteak` burned = 1 share
     pub total_shares: Uint128,
-    /// Amount of `uluna` in this batch that have not been claimed
+    /// Amount of `uluna` undelegated in this batch that have not been claimed
     pub uluna_unclaimed: Uint128,
     /// Estimated time when this batch will finish unbonding
     pub est_unbond_end_time: u64,
+    /// Amount of `uluna` in this batch that was not undelegated, but paid from Luna held by the
+    /// contract, i.e. bonds netted against the batch and the batch's share of the instant unbonding
+    /// buffer, and have not been claimed. These can be claimed right away, without waiting for the
+    /// batch to finish unbonding. Zero for batches submitted before v2.1.0
+    #[serde(default)]
+    pub uluna_held: Uint128,
+    /// Shares whose part of `uluna_held` has not been claimed
+    #[serde(default)]
+    pub held_shares: Uint128,
+    /// Validators this batch was undelegated from, and the amounts of `uluna` undelegated from each.
+    /// Empty for batches submitted before v2.1.0
+    #[serde(default)]
+    pub undelegations: Vec<(String, Uint128)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -181,6 +583,35 @@ pub struct UnbondRequest {
     pub user: Addr,
     /// The user's share in the batch
     pub shares: Uint128,
+    /// Sequence number of this request among the user's requests in the batch. Zero unless the user
+    /// queued more Steak after transferring the NFT of their previous request
+    #[serde(default)]
+    pub seq: u64,
+    /// Whether the user has claimed their part of the Luna held by the contract for the batch
+    #[serde(default)]
+    pub held_claimed: bool,
+}
+
+impl UnbondRequest {
+    /// Key of this request within its batch: the user's address, suffixed with the sequence number
+    /// if it is not the user's first request in the batch
+    pub fn key(&self) -> String {
+        Self::key_of(&self.user, self.seq)
+    }
+
+    /// Key of the user's request with the given sequence number, see `key`
+    pub fn key_of(user: &Addr, seq: u64) -> String {
+        if seq == 0 {
+            user.to_string()
+        } else {
+            format!("{}/{}", user, seq)
+        }
+    }
+
+    /// ID of the NFT representing this request, if unbond NFTs are enabled
+    pub fn token_id(&self) -> String {
+        format!("{}/{}", self.id, self.key())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -189,6 +620,8 @@ pub struct UnbondRequestsByBatchResponseItem {
     pub user: String,
     /// The user's share in the batch
     pub shares: Uint128,
+    /// Sequence number of the request among the user's requests in the batch
+    pub seq: u64,
 }
 
 impl From<UnbondRequest> for UnbondRequestsByBatchResponseItem {
@@ -196,6 +629,7 @@ impl From<UnbondRequest> for UnbondRequestsByBatchResponseItem {
         Self {
             user: s.user.into(),
             shares: s.shares,
+            seq: s.seq,
         }
     }
 }
@@ -206,6 +640,8 @@ pub struct UnbondRequestsByUserResponseItem {
     pub id: u64,
     /// The user's share in the batch
     pub shares: Uint128,
+    /// Sequence number of the request among the user's requests in the batch
+    pub seq: u64,
 }
 
 impl From<UnbondRequest> for UnbondRequestsByUserResponseItem {
@@ -213,8 +649,63 @@ impl From<UnbondRequest> for UnbondRequestsByUserResponseItem {
         Self {
             id: s.id,
             shares: s.shares,
+            seq: s.seq,
         }
     }
 }
 
-pub type MigrateMsg = Empty;
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UnbondStatus {
+    /// The batch has not yet been submitted for unbonding
+    Pending,
+    /// The batch has been submitted, and is still in the unbonding period
+    Unbonding,
+    /// The batch has finished unbonding, but needs to be reconciled before it can be withdrawn
+    Unreconciled,
+    /// The batch can be withdrawn
+    Claimable,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingSummaryItem {
+    /// ID of the batch
+    pub id: u64,
+    /// The user's share in the batch
+    pub shares: Uint128,
+    /// Status of the batch
+    pub status: UnbondStatus,
+    /// Estimated amount of `uluna` the user has yet to receive, including their part of the Luna held
+    /// by the contract for the batch if not yet claimed. For pending batches, this is based on the
+    /// current exchange rate
+    pub est_uluna: Uint128,
+    /// Estimated time when the batch will finish unbonding
+    pub est_unbond_end_time: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingSummaryResponse {
+    /// The user's outstanding unbonding requests
+    pub requests: Vec<UnbondingSummaryItem>,
+    /// Total amount of `uluna` the user can withdraw right now
+    pub total_withdrawable: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Storage upgrades to be applied, in the given order
+    pub steps: Vec<MigrateStep>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrateStep {
+    /// Initialize the bond denom and the protocol fee, which did not exist in v2.0.0
+    InitDenomAndFee {
+        denom: String,
+        fee_account: String,
+        fee_rate: Decimal,
+    },
+    /// Assign each whitelisted validator a weight of 1, as v2.0.0 stored validators without weights
+    InitValidatorWeights {},
+}
@@ -0,0 +1,96 @@
+//! Subset of the Astroport router interface used by Steak Hub to swap staking rewards
+
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    /// A CW20 token
+    Token {
+        contract_addr: Addr,
+    },
+    /// A native coin
+    NativeToken {
+        denom: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapOperation {
+    /// Swap using the chain's native market module
+    NativeSwap {
+        offer_denom: String,
+        ask_denom: String,
+    },
+    /// Swap using an Astroport pair
+    AstroSwap {
+        offer_asset_info: AssetInfo,
+        ask_asset_info: AssetInfo,
+    },
+}
+
+impl SwapOperation {
+    /// The asset to be offered in this operation
+    pub fn offer_asset_info(&self) -> AssetInfo {
+        match self {
+            SwapOperation::NativeSwap {
+                offer_denom,
+                ..
+            } => AssetInfo::NativeToken {
+                denom: offer_denom.clone(),
+            },
+            SwapOperation::AstroSwap {
+                offer_asset_info,
+                ..
+            } => offer_asset_info.clone(),
+        }
+    }
+
+    /// The asset to be received from this operation
+    pub fn ask_asset_info(&self) -> AssetInfo {
+        match self {
+            SwapOperation::NativeSwap {
+                ask_denom,
+                ..
+            } => AssetInfo::NativeToken {
+                denom: ask_denom.clone(),
+            },
+            SwapOperation::AstroSwap {
+                ask_asset_info,
+                ..
+            } => ask_asset_info.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Perform the swap operations in the given order; fails if the final amount received is less
+    /// than `minimum_receive`, or if the spread of any single operation exceeds `max_spread`
+    ExecuteSwapOperations {
+        operations: Vec<SwapOperation>,
+        minimum_receive: Option<Uint128>,
+        to: Option<String>,
+        max_spread: Option<Decimal>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Simulate the amount to be received from the swap operations. Response: `SimulateSwapOperationsResponse`
+    SimulateSwapOperations {
+        offer_amount: Uint128,
+        operations: Vec<SwapOperation>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateSwapOperationsResponse {
+    /// Amount of the final asset to be received
+    pub amount: Uint128,
+}
@@ -0,0 +1,148 @@
+use cosmwasm_std::{
+    entry_point, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdResult, Storage,
+};
+use cw721_base::{ContractError, Cw721Contract, ExecuteMsg, Extension, InstantiateMsg, QueryMsg};
+
+pub type UnbondNftContract<'a> = Cw721Contract<'a, Extension, Empty>;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> StdResult<Response> {
+    UnbondNftContract::default().instantiate(deps, env, info, msg)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg<Extension>,
+) -> Result<Response, ContractError> {
+    // For `burn`, we assert that the caller is the minter, and allow it to burn any NFT regardless
+    // of who owns it
+    match msg {
+        ExecuteMsg::Burn {
+            token_id,
+        } => burn(deps, info.sender, token_id),
+        _ => UnbondNftContract::default().execute(deps, env, info, msg),
+    }
+}
+
+fn burn(deps: DepsMut, sender: Addr, token_id: String) -> Result<Response, ContractError> {
+    let contract = UnbondNftContract::default();
+
+    assert_minter(deps.storage, &sender)?;
+
+    contract.tokens.load(deps.storage, &token_id)?;
+    contract.tokens.remove(deps.storage, &token_id)?;
+    contract.decrement_tokens(deps.storage)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "burn")
+        .add_attribute("sender", sender)
+        .add_attribute("token_id", token_id))
+}
+
+fn assert_minter(storage: &dyn Storage, sender: &Addr) -> Result<(), ContractError> {
+    let minter = UnbondNftContract::default().minter.load(storage)?;
+
+    if *sender != minter {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    UnbondNftContract::default().query(deps, env, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::OwnedDeps;
+    use cw721_base::MintMsg;
+
+    use super::*;
+
+    fn setup_test() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+
+        instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("deployer", &[]),
+            InstantiateMsg {
+                name: "Steak Unbond NFT".to_string(),
+                symbol: "usteak".to_string(),
+                minter: "steak_hub".to_string(),
+            },
+        )
+        .unwrap();
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("steak_hub", &[]),
+            ExecuteMsg::Mint(MintMsg {
+                token_id: "1/alice".to_string(),
+                owner: "alice".to_string(),
+                token_uri: None,
+                extension: None,
+            }),
+        )
+        .unwrap();
+
+        deps
+    }
+
+    #[test]
+    fn burning_by_minter() {
+        let mut deps = setup_test();
+
+        // Alice is not allowed to burn her NFT
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Burn {
+                token_id: "1/alice".to_string(),
+            },
+        );
+        assert_eq!(res, Err(ContractError::Unauthorized {}));
+
+        // Steak Hub can burn Alice's NFT
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("steak_hub", &[]),
+            ExecuteMsg::Burn {
+                token_id: "1/alice".to_string(),
+            },
+        );
+        assert!(res.is_ok());
+
+        // The NFT should have been removed
+        let contract = UnbondNftContract::default();
+        assert!(!contract.tokens.has(deps.as_ref().storage, "1/alice"));
+        assert_eq!(contract.token_count(deps.as_ref().storage).unwrap(), 0);
+
+        // Burning a non-existent NFT fails
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("steak_hub", &[]),
+            ExecuteMsg::Burn {
+                token_id: "1/alice".to_string(),
+            },
+        );
+        assert!(res.is_err());
+    }
+}
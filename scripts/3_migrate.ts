@@ -31,8 +31,7 @@ const argv = yargs(process.argv)
     },
     msg: {
       type: "string",
-      demandOption: false,
-      default: "{}",
+      demandOption: true,
     },
     "code-id": {
       type: "number",